@@ -0,0 +1,127 @@
+use crate::{
+    absorb_auth_snapshot, codex_auth_path, events, find_account_for_auth, lock_data,
+    read_codex_auth, save_app_data, upsert_account, vault, SharedState,
+};
+use serde::Serialize;
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+pub(crate) const CODEX_AUTH_CHANGED_EVENT: &str = "codex-auth-changed";
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum CodexAuthChangeKind {
+    LoggedIn,
+    LoggedOut,
+    TokensRefreshed,
+    ActiveChanged,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CodexAuthChanged {
+    kind: CodexAuthChangeKind,
+    active_account_id: Option<String>,
+}
+
+type FileFingerprint = Option<(SystemTime, u64)>;
+
+fn auth_fingerprint() -> FileFingerprint {
+    let path = codex_auth_path().ok()?;
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+/// Brings `active_account_id` and the matching account in line with whatever
+/// auth.json currently holds. Returns `None` when nothing had to change.
+fn reconcile_codex_auth(state: &Arc<SharedState>) -> Result<Option<CodexAuthChanged>, String> {
+    let auth = read_codex_auth()?;
+    let mut data = lock_data(state)?;
+    let previous_active = data.active_account_id.clone();
+
+    let Some(auth) = auth else {
+        if data.active_account_id.is_none() {
+            return Ok(None);
+        }
+        data.active_account_id = None;
+        save_app_data(&mut data)?;
+        events::active_account_changed(state, previous_active, None);
+        return Ok(Some(CodexAuthChanged {
+            kind: CodexAuthChangeKind::LoggedOut,
+            active_account_id: None,
+        }));
+    };
+
+    let refreshed = absorb_auth_snapshot(&mut data, &auth).is_some();
+    let matched = find_account_for_auth(&mut data.accounts, &auth).map(|a| a.id.clone());
+
+    let (account_id, kind) = match matched {
+        Some(id) if refreshed => (id, CodexAuthChangeKind::TokensRefreshed),
+        Some(id) => (id, CodexAuthChangeKind::ActiveChanged),
+        None => {
+            let account = upsert_account(
+                &mut data,
+                auth.tokens.clone(),
+                auth.email.clone(),
+                auth.account_id.clone(),
+            );
+            if let Some(stored) = data.accounts.iter_mut().find(|a| a.id == account.id) {
+                stored.last_refresh_at = auth.last_refresh.or(stored.last_refresh_at);
+            }
+            (account.id, CodexAuthChangeKind::LoggedIn)
+        }
+    };
+
+    let already_active = data.active_account_id.as_deref() == Some(account_id.as_str());
+    if already_active && matches!(kind, CodexAuthChangeKind::ActiveChanged) {
+        return Ok(None);
+    }
+
+    data.active_account_id = Some(account_id.clone());
+    save_app_data(&mut data)?;
+    events::active_account_changed(state, previous_active, Some(account_id.clone()));
+
+    Ok(Some(CodexAuthChanged {
+        kind,
+        active_account_id: Some(account_id),
+    }))
+}
+
+/// Polls auth.json for changes made by the Codex CLI or IDE extensions and
+/// reconciles the store, notifying the UI through `codex-auth-changed`.
+pub(crate) fn start_auth_watcher(state: Arc<SharedState>) {
+    std::thread::spawn(move || {
+        let mut last_seen: Option<FileFingerprint> = None;
+
+        loop {
+            let current = auth_fingerprint();
+            // While the vault is locked the store only holds placeholders, so
+            // a change stays unseen and is reconciled on the first poll after
+            // unlock instead of being lost.
+            if last_seen != Some(current) && vault::ensure_unlocked().is_ok() {
+                match reconcile_codex_auth(&state) {
+                    Ok(Some(change)) => {
+                        last_seen = Some(current);
+                        log::info!(
+                            "auth.json changed externally ({:?}), active account: {:?}",
+                            change.kind,
+                            change.active_account_id
+                        );
+                        events::emit(&state, CODEX_AUTH_CHANGED_EVENT, change);
+                    }
+                    Ok(None) => last_seen = Some(current),
+                    // Locked while reconciling; try again after unlock.
+                    Err(_) if vault::ensure_unlocked().is_err() => {}
+                    Err(err) => {
+                        last_seen = Some(current);
+                        log::warn!("Failed to reconcile auth.json: {}", err);
+                    }
+                }
+            }
+
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    });
+}
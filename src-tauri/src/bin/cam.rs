@@ -0,0 +1,3 @@
+fn main() {
+    codex_account_manager_lib::cli::main();
+}
@@ -0,0 +1,476 @@
+//! Headless front end used by the `cam` binary. It drives the same store and
+//! helpers as the Tauri commands, so scripts and the GUI see identical state.
+
+use crate::health::{AccountHealth, AccountStatus};
+use crate::view::{AccountView, AppDataView, ProxyView};
+use crate::{
+    begin_oauth_flow, complete_flow_with_callback, lock_data, lock_flows, parse_app_data,
+    refresh_quotas_for_all, remove_stored_account, resolve_account, rotation, run_proxy_test,
+    select_active_proxy, storage, store_proxy, switch_account, vault, AppData, OauthFlowStatus,
+    QuotaWindow, SharedState,
+};
+use chrono::{Local, TimeZone};
+use serde::Serialize;
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const PASSPHRASE_ENV: &str = "CAM_PASSPHRASE";
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(600);
+const LOGIN_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+const USAGE: &str = "Usage: cam [--json] <command>
+
+Commands:
+  list                          Show stored accounts
+  switch <email|id> [--ide <name>]
+                                Make an account active in auth.json
+  quota [--refresh]             Show quota, optionally fetching it first
+  login                         Add an account through the browser OAuth flow
+  remove <email|id>             Delete a stored account
+  proxy list                    Show stored proxies
+  proxy add <login:pass@host:port>
+                                Store a new proxy
+  proxy test <id|host:port>     Measure proxy latency
+  proxy use <id|host:port|none> Select the proxy used for outbound requests
+
+Options:
+  --json                        Print machine-readable output
+
+When state.json is encrypted, the passphrase is read from CAM_PASSPHRASE or
+prompted on stdin.";
+
+struct Output {
+    json: bool,
+}
+
+impl Output {
+    fn emit<T: Serialize>(&self, value: &T, human: impl FnOnce()) -> Result<(), String> {
+        if self.json {
+            let text = serde_json::to_string_pretty(value)
+                .map_err(|e| format!("Failed to serialize output: {e}"))?;
+            println!("{text}");
+        } else {
+            human();
+        }
+        Ok(())
+    }
+}
+
+/// Entry point of the `cam` binary. Exits with status 1 on failure and 2 on
+/// a usage error.
+pub fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let json = args.iter().any(|arg| arg == "--json");
+    let args: Vec<String> = args.into_iter().filter(|arg| arg != "--json").collect();
+
+    if args.is_empty() || matches!(args[0].as_str(), "help" | "-h" | "--help") {
+        println!("{USAGE}");
+        std::process::exit(if args.is_empty() { 2 } else { 0 });
+    }
+
+    if let Err(err) = run(&args, &Output { json }) {
+        if json {
+            println!("{}", serde_json::json!({ "error": err }));
+        } else {
+            eprintln!("error: {err}");
+        }
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &[String], out: &Output) -> Result<(), String> {
+    let state = Arc::new(SharedState::new(open_store()?));
+    let rest = &args[1..];
+
+    match args[0].as_str() {
+        "list" => cmd_list(&state, out),
+        "switch" => cmd_switch(&state, rest, out),
+        "quota" => cmd_quota(&state, rest, out),
+        "login" => cmd_login(&state, out),
+        "remove" => cmd_remove(&state, rest, out),
+        "proxy" => cmd_proxy(&state, rest, out),
+        other => Err(format!(
+            "Unknown command '{other}'. Run `cam --help` for usage."
+        )),
+    }
+}
+
+/// Loads state.json, unlocking the vault first when the file is sealed.
+fn open_store() -> Result<AppData, String> {
+    vault::detect_sealed_state();
+    if !vault::status()?.locked {
+        return Ok(storage::load_with_recovery()?);
+    }
+
+    let passphrase = match std::env::var(PASSPHRASE_ENV) {
+        Ok(value) if !value.is_empty() => value,
+        _ => {
+            eprint!("Vault passphrase: ");
+            std::io::stderr().flush().ok();
+            let mut line = String::new();
+            std::io::stdin()
+                .lock()
+                .read_line(&mut line)
+                .map_err(|e| format!("Failed to read passphrase: {e}"))?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+
+    let text = vault::unlock(&passphrase)?;
+    let data = parse_app_data(&text)?;
+    storage::mark_synced(&data);
+    Ok(data)
+}
+
+fn snapshot(state: &Arc<SharedState>) -> Result<AppDataView, String> {
+    let data = lock_data(state)?;
+    Ok(AppDataView::from(&*data))
+}
+
+fn expect_arg<'a>(args: &'a [String], what: &str) -> Result<&'a str, String> {
+    args.first()
+        .map(String::as_str)
+        .ok_or_else(|| format!("Missing {what}. Run `cam --help` for usage."))
+}
+
+fn resolve_proxy(data: &AppData, needle: &str) -> Result<String, String> {
+    data.proxies
+        .iter()
+        .find(|p| p.id == needle || format!("{}:{}", p.host, p.port) == needle)
+        .map(|p| p.id.clone())
+        .ok_or_else(|| format!("No proxy matches '{needle}'"))
+}
+
+fn account_label(account: &AccountView) -> &str {
+    account.email.as_deref().unwrap_or(&account.id)
+}
+
+fn format_ts(ts: i64) -> String {
+    Local
+        .timestamp_opt(ts, 0)
+        .single()
+        .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| ts.to_string())
+}
+
+fn format_window(window: &QuotaWindow) -> String {
+    let Some(used) = window.used_percent else {
+        return "-".to_string();
+    };
+    let left = (100.0 - used).clamp(0.0, 100.0);
+    match window.reset_at {
+        Some(reset_at) => format!("{left:.0}% left, resets {}", format_ts(reset_at)),
+        None => format!("{left:.0}% left"),
+    }
+}
+
+/// Describes anything but a healthy account, e.g. "rate limited (HTTP 429)
+/// since 2024-05-01 10:00, retry 2024-05-01 10:05".
+fn format_health(health: &AccountHealth) -> Option<String> {
+    let label = match health.status {
+        AccountStatus::Healthy => return None,
+        AccountStatus::Stale => "stale",
+        AccountStatus::TokenExpired => "token expired",
+        AccountStatus::NeedsReauth => "needs sign-in",
+        AccountStatus::RateLimited => "rate limited",
+        AccountStatus::Deactivated => "deactivated",
+        AccountStatus::NetworkError => "network error",
+    };
+    let mut text = label.to_string();
+    if let Some(status) = health.http_status {
+        text.push_str(&format!(" (HTTP {status})"));
+    }
+    if let Some(since) = health.since {
+        text.push_str(&format!(" since {}", format_ts(since)));
+    }
+    if let Some(retry_at) = health.retry_at {
+        text.push_str(&format!(", retry {}", format_ts(retry_at)));
+    }
+    Some(text)
+}
+
+fn print_accounts(view: &AppDataView) {
+    if view.accounts.is_empty() {
+        println!("No accounts stored. Run `cam login` to add one.");
+        return;
+    }
+
+    for account in &view.accounts {
+        let marker = if view.active_account_id.as_deref() == Some(account.id.as_str()) {
+            "*"
+        } else {
+            " "
+        };
+        let plan = account
+            .quota
+            .as_ref()
+            .and_then(|quota| quota.plan_type.as_deref())
+            .unwrap_or("-");
+        println!(
+            "{marker} {}  [{plan}]  {}",
+            account_label(account),
+            account.id
+        );
+    }
+}
+
+fn print_quota(view: &AppDataView) {
+    if view.accounts.is_empty() {
+        println!("No accounts stored.");
+        return;
+    }
+
+    for account in &view.accounts {
+        let marker = if view.active_account_id.as_deref() == Some(account.id.as_str()) {
+            "*"
+        } else {
+            " "
+        };
+        println!("{marker} {}", account_label(account));
+        match &account.quota {
+            Some(quota) => {
+                println!("    5-hour: {}", format_window(&quota.primary));
+                println!("    weekly: {}", format_window(&quota.secondary));
+            }
+            None => println!("    quota not fetched yet"),
+        }
+        if let Some(status) = format_health(&account.health) {
+            println!("    status: {status}");
+        }
+        if let Some(err) = &account.last_error {
+            println!("    error: {err}");
+        }
+    }
+}
+
+fn print_proxies(view: &AppDataView) {
+    if view.proxies.is_empty() {
+        println!("No proxies stored.");
+        return;
+    }
+
+    for proxy in &view.proxies {
+        let marker = if view.active_proxy_id.as_deref() == Some(proxy.id.as_str()) {
+            "*"
+        } else {
+            " "
+        };
+        let status = match (proxy.last_status.as_deref(), proxy.last_latency_ms) {
+            (Some("ok"), Some(latency)) => format!("{latency} ms"),
+            (Some(status), _) => status.to_string(),
+            (None, _) => "untested".to_string(),
+        };
+        println!(
+            "{marker} {}@{}:{}  ({status})  {}",
+            proxy.login, proxy.host, proxy.port, proxy.id
+        );
+    }
+}
+
+fn cmd_list(state: &Arc<SharedState>, out: &Output) -> Result<(), String> {
+    let view = snapshot(state)?;
+    out.emit(&view.accounts, || print_accounts(&view))
+}
+
+fn cmd_switch(state: &Arc<SharedState>, args: &[String], out: &Output) -> Result<(), String> {
+    let needle = expect_arg(args, "account email or id")?;
+    let ide = match args.iter().position(|arg| arg == "--ide") {
+        Some(index) => Some(
+            args.get(index + 1)
+                .cloned()
+                .ok_or_else(|| "--ide needs a value".to_string())?,
+        ),
+        None => None,
+    };
+
+    let account_id = {
+        let data = lock_data(state)?;
+        resolve_account(&data, needle)?
+    };
+
+    let response = switch_account(state, account_id.clone(), ide)?;
+    out.emit(&response, || {
+        let label = response
+            .state
+            .accounts
+            .iter()
+            .find(|a| a.id == account_id)
+            .map_or(account_id.as_str(), account_label);
+        println!("Switched to {label}");
+        if response.reloaded {
+            if let Some(ide) = &response.ide {
+                println!("Reloaded {ide}");
+            }
+        }
+        if let Some(warning) = &response.warning {
+            println!("{warning}");
+        }
+    })
+}
+
+fn cmd_quota(state: &Arc<SharedState>, args: &[String], out: &Output) -> Result<(), String> {
+    let refresh = args.iter().any(|arg| arg == "--refresh");
+
+    let mut rotated = None;
+    if refresh {
+        let report = refresh_quotas_for_all(state)?;
+        if !report.pending.is_empty() {
+            eprintln!(
+                "{} account(s) did not finish refreshing in time; their quota may be stale",
+                report.pending.len()
+            );
+        }
+        rotated = rotation::maybe_rotate(state)?;
+    }
+
+    let view = snapshot(state)?;
+    out.emit(&view.accounts, || {
+        print_quota(&view);
+        if let Some(event) = &rotated {
+            println!(
+                "Rotated active account to {} ({})",
+                event.to_account_id, event.reason
+            );
+        }
+    })
+}
+
+fn cmd_remove(state: &Arc<SharedState>, args: &[String], out: &Output) -> Result<(), String> {
+    let needle = expect_arg(args, "account email or id")?;
+    let account_id = {
+        let data = lock_data(state)?;
+        resolve_account(&data, needle)?
+    };
+
+    let view = remove_stored_account(state, &account_id)?;
+    out.emit(&view.accounts, || println!("Removed {needle}"))
+}
+
+/// Prints the authorize URL and waits until either the local callback
+/// listener or a callback URL pasted on stdin finishes the flow.
+fn cmd_login(state: &Arc<SharedState>, out: &Output) -> Result<(), String> {
+    let started = begin_oauth_flow(state)?;
+
+    eprintln!(
+        "Open this URL in a browser and sign in:\n\n{}\n",
+        started.authorization_url
+    );
+    eprintln!(
+        "Waiting for the redirect to {}. If the browser cannot reach it, paste the final URL here.",
+        started.redirect_uri
+    );
+
+    {
+        let state = Arc::clone(state);
+        let flow_id = started.flow_id.clone();
+        std::thread::spawn(move || {
+            let stdin = std::io::stdin();
+            for line in stdin.lock().lines() {
+                let Ok(line) = line else {
+                    return;
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match complete_flow_with_callback(&state, &flow_id, &line) {
+                    Ok(()) => return,
+                    Err(err) => eprintln!("{err}"),
+                }
+            }
+        });
+    }
+
+    let deadline = Instant::now() + LOGIN_TIMEOUT;
+    let account_id = loop {
+        let status = {
+            let flows = lock_flows(state)?;
+            let flow = flows
+                .get(&started.flow_id)
+                .ok_or_else(|| "OAuth flow not found".to_string())?;
+            (flow.status.clone(), flow.result_account_id.clone())
+        };
+
+        match status {
+            (OauthFlowStatus::Completed, Some(account_id)) => break account_id,
+            (OauthFlowStatus::Error(err), _) => return Err(err),
+            (OauthFlowStatus::Expired, _) => {
+                return Err("The login expired before the callback arrived".to_string())
+            }
+            (OauthFlowStatus::Cancelled, _) => return Err("The login was cancelled".to_string()),
+            _ if Instant::now() >= deadline => {
+                return Err("Timed out waiting for the OAuth callback".to_string())
+            }
+            _ => std::thread::sleep(LOGIN_POLL_INTERVAL),
+        }
+    };
+
+    let data = lock_data(state)?;
+    let account = data
+        .accounts
+        .iter()
+        .find(|a| a.id == account_id)
+        .map(AccountView::from)
+        .ok_or_else(|| "Account disappeared after login".to_string())?;
+    out.emit(&account, || {
+        println!("Added {}", account_label(&account));
+        if let Some(err) = &account.last_error {
+            println!("Quota check failed: {err}");
+        }
+    })
+}
+
+fn cmd_proxy(state: &Arc<SharedState>, args: &[String], out: &Output) -> Result<(), String> {
+    let action = expect_arg(args, "proxy action (list, add, test, use)")?;
+    let rest = &args[1..];
+
+    match action {
+        "list" => {
+            let view = snapshot(state)?;
+            out.emit(&view.proxies, || print_proxies(&view))
+        }
+        "add" => {
+            let value = expect_arg(rest, "proxy in login:password@host:port form")?;
+            let view = store_proxy(state, None, value)?;
+            let added: Option<&ProxyView> = view.proxies.last();
+            out.emit(&added, || {
+                if let Some(proxy) = added {
+                    println!("Added proxy {}:{} ({})", proxy.host, proxy.port, proxy.id);
+                }
+            })
+        }
+        "test" => {
+            let needle = expect_arg(rest, "proxy id or host:port")?;
+            let proxy_id = {
+                let data = lock_data(state)?;
+                resolve_proxy(&data, needle)?
+            };
+            let result = run_proxy_test(state, proxy_id)?;
+            out.emit(&result, || match (&result.latency_ms, &result.error) {
+                (Some(latency), _) => println!("Reachable in {latency} ms"),
+                (None, Some(err)) => println!("Unreachable: {err}"),
+                (None, None) => println!("Unreachable"),
+            })?;
+            if result.reachable {
+                Ok(())
+            } else {
+                Err("Proxy test failed".to_string())
+            }
+        }
+        "use" => {
+            let needle = expect_arg(rest, "proxy id, host:port or 'none'")?;
+            let proxy_id = if needle == "none" {
+                None
+            } else {
+                let data = lock_data(state)?;
+                Some(resolve_proxy(&data, needle)?)
+            };
+            let view = select_active_proxy(state, proxy_id)?;
+            out.emit(&view.proxies, || match &view.active_proxy_id {
+                Some(_) => println!("Using proxy {needle}"),
+                None => println!("Proxy disabled"),
+            })
+        }
+        other => Err(format!("Unknown proxy action '{other}'")),
+    }
+}
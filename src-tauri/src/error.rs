@@ -0,0 +1,202 @@
+//! The error type returned by every command. It reaches the webview as
+//! `{ code, message, details }`; `code` is stable so the UI can branch on it,
+//! `message` is for display and `details` carries variant fields such as the
+//! HTTP status. Helpers that still return `String` are mapped to a variant at
+//! the call site; there is deliberately no blanket conversion, so a failure
+//! never reaches the UI as `internal` by accident.
+
+use crate::http::{HttpError, HttpErrorKind};
+use crate::{migrate, vault};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use serde_json::{json, Value};
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub(crate) enum AppError {
+    /// Reading or writing files under the data directory or `~/.codex`.
+    Storage(String),
+    /// A stored file exists but could not be decoded.
+    Parse(String),
+    /// The state file was written by a newer build. It is left untouched.
+    UnsupportedSchema {
+        version: u32,
+    },
+    VaultLocked,
+    Vault(String),
+    /// The request never got an HTTP answer, or got one outside the cases below.
+    Network {
+        message: String,
+        status: Option<u16>,
+    },
+    Proxy(String),
+    InvalidInput(String),
+    OAuthFlowNotFound,
+    OAuthCallback(String),
+    /// The callback listener could not bind any configured port.
+    OAuthCallbackServer(String),
+    /// The relay could not bind its port.
+    Relay(String),
+    OAuthStateMismatch,
+    OAuthExchange {
+        message: String,
+        status: Option<u16>,
+    },
+    TokenRefresh {
+        message: String,
+        status: Option<u16>,
+    },
+    QuotaHttp {
+        message: String,
+        status: u16,
+    },
+    AccountNotFound {
+        account_id: String,
+    },
+    ProxyNotFound {
+        proxy_id: String,
+    },
+    /// Failed reloads, including a selected target that is no longer
+    /// configured. Unknown or invalid targets passed in are `InvalidInput`.
+    IdeReload(String),
+    Internal(String),
+}
+
+impl AppError {
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            Self::Storage(_) => "storage",
+            Self::Parse(_) => "parse",
+            Self::UnsupportedSchema { .. } => "unsupported_schema",
+            Self::VaultLocked => "vault_locked",
+            Self::Vault(_) => "vault",
+            Self::Network { .. } => "network",
+            Self::Proxy(_) => "proxy",
+            Self::InvalidInput(_) => "invalid_input",
+            Self::OAuthFlowNotFound => "oauth_flow_not_found",
+            Self::OAuthCallback(_) => "oauth_callback",
+            Self::OAuthCallbackServer(_) => "oauth_callback_server",
+            Self::Relay(_) => "relay",
+            Self::OAuthStateMismatch => "oauth_state_mismatch",
+            Self::OAuthExchange { .. } => "oauth_exchange",
+            Self::TokenRefresh { .. } => "token_refresh",
+            Self::QuotaHttp { .. } => "quota_http",
+            Self::AccountNotFound { .. } => "account_not_found",
+            Self::ProxyNotFound { .. } => "proxy_not_found",
+            Self::IdeReload(_) => "ide_reload",
+            Self::Internal(_) => "internal",
+        }
+    }
+
+    fn details(&self) -> Value {
+        match self {
+            Self::Network { status, .. }
+            | Self::OAuthExchange { status, .. }
+            | Self::TokenRefresh { status, .. } => json!({ "status": status }),
+            Self::QuotaHttp { status, .. } => json!({ "status": status }),
+            Self::AccountNotFound { account_id } => json!({ "accountId": account_id }),
+            Self::ProxyNotFound { proxy_id } => json!({ "proxyId": proxy_id }),
+            Self::UnsupportedSchema { version } => json!({
+                "version": version,
+                "supported": migrate::CURRENT_SCHEMA_VERSION,
+            }),
+            _ => Value::Null,
+        }
+    }
+
+    pub(crate) fn account_not_found(account_id: &str) -> Self {
+        Self::AccountNotFound {
+            account_id: account_id.to_string(),
+        }
+    }
+
+    /// Errors from `vault`, which reports a locked vault by message.
+    pub(crate) fn vault(message: String) -> Self {
+        if message == vault::VAULT_LOCKED_ERROR {
+            Self::VaultLocked
+        } else {
+            Self::Vault(message)
+        }
+    }
+
+    /// A failed quota request. HTTP answers keep their status; credentials
+    /// rejected before any request was sent count as a token problem.
+    pub(crate) fn quota(err: HttpError) -> Self {
+        match (err.status, err.kind) {
+            (Some(status), _) => Self::QuotaHttp {
+                message: err.message,
+                status,
+            },
+            (None, HttpErrorKind::Auth) => Self::TokenRefresh {
+                message: err.message,
+                status: None,
+            },
+            (None, _) => Self::Network {
+                message: err.message,
+                status: None,
+            },
+        }
+    }
+
+    pub(crate) fn oauth_exchange(err: HttpError) -> Self {
+        Self::OAuthExchange {
+            message: err.message,
+            status: err.status,
+        }
+    }
+
+    pub(crate) fn token_refresh(err: HttpError) -> Self {
+        Self::TokenRefresh {
+            message: err.message,
+            status: err.status,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Storage(message)
+            | Self::Parse(message)
+            | Self::Vault(message)
+            | Self::Proxy(message)
+            | Self::InvalidInput(message)
+            | Self::OAuthCallback(message)
+            | Self::OAuthCallbackServer(message)
+            | Self::Relay(message)
+            | Self::IdeReload(message)
+            | Self::Internal(message)
+            | Self::Network { message, .. }
+            | Self::OAuthExchange { message, .. }
+            | Self::TokenRefresh { message, .. }
+            | Self::QuotaHttp { message, .. } => f.write_str(message),
+            Self::VaultLocked => f.write_str(vault::VAULT_LOCKED_ERROR),
+            Self::OAuthFlowNotFound => f.write_str("OAuth flow not found"),
+            Self::OAuthStateMismatch => {
+                f.write_str("State mismatch. Ensure callback belongs to the current login session.")
+            }
+            Self::AccountNotFound { .. } => f.write_str("Account not found"),
+            Self::ProxyNotFound { .. } => f.write_str("Proxy not found"),
+            Self::UnsupportedSchema { version } => write!(
+                f,
+                "State file uses schema version {version}, but this build only supports up to {}. Update the app to open it.",
+                migrate::CURRENT_SCHEMA_VERSION
+            ),
+        }
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut out = serializer.serialize_struct("AppError", 3)?;
+        out.serialize_field("code", self.code())?;
+        out.serialize_field("message", &self.to_string())?;
+        out.serialize_field("details", &self.details())?;
+        out.end()
+    }
+}
+
+impl From<AppError> for String {
+    fn from(err: AppError) -> Self {
+        err.to_string()
+    }
+}
@@ -0,0 +1,102 @@
+//! Typed events pushed to the webview so it can react to background work
+//! instead of polling. Payloads match `ui/src/events.ts`. Events go through the
+//! `AppHandle` attached at startup; the CLI never attaches one, so emitting
+//! there does nothing.
+
+use crate::view::AccountView;
+use crate::{flow_to_response, lock_data, lock_flows, Account, QuotaInfo, SharedState};
+use serde::Serialize;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter};
+
+pub(crate) const OAUTH_FLOW_UPDATED_EVENT: &str = "oauth-flow-updated";
+pub(crate) const ACCOUNT_UPDATED_EVENT: &str = "account-updated";
+pub(crate) const QUOTA_UPDATED_EVENT: &str = "quota-updated";
+pub(crate) const PROXY_TESTED_EVENT: &str = "proxy-tested";
+pub(crate) const ACTIVE_ACCOUNT_CHANGED_EVENT: &str = "active-account-changed";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct QuotaUpdated {
+    account_id: String,
+    quota: Option<QuotaInfo>,
+    /// Set when the fetch failed; `quota` then still holds the last good value.
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ActiveAccountChanged {
+    previous_account_id: Option<String>,
+    active_account_id: Option<String>,
+}
+
+pub(crate) fn attach(state: &Arc<SharedState>, app: AppHandle) {
+    let _ = state.app.set(app);
+}
+
+pub(crate) fn emit<S: Serialize + Clone>(state: &Arc<SharedState>, event: &str, payload: S) {
+    let Some(app) = state.app.get() else {
+        return;
+    };
+    if let Err(err) = app.emit(event, payload) {
+        log::warn!("Failed to emit {}: {}", event, err);
+    }
+}
+
+/// Sends the flow's current status. Must not be called with the data or flow
+/// locks held.
+pub(crate) fn oauth_flow_updated(state: &Arc<SharedState>, flow_id: &str) {
+    if state.app.get().is_none() {
+        return;
+    }
+    let flow = match lock_flows(state) {
+        Ok(flows) => flows.get(flow_id).cloned(),
+        Err(_) => None,
+    };
+    let Some(flow) = flow else {
+        return;
+    };
+    let Ok(data) = lock_data(state) else {
+        return;
+    };
+    let payload = flow_to_response(&flow, &data);
+    drop(data);
+    emit(state, OAUTH_FLOW_UPDATED_EVENT, payload);
+}
+
+pub(crate) fn account_updated(state: &Arc<SharedState>, account: &Account) {
+    emit(state, ACCOUNT_UPDATED_EVENT, AccountView::from(account));
+}
+
+/// Reports the outcome of a quota fetch along with the updated account.
+pub(crate) fn quota_updated(state: &Arc<SharedState>, account: &Account) {
+    account_updated(state, account);
+    emit(
+        state,
+        QUOTA_UPDATED_EVENT,
+        QuotaUpdated {
+            account_id: account.id.clone(),
+            quota: account.quota.clone(),
+            error: account.last_error.clone(),
+        },
+    );
+}
+
+pub(crate) fn active_account_changed(
+    state: &Arc<SharedState>,
+    previous: Option<String>,
+    current: Option<String>,
+) {
+    if previous == current {
+        return;
+    }
+    emit(
+        state,
+        ACTIVE_ACCOUNT_CHANGED_EVENT,
+        ActiveAccountChanged {
+            previous_account_id: previous,
+            active_account_id: current,
+        },
+    );
+}
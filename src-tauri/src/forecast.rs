@@ -0,0 +1,267 @@
+//! Burn-rate forecasts built from the quota history. For each window the
+//! samples since it last reset are fitted with a straight line; with too few
+//! samples the average rate since the window opened is used instead.
+
+use crate::history::{self, QuotaSample};
+use crate::{now_ts, Account, QuotaWindow};
+use serde::Serialize;
+
+/// Samples closer together than this are too noisy to fit a slope on.
+const MIN_REGRESSION_SPAN_SECS: i64 = 10 * 60;
+/// `reset_at` can shift by a few seconds between fetches of the same window.
+const RESET_TOLERANCE_SECS: i64 = 120;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ForecastMethod {
+    /// Least-squares fit over the samples of the current window.
+    Regression,
+    /// Current usage divided by the time since the window opened.
+    WindowAverage,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WindowForecast {
+    pub used_percent: f64,
+    pub reset_at: Option<i64>,
+    /// Percentage points per hour; `None` when there is nothing to estimate from.
+    pub burn_rate_per_hour: Option<f64>,
+    /// When usage is projected to reach 100%, if the rate is positive.
+    pub exhausts_at: Option<i64>,
+    pub will_exhaust_before_reset: bool,
+    /// Projected usage when the window resets, capped at 100.
+    pub projected_at_reset: Option<f64>,
+    pub method: Option<ForecastMethod>,
+    pub samples: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AccountForecast {
+    pub account_id: String,
+    pub email: Option<String>,
+    pub primary: Option<WindowForecast>,
+    pub secondary: Option<WindowForecast>,
+    pub generated_at: i64,
+}
+
+/// Slope of `used_percent` over time in percent per second.
+fn regression_slope(points: &[(i64, f64)]) -> Option<f64> {
+    let (first, last) = (points.first()?.0, points.last()?.0);
+    if points.len() < 2 || last - first < MIN_REGRESSION_SPAN_SECS {
+        return None;
+    }
+
+    let n = points.len() as f64;
+    let mean_t = points.iter().map(|(t, _)| (t - first) as f64).sum::<f64>() / n;
+    let mean_u = points.iter().map(|(_, u)| u).sum::<f64>() / n;
+    let (mut num, mut den) = (0.0, 0.0);
+    for (t, u) in points {
+        let dt = (t - first) as f64 - mean_t;
+        num += dt * (u - mean_u);
+        den += dt * dt;
+    }
+    (den > 0.0).then(|| num / den)
+}
+
+fn forecast_window(
+    current: &QuotaWindow,
+    fetched_at: i64,
+    samples: &[QuotaSample],
+    pick: fn(&QuotaSample) -> &QuotaWindow,
+) -> Option<WindowForecast> {
+    let used = current.used_percent?;
+    let window_start = current
+        .reset_at
+        .zip(current.limit_window_seconds)
+        .map(|(reset_at, length)| reset_at - length);
+
+    let same_window = |sample: &QuotaSample| match (pick(sample).reset_at, current.reset_at) {
+        (Some(a), Some(b)) => (a - b).abs() <= RESET_TOLERANCE_SECS,
+        _ => window_start.map_or(true, |start| sample.fetched_at >= start),
+    };
+
+    let mut points: Vec<(i64, f64)> = samples
+        .iter()
+        .filter(|sample| sample.fetched_at < fetched_at && same_window(sample))
+        .filter_map(|sample| Some((sample.fetched_at, pick(sample).used_percent?)))
+        .collect();
+    points.push((fetched_at, used));
+    points.sort_by_key(|(t, _)| *t);
+
+    let (rate, method) = match regression_slope(&points) {
+        Some(slope) => (Some(slope.max(0.0)), Some(ForecastMethod::Regression)),
+        None => match window_start {
+            Some(start) if fetched_at > start => (
+                Some(used / (fetched_at - start) as f64),
+                Some(ForecastMethod::WindowAverage),
+            ),
+            _ => (None, None),
+        },
+    };
+
+    let exhausts_at = rate.filter(|rate| *rate > 0.0).map(|rate| {
+        if used >= 100.0 {
+            fetched_at
+        } else {
+            fetched_at + ((100.0 - used) / rate).ceil() as i64
+        }
+    });
+    let projected_at_reset = rate.zip(current.reset_at).map(|(rate, reset_at)| {
+        let remaining = (reset_at - fetched_at).max(0) as f64;
+        (used + rate * remaining).min(100.0)
+    });
+    let will_exhaust_before_reset = match (exhausts_at, current.reset_at) {
+        (Some(at), Some(reset_at)) => at < reset_at,
+        (Some(_), None) => true,
+        _ => false,
+    };
+
+    Some(WindowForecast {
+        used_percent: used,
+        reset_at: current.reset_at,
+        burn_rate_per_hour: rate.map(|rate| rate * 3600.0),
+        exhausts_at,
+        will_exhaust_before_reset,
+        projected_at_reset,
+        method,
+        samples: points.len(),
+    })
+}
+
+pub(crate) fn forecast_account(account: &Account) -> Result<AccountForecast, String> {
+    let mut forecast = AccountForecast {
+        account_id: account.id.clone(),
+        email: account.email.clone(),
+        primary: None,
+        secondary: None,
+        generated_at: now_ts(),
+    };
+    let Some(quota) = account.quota.as_ref() else {
+        return Ok(forecast);
+    };
+
+    // Only the longer window's span of history can matter for either forecast.
+    let since = [&quota.primary, &quota.secondary]
+        .iter()
+        .filter_map(|w| w.reset_at.zip(w.limit_window_seconds))
+        .map(|(reset_at, length)| reset_at - length)
+        .min();
+    let samples = history::query(Some(&account.id), since, None)?
+        .into_iter()
+        .next()
+        .map(|series| series.samples)
+        .unwrap_or_default();
+
+    forecast.primary = forecast_window(&quota.primary, quota.fetched_at, &samples, |s| &s.primary);
+    forecast.secondary = forecast_window(&quota.secondary, quota.fetched_at, &samples, |s| {
+        &s.secondary
+    });
+    Ok(forecast)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 3600;
+    const WINDOW: i64 = 5 * HOUR;
+    const RESET_AT: i64 = 100_000;
+    const WINDOW_START: i64 = RESET_AT - WINDOW;
+
+    fn window(used_percent: f64, reset_at: i64) -> QuotaWindow {
+        QuotaWindow {
+            used_percent: Some(used_percent),
+            limit_window_seconds: Some(WINDOW),
+            reset_at: Some(reset_at),
+            fetched_at: None,
+        }
+    }
+
+    fn sample(fetched_at: i64, used_percent: f64, reset_at: i64) -> QuotaSample {
+        QuotaSample {
+            account_id: "account-1".to_string(),
+            email: None,
+            plan_type: None,
+            primary: window(used_percent, reset_at),
+            secondary: QuotaWindow::default(),
+            fetched_at,
+        }
+    }
+
+    fn forecast(used_percent: f64, fetched_at: i64, samples: &[QuotaSample]) -> WindowForecast {
+        forecast_window(&window(used_percent, RESET_AT), fetched_at, samples, |s| {
+            &s.primary
+        })
+        .unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn slope_needs_enough_span() {
+        assert_eq!(regression_slope(&[(0, 10.0)]), None);
+        assert_eq!(regression_slope(&[(0, 10.0), (60, 20.0)]), None);
+        let slope = regression_slope(&[(0, 10.0), (HOUR, 20.0), (2 * HOUR, 30.0)]).unwrap();
+        assert_close(slope * HOUR as f64, 10.0);
+    }
+
+    #[test]
+    fn fits_samples_of_the_current_window() {
+        let samples = [
+            // The previous window, which must not drag the rate down.
+            sample(WINDOW_START - HOUR, 90.0, WINDOW_START),
+            sample(WINDOW_START + HOUR, 10.0, RESET_AT),
+            sample(WINDOW_START + 2 * HOUR, 20.0, RESET_AT + 30),
+        ];
+        let forecast = forecast(30.0, WINDOW_START + 3 * HOUR, &samples);
+
+        assert!(matches!(forecast.method, Some(ForecastMethod::Regression)));
+        assert_eq!(forecast.samples, 3);
+        assert_close(forecast.burn_rate_per_hour.unwrap(), 10.0);
+        // 70 points left at 10 per hour.
+        let exhausts_at = forecast.exhausts_at.unwrap();
+        assert!((exhausts_at - (WINDOW_START + 10 * HOUR)).abs() <= 1);
+        assert!(!forecast.will_exhaust_before_reset);
+        assert_close(forecast.projected_at_reset.unwrap(), 50.0);
+    }
+
+    #[test]
+    fn falls_back_to_the_window_average() {
+        let forecast = forecast(40.0, WINDOW_START + 2 * HOUR, &[]);
+
+        assert!(matches!(
+            forecast.method,
+            Some(ForecastMethod::WindowAverage)
+        ));
+        assert_eq!(forecast.samples, 1);
+        assert_close(forecast.burn_rate_per_hour.unwrap(), 20.0);
+        // 60 points left at 20 per hour, with 3 hours to go.
+        assert!((forecast.exhausts_at.unwrap() - (WINDOW_START + 5 * HOUR)).abs() <= 1);
+        assert!(!forecast.will_exhaust_before_reset);
+        assert_close(forecast.projected_at_reset.unwrap(), 100.0);
+    }
+
+    #[test]
+    fn flags_exhaustion_before_reset() {
+        let samples = [sample(WINDOW_START, 0.0, RESET_AT)];
+        let forecast = forecast(50.0, WINDOW_START + HOUR, &samples);
+
+        assert_close(forecast.burn_rate_per_hour.unwrap(), 50.0);
+        assert!(forecast.will_exhaust_before_reset);
+        assert_eq!(forecast.projected_at_reset, Some(100.0));
+    }
+
+    #[test]
+    fn never_reports_a_negative_rate() {
+        let samples = [sample(WINDOW_START + HOUR, 40.0, RESET_AT)];
+        let forecast = forecast(20.0, WINDOW_START + 2 * HOUR, &samples);
+
+        assert_eq!(forecast.burn_rate_per_hour, Some(0.0));
+        assert_eq!(forecast.exhausts_at, None);
+        assert!(!forecast.will_exhaust_before_reset);
+    }
+}
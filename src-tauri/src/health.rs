@@ -0,0 +1,150 @@
+//! Structured account health. `Account.last_error` keeps the message for the
+//! UI; the status here says what kind of trouble the account is in, so
+//! rotation and the UI do not have to guess from error strings.
+
+use crate::http::{HttpError, HttpErrorKind};
+use crate::now_ts;
+use serde::{Deserialize, Serialize};
+
+/// A healthy account that has not been checked for this long is reported as
+/// stale.
+const STALE_AFTER_SECS: i64 = 30 * 60;
+/// Error bodies that mean the account itself is gone rather than its token.
+const DEACTIVATED_MARKERS: [&str; 4] = ["deactivated", "banned", "suspended", "disabled"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountStatus {
+    /// The last quota check succeeded.
+    Healthy,
+    /// Not checked yet, or not checked recently.
+    #[default]
+    Stale,
+    /// The access token was rejected and renewing it failed for a transient
+    /// reason; the next refresh may fix it.
+    TokenExpired,
+    /// The refresh token was rejected or is missing; only a new login helps.
+    NeedsReauth,
+    RateLimited,
+    Deactivated,
+    /// Connection failures, timeouts, proxy trouble and upstream 5xx.
+    NetworkError,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountHealth {
+    pub status: AccountStatus,
+    /// When the account entered `status`.
+    pub since: Option<i64>,
+    /// When `status` was last confirmed.
+    pub checked_at: Option<i64>,
+    /// Status code of the response that caused it, if there was one.
+    pub http_status: Option<u16>,
+    /// For `RateLimited`: when upstream asked to be contacted again.
+    pub retry_at: Option<i64>,
+}
+
+fn is_deactivated(err: &HttpError) -> bool {
+    let message = err.message.to_ascii_lowercase();
+    DEACTIVATED_MARKERS
+        .iter()
+        .any(|marker| message.contains(marker))
+}
+
+impl AccountHealth {
+    pub(crate) fn set(&mut self, status: AccountStatus, http_status: Option<u16>) {
+        let now = now_ts();
+        if self.status != status || self.since.is_none() {
+            self.since = Some(now);
+        }
+        self.status = status;
+        self.checked_at = Some(now);
+        self.http_status = http_status;
+        self.retry_at = None;
+    }
+
+    pub(crate) fn mark_healthy(&mut self) {
+        self.set(AccountStatus::Healthy, None);
+    }
+
+    /// Records a failed request made with the account's access token.
+    pub(crate) fn mark_error(&mut self, err: &HttpError) {
+        let status = match err.kind {
+            _ if is_deactivated(err) => AccountStatus::Deactivated,
+            HttpErrorKind::Auth => AccountStatus::NeedsReauth,
+            HttpErrorKind::RateLimited => AccountStatus::RateLimited,
+            _ => AccountStatus::NetworkError,
+        };
+        self.set(status, err.status);
+        if status == AccountStatus::RateLimited {
+            self.retry_at = err
+                .retry_after
+                .map(|delay| now_ts() + delay.as_secs() as i64);
+        }
+    }
+
+    /// Records a failed token renewal. Anything short of a rejected grant
+    /// leaves the account waiting for the next renewal attempt.
+    pub(crate) fn mark_refresh_error(&mut self, err: &HttpError) {
+        match err.kind {
+            HttpErrorKind::Auth => self.mark_error(err),
+            _ if is_deactivated(err) => self.mark_error(err),
+            _ => self.set(AccountStatus::TokenExpired, err.status),
+        }
+    }
+
+    /// New credentials clear token trouble; whether they work is only known
+    /// after the next quota check.
+    pub(crate) fn credentials_renewed(&mut self) {
+        if matches!(
+            self.status,
+            AccountStatus::TokenExpired | AccountStatus::NeedsReauth
+        ) {
+            self.set(AccountStatus::Stale, None);
+        }
+    }
+
+    /// A new login replaces whatever was wrong with the old credentials,
+    /// including a deactivation that has since been lifted. Rate limits
+    /// belong to the account rather than the login, so they stay.
+    pub(crate) fn signed_in(&mut self) {
+        if matches!(
+            self.status,
+            AccountStatus::TokenExpired | AccountStatus::NeedsReauth | AccountStatus::Deactivated
+        ) {
+            self.set(AccountStatus::Stale, None);
+        }
+    }
+
+    /// The status as of `now`: old successes turn stale and rate limits lapse
+    /// once their retry time has passed.
+    pub(crate) fn current(&self, now: i64) -> Self {
+        let lapsed = match self.status {
+            AccountStatus::Healthy => self
+                .checked_at
+                .map(|at| at + STALE_AFTER_SECS)
+                .filter(|stale_at| *stale_at <= now),
+            AccountStatus::RateLimited => self.retry_at.filter(|retry_at| *retry_at <= now),
+            _ => None,
+        };
+        match lapsed {
+            Some(since) => Self {
+                status: AccountStatus::Stale,
+                since: Some(since),
+                http_status: None,
+                retry_at: None,
+                ..self.clone()
+            },
+            None => self.clone(),
+        }
+    }
+
+    /// Whether the account can be switched to or relayed through.
+    pub(crate) fn is_usable(&self, now: i64) -> bool {
+        matches!(
+            self.current(now).status,
+            AccountStatus::Healthy | AccountStatus::Stale
+        )
+    }
+}
@@ -0,0 +1,268 @@
+//! Append-only quota history kept in `quota_history.jsonl` next to
+//! `state.json`. Every successful quota fetch adds one line; older samples are
+//! downsampled so the file stays small.
+
+use crate::{app_storage_dir, now_ts, storage, Account, QuotaWindow};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const HISTORY_FILE: &str = "quota_history.jsonl";
+/// Compaction runs once the file grows past this size.
+const COMPACT_THRESHOLD_BYTES: u64 = 4 * 1024 * 1024;
+const HOUR: i64 = 3600;
+const DAY: i64 = 24 * HOUR;
+
+/// Retention tiers as (max age, bucket width): samples younger than the age
+/// keep one entry per bucket and account. Anything older than the last tier
+/// is dropped.
+const RETENTION: &[(i64, i64)] = &[(2 * DAY, 0), (30 * DAY, HOUR), (180 * DAY, DAY)];
+
+static HISTORY_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct QuotaSample {
+    pub account_id: String,
+    /// Kept on every line so history stays readable after an account is removed.
+    pub email: Option<String>,
+    pub plan_type: Option<String>,
+    pub primary: QuotaWindow,
+    pub secondary: QuotaWindow,
+    pub fetched_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct QuotaSeries {
+    pub account_id: String,
+    pub email: Option<String>,
+    pub samples: Vec<QuotaSample>,
+}
+
+/// Quota consumed in a range, in percentage points of each window. Resets are
+/// accounted for, so several exhausted windows add up to more than 100.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct UsageSummary {
+    pub account_id: String,
+    pub email: Option<String>,
+    pub primary_used: f64,
+    pub secondary_used: f64,
+    pub samples: usize,
+}
+
+fn history_path() -> Result<PathBuf, String> {
+    Ok(app_storage_dir()?.join(HISTORY_FILE))
+}
+
+fn read_all(path: &Path) -> Result<Vec<QuotaSample>, String> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(format!("Failed to open quota history: {err}")),
+    };
+
+    let mut samples = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| format!("Failed to read quota history: {e}"))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        // A torn final line from a crash is skipped rather than failing the query.
+        match serde_json::from_str::<QuotaSample>(&line) {
+            Ok(sample) => samples.push(sample),
+            Err(err) => log::warn!("Skipping unreadable quota history line: {}", err),
+        }
+    }
+    Ok(samples)
+}
+
+/// Appends the account's current quota. Failures are logged; history is
+/// best effort and must never block a refresh.
+pub(crate) fn record(account: &Account) {
+    let Some(quota) = account.quota.as_ref() else {
+        return;
+    };
+    let sample = QuotaSample {
+        account_id: account.id.clone(),
+        email: account.email.clone(),
+        plan_type: quota.plan_type.clone(),
+        primary: quota.primary.clone(),
+        secondary: quota.secondary.clone(),
+        fetched_at: quota.fetched_at,
+    };
+
+    if let Err(err) = append(&sample) {
+        log::warn!("Failed to record quota history: {}", err);
+    }
+}
+
+fn append(sample: &QuotaSample) -> Result<(), String> {
+    let _guard = HISTORY_LOCK
+        .lock()
+        .map_err(|_| "Failed to lock quota history".to_string())?;
+    let path = history_path()?;
+
+    let mut line =
+        serde_json::to_string(sample).map_err(|e| format!("Failed to serialize sample: {e}"))?;
+    line.push('\n');
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to open quota history: {e}"))?;
+    file.write_all(line.as_bytes())
+        .map_err(|e| format!("Failed to append quota history: {e}"))?;
+
+    let size = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+    drop(file);
+    if size > COMPACT_THRESHOLD_BYTES {
+        compact_locked(&path)?;
+    }
+    Ok(())
+}
+
+/// Applies the retention tiers. Called at startup and whenever the file grows
+/// past the size threshold.
+pub(crate) fn compact() -> Result<(), String> {
+    let _guard = HISTORY_LOCK
+        .lock()
+        .map_err(|_| "Failed to lock quota history".to_string())?;
+    let path = history_path()?;
+    if !path.exists() {
+        return Ok(());
+    }
+    compact_locked(&path)
+}
+
+fn compact_locked(path: &Path) -> Result<(), String> {
+    let samples = read_all(path)?;
+    let before = samples.len();
+    let kept = downsample(samples, now_ts());
+
+    let mut text = String::new();
+    for sample in &kept {
+        let line = serde_json::to_string(sample)
+            .map_err(|e| format!("Failed to serialize sample: {e}"))?;
+        text.push_str(&line);
+        text.push('\n');
+    }
+
+    storage::write_atomic(path, text.as_bytes())?;
+
+    log::info!(
+        "Compacted quota history from {} to {} samples",
+        before,
+        kept.len()
+    );
+    Ok(())
+}
+
+/// Keeps the newest sample per account and bucket of the matching tier.
+fn downsample(mut samples: Vec<QuotaSample>, now: i64) -> Vec<QuotaSample> {
+    samples.sort_by_key(|sample| sample.fetched_at);
+
+    let mut buckets: HashMap<(String, usize, i64), usize> = HashMap::new();
+    let mut kept: Vec<Option<QuotaSample>> = Vec::with_capacity(samples.len());
+
+    for sample in samples {
+        let age = now - sample.fetched_at;
+        let Some(tier) = RETENTION.iter().position(|(max_age, _)| age <= *max_age) else {
+            continue;
+        };
+        let width = RETENTION[tier].1;
+        if width == 0 {
+            kept.push(Some(sample));
+            continue;
+        }
+
+        let key = (sample.account_id.clone(), tier, sample.fetched_at / width);
+        if let Some(&index) = buckets.get(&key) {
+            kept[index] = None;
+        }
+        buckets.insert(key, kept.len());
+        kept.push(Some(sample));
+    }
+
+    kept.into_iter().flatten().collect()
+}
+
+fn in_range(sample: &QuotaSample, from: Option<i64>, to: Option<i64>) -> bool {
+    from.map_or(true, |from| sample.fetched_at >= from)
+        && to.map_or(true, |to| sample.fetched_at <= to)
+}
+
+/// Samples per account within `[from, to]`, oldest first.
+pub(crate) fn query(
+    account_id: Option<&str>,
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Result<Vec<QuotaSeries>, String> {
+    let samples = {
+        let _guard = HISTORY_LOCK
+            .lock()
+            .map_err(|_| "Failed to lock quota history".to_string())?;
+        read_all(&history_path()?)?
+    };
+
+    let mut series: BTreeMap<String, QuotaSeries> = BTreeMap::new();
+    for sample in samples {
+        if account_id.is_some_and(|id| id != sample.account_id) || !in_range(&sample, from, to) {
+            continue;
+        }
+        let entry = series
+            .entry(sample.account_id.clone())
+            .or_insert_with(|| QuotaSeries {
+                account_id: sample.account_id.clone(),
+                email: None,
+                samples: Vec::new(),
+            });
+        entry.email = sample.email.clone().or(entry.email.take());
+        entry.samples.push(sample);
+    }
+
+    let mut series: Vec<QuotaSeries> = series.into_values().collect();
+    for entry in &mut series {
+        entry.samples.sort_by_key(|sample| sample.fetched_at);
+    }
+    Ok(series)
+}
+
+/// Sums usage increases between consecutive samples. A drop means the window
+/// reset, so the new value counts as fresh usage.
+fn consumed(windows: impl Iterator<Item = Option<f64>>) -> f64 {
+    let mut total = 0.0;
+    let mut previous: Option<f64> = None;
+    for used in windows.flatten() {
+        if let Some(prev) = previous {
+            total += if used >= prev { used - prev } else { used };
+        }
+        previous = Some(used);
+    }
+    total
+}
+
+/// Per-account consumption in `[from, to]`, heaviest user first.
+pub(crate) fn usage_summary(
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Result<Vec<UsageSummary>, String> {
+    let mut summaries: Vec<UsageSummary> = query(None, from, to)?
+        .into_iter()
+        .map(|series| UsageSummary {
+            primary_used: consumed(series.samples.iter().map(|s| s.primary.used_percent)),
+            secondary_used: consumed(series.samples.iter().map(|s| s.secondary.used_percent)),
+            samples: series.samples.len(),
+            account_id: series.account_id,
+            email: series.email,
+        })
+        .collect();
+
+    summaries.sort_by(|a, b| b.secondary_used.total_cmp(&a.secondary_used));
+    Ok(summaries)
+}
@@ -0,0 +1,257 @@
+//! Outbound HTTP: a shared pooled client and the retry policy every upstream
+//! call goes through.
+//!
+//! Building a reqwest client per request throws away the connection pool and
+//! TLS sessions, so one client is kept for the current proxy configuration and
+//! handed out by clone; clones share the pool. Clients carry no overall
+//! timeout, so callers set one per request.
+
+use crate::{proxy_to_url, ProxyEntry};
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::StatusCode;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+/// A longer Retry-After is reported to the caller instead of slept through.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// The proxy URL the cached client was built for, and the client itself.
+static CLIENT: Mutex<Option<(Option<String>, Client)>> = Mutex::new(None);
+
+pub(crate) fn shared_client(proxy: Option<&ProxyEntry>) -> Result<Client, String> {
+    let proxy_url = proxy.map(proxy_to_url).transpose()?;
+    let mut slot = CLIENT
+        .lock()
+        .map_err(|_| "Failed to lock HTTP client".to_string())?;
+
+    if let Some((cached_url, client)) = slot.as_ref() {
+        if *cached_url == proxy_url {
+            return Ok(client.clone());
+        }
+    }
+
+    let mut builder = Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(None::<Duration>)
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT);
+    if let Some(url) = &proxy_url {
+        let proxy_cfg = reqwest::Proxy::all(url).map_err(|e| format!("Invalid proxy: {e}"))?;
+        builder = builder.proxy(proxy_cfg);
+    }
+    let client = builder
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {e}"))?;
+
+    *slot = Some((proxy_url, client.clone()));
+    Ok(client)
+}
+
+/// Drops the cached client along with the proxy credentials it holds.
+pub(crate) fn clear() {
+    if let Ok(mut slot) = CLIENT.lock() {
+        *slot = None;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HttpErrorKind {
+    /// The connection was never established, so the request was not sent.
+    Connect,
+    Timeout,
+    /// 429 Too Many Requests.
+    RateLimited,
+    /// 5xx from upstream.
+    Server,
+    /// 401/403, or a rejected grant at the token endpoint.
+    Auth,
+    /// Any other 4xx.
+    Client,
+    /// Broken connections, unreadable bodies and other transport failures.
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct HttpError {
+    pub kind: HttpErrorKind,
+    pub status: Option<u16>,
+    pub retry_after: Option<Duration>,
+    pub message: String,
+}
+
+impl HttpError {
+    pub(crate) fn new(kind: HttpErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            status: None,
+            retry_after: None,
+            message: message.into(),
+        }
+    }
+
+    fn from_transport(err: &reqwest::Error, label: &str) -> Self {
+        let kind = if err.is_timeout() {
+            HttpErrorKind::Timeout
+        } else if err.is_connect() {
+            HttpErrorKind::Connect
+        } else {
+            HttpErrorKind::Other
+        };
+        Self::new(kind, format!("{label} failed: {err}"))
+    }
+
+    fn from_status(status: StatusCode, retry_after: Option<Duration>, message: String) -> Self {
+        let kind = match status.as_u16() {
+            429 => HttpErrorKind::RateLimited,
+            401 | 403 => HttpErrorKind::Auth,
+            500..=599 => HttpErrorKind::Server,
+            _ => HttpErrorKind::Client,
+        };
+        Self {
+            kind,
+            status: Some(status.as_u16()),
+            retry_after,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<HttpError> for String {
+    fn from(err: HttpError) -> Self {
+        err.message
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Whether repeating a request that may have reached the server is safe.
+    /// Token grants are single use, so those only retry when the server
+    /// cannot have acted on the first attempt.
+    pub idempotent: bool,
+}
+
+/// For reads such as the usage endpoint.
+pub(crate) const READ_POLICY: RetryPolicy = RetryPolicy {
+    max_attempts: 3,
+    base_delay: Duration::from_millis(500),
+    max_delay: Duration::from_secs(8),
+    idempotent: true,
+};
+
+/// For OAuth token grants.
+pub(crate) const GRANT_POLICY: RetryPolicy = RetryPolicy {
+    max_attempts: 3,
+    base_delay: Duration::from_secs(1),
+    max_delay: Duration::from_secs(8),
+    idempotent: false,
+};
+
+impl RetryPolicy {
+    fn should_retry(&self, err: &HttpError) -> bool {
+        match err.kind {
+            HttpErrorKind::Connect | HttpErrorKind::RateLimited => true,
+            HttpErrorKind::Server if !self.idempotent => err.status == Some(503),
+            HttpErrorKind::Timeout | HttpErrorKind::Server | HttpErrorKind::Other => {
+                self.idempotent
+            }
+            HttpErrorKind::Auth | HttpErrorKind::Client => false,
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(1 << attempt.saturating_sub(1).min(8));
+        let jitter_ms = rand::thread_rng().gen_range(0..=self.base_delay.as_millis() as u64 / 2);
+        exponential.min(self.max_delay) + Duration::from_millis(jitter_ms)
+    }
+}
+
+/// Parses `Retry-After` as either delay seconds or an HTTP date.
+pub(crate) fn parse_retry_after(response: &Response) -> Option<Duration> {
+    let value = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = DateTime::parse_from_rfc2822(value)
+        .ok()?
+        .with_timezone(&Utc);
+    (at - Utc::now()).to_std().ok()
+}
+
+fn read_response(response: Response, label: &str) -> Result<String, HttpError> {
+    let status = response.status();
+    let retry_after = parse_retry_after(&response);
+    let body = response.text().map_err(|e| {
+        HttpError::new(
+            HttpErrorKind::Other,
+            format!("{label} failed: unreadable response: {e}"),
+        )
+    })?;
+
+    if status.is_success() {
+        return Ok(body);
+    }
+    let message = format!(
+        "{label} failed ({status}): {}",
+        body.chars().take(240).collect::<String>()
+    );
+    Err(HttpError::from_status(status, retry_after, message))
+}
+
+/// Sends the request built by `build`, retrying transient failures according
+/// to `policy`, and returns the body of the first successful response.
+/// `label` names the call in error messages, e.g. "Quota request".
+pub(crate) fn send(
+    policy: &RetryPolicy,
+    label: &str,
+    build: impl Fn() -> RequestBuilder,
+) -> Result<String, HttpError> {
+    let mut attempt = 1;
+    loop {
+        let result = build()
+            .send()
+            .map_err(|e| HttpError::from_transport(&e, label))
+            .and_then(|response| read_response(response, label));
+
+        let err = match result {
+            Ok(body) => return Ok(body),
+            Err(err) => err,
+        };
+        if attempt >= policy.max_attempts || !policy.should_retry(&err) {
+            return Err(err);
+        }
+        let delay = err.retry_after.unwrap_or_else(|| policy.backoff(attempt));
+        if delay > MAX_RETRY_AFTER {
+            return Err(err);
+        }
+
+        log::info!(
+            "{} (attempt {}/{}), retrying in {}ms",
+            err.message,
+            attempt,
+            policy.max_attempts,
+            delay.as_millis()
+        );
+        std::thread::sleep(delay);
+        attempt += 1;
+    }
+}
@@ -0,0 +1,558 @@
+//! IDE targets the app can reload after a switch. Built-in editors are defined
+//! here; users can add their own in settings, which are stored in
+//! `AppData::custom_ides` and merged on top.
+
+use crate::AppData;
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+#[cfg(not(target_os = "windows"))]
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+#[cfg(target_os = "windows")]
+use std::os::windows::process::CommandExt;
+#[cfg(target_os = "windows")]
+use std::process::Output;
+
+#[cfg(target_os = "windows")]
+const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+/// How an IDE picks up a new auth.json.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum IdeReloadStrategy {
+    /// Run `<cli> --reuse-window --command workbench.action.reloadWindow`
+    /// (VS Code family), restarting the process if no CLI is found.
+    #[default]
+    ReloadWindow,
+    /// Kill the running processes and relaunch from the executable path.
+    Restart,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdeTarget {
+    pub id: String,
+    pub label: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub cli_candidates: Vec<String>,
+    /// `Get-Process` names on Windows, executable file names on Linux and
+    /// `.app` bundle names on macOS.
+    #[serde(default)]
+    pub process_names: Vec<String>,
+    #[serde(default)]
+    pub reload: IdeReloadStrategy,
+    #[serde(default, skip_deserializing)]
+    pub builtin: bool,
+}
+
+fn builtin(
+    id: &str,
+    label: &str,
+    aliases: &[&str],
+    cli_candidates: &[&str],
+    process_names: &[&str],
+    reload: IdeReloadStrategy,
+) -> IdeTarget {
+    let owned = |items: &[&str]| items.iter().map(|item| item.to_string()).collect();
+    IdeTarget {
+        id: id.to_string(),
+        label: label.to_string(),
+        aliases: owned(aliases),
+        cli_candidates: owned(cli_candidates),
+        process_names: owned(process_names),
+        reload,
+        builtin: true,
+    }
+}
+
+pub(crate) fn builtin_targets() -> Vec<IdeTarget> {
+    use IdeReloadStrategy::{ReloadWindow, Restart};
+
+    let windows = cfg!(target_os = "windows");
+    let names = |windows_names: &'static [&'static str], unix_names: &'static [&'static str]| {
+        if windows {
+            windows_names
+        } else {
+            unix_names
+        }
+    };
+
+    vec![
+        builtin(
+            "vscode",
+            "VS Code",
+            &["code"],
+            &["code", "code-insiders"],
+            names(
+                &["Code", "Code - Insiders"],
+                &[
+                    "code",
+                    "code-insiders",
+                    "Visual Studio Code",
+                    "Visual Studio Code - Insiders",
+                ],
+            ),
+            ReloadWindow,
+        ),
+        builtin(
+            "cursor",
+            "Cursor",
+            &[],
+            &["cursor"],
+            names(&["Cursor"], &["cursor", "Cursor"]),
+            ReloadWindow,
+        ),
+        builtin(
+            "windsurf",
+            "Windsurf",
+            &[],
+            &["windsurf"],
+            names(&["Windsurf"], &["windsurf", "Windsurf"]),
+            ReloadWindow,
+        ),
+        builtin(
+            "trae",
+            "Trae",
+            &[],
+            &["trae"],
+            names(&["Trae"], &["trae", "Trae"]),
+            ReloadWindow,
+        ),
+        builtin(
+            "vscodium",
+            "VSCodium",
+            &["codium"],
+            &["codium"],
+            names(&["VSCodium"], &["codium", "VSCodium"]),
+            ReloadWindow,
+        ),
+        // Zed's CLI has no command palette entry point, so it is restarted.
+        builtin(
+            "zed",
+            "Zed",
+            &[],
+            &["zed"],
+            names(&["Zed"], &["zed", "zed-editor", "Zed"]),
+            Restart,
+        ),
+    ]
+}
+
+/// Built-in targets followed by the user's custom ones.
+pub(crate) fn all_targets(data: &AppData) -> Vec<IdeTarget> {
+    let mut targets = builtin_targets();
+    targets.extend(data.custom_ides.iter().cloned());
+    targets
+}
+
+/// Looks a target up by id or alias, case-insensitively.
+pub(crate) fn find_target(data: &AppData, input: &str) -> Option<IdeTarget> {
+    let needle = input.trim().to_ascii_lowercase();
+    all_targets(data).into_iter().find(|target| {
+        target.id == needle
+            || target
+                .aliases
+                .iter()
+                .any(|alias| alias.eq_ignore_ascii_case(&needle))
+    })
+}
+
+/// Resolves user input to a canonical target id.
+pub(crate) fn normalize_target(data: &AppData, input: &str) -> Result<String, String> {
+    find_target(data, input)
+        .map(|target| target.id)
+        .ok_or_else(|| "Invalid IDE target".to_string())
+}
+
+fn clean_list(items: Vec<String>) -> Vec<String> {
+    items
+        .into_iter()
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Normalizes a custom target coming from settings and rejects ids that
+/// would shadow another entry.
+pub(crate) fn validate_custom(data: &AppData, target: IdeTarget) -> Result<IdeTarget, String> {
+    let id = target.id.trim().to_ascii_lowercase();
+    if id.is_empty()
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err("IDE id may only contain letters, digits, '-' and '_'".to_string());
+    }
+    if builtin_targets().iter().any(|b| b.id == id) {
+        return Err(format!("'{id}' is a built-in IDE target"));
+    }
+
+    let target = IdeTarget {
+        label: match target.label.trim() {
+            "" => id.clone(),
+            label => label.to_string(),
+        },
+        aliases: clean_list(target.aliases)
+            .into_iter()
+            .map(|alias| alias.to_ascii_lowercase())
+            .collect(),
+        cli_candidates: clean_list(target.cli_candidates),
+        process_names: clean_list(target.process_names),
+        reload: target.reload,
+        builtin: false,
+        id,
+    };
+
+    if target.cli_candidates.is_empty() && target.process_names.is_empty() {
+        return Err("Add at least one CLI command or process name".to_string());
+    }
+
+    let taken = all_targets(data)
+        .into_iter()
+        .filter(|other| other.builtin || other.id != target.id)
+        .flat_map(|other| std::iter::once(other.id).chain(other.aliases))
+        .find(|name| *name == target.id || target.aliases.contains(name));
+    if let Some(name) = taken {
+        return Err(format!("'{name}' is already used by another IDE target"));
+    }
+
+    Ok(target)
+}
+
+#[cfg(target_os = "windows")]
+fn run_hidden_powershell(script: &str) -> Result<Output, String> {
+    Command::new("powershell")
+        .args([
+            "-NoProfile",
+            "-ExecutionPolicy",
+            "Bypass",
+            "-WindowStyle",
+            "Hidden",
+            "-Command",
+            script,
+        ])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+        .map_err(|e| format!("Failed to execute PowerShell command: {e}"))
+}
+
+/// Single-quoted PowerShell array items; custom names come from settings, so
+/// embedded quotes are escaped.
+#[cfg(target_os = "windows")]
+fn powershell_list(items: &[String]) -> String {
+    items
+        .iter()
+        .map(|name| format!("'{}'", name.replace('\'', "''")))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(target_os = "windows")]
+fn trigger_reload_command(cli_candidates: &[String]) -> Result<bool, String> {
+    if cli_candidates.is_empty() {
+        return Ok(false);
+    }
+    let quoted = powershell_list(cli_candidates);
+
+    let script = format!(
+        "$ErrorActionPreference='SilentlyContinue'; \
+$cmds=@({quoted}); \
+$ok=$false; \
+foreach ($cmd in $cmds) {{ \
+  if (Get-Command $cmd -ErrorAction SilentlyContinue) {{ \
+    & $cmd --reuse-window --command workbench.action.reloadWindow | Out-Null; \
+    $ok=$true; \
+    break; \
+  }} \
+}}; \
+if ($ok) {{ exit 0 }} else {{ exit 2 }}"
+    );
+
+    let output = run_hidden_powershell(&script)?;
+    match output.status.code() {
+        Some(0) => Ok(true),
+        Some(2) => Ok(false),
+        _ => {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            if stderr.is_empty() {
+                Err("IDE reload command failed".to_string())
+            } else {
+                Err(format!("IDE reload command failed: {stderr}"))
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| {
+            std::fs::metadata(candidate)
+                .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+                .unwrap_or(false)
+        })
+}
+
+#[cfg(not(target_os = "windows"))]
+fn trigger_reload_command(cli_candidates: &[String]) -> Result<bool, String> {
+    let Some(cli) = cli_candidates.iter().find_map(|name| find_in_path(name)) else {
+        return Ok(false);
+    };
+
+    let output = Command::new(&cli)
+        .args([
+            "--reuse-window",
+            "--command",
+            "workbench.action.reloadWindow",
+        ])
+        .stdin(std::process::Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to run {}: {e}", cli.display()))?;
+
+    if output.status.success() {
+        return Ok(true);
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    if stderr.is_empty() {
+        Err("IDE reload command failed".to_string())
+    } else {
+        Err(format!("IDE reload command failed: {stderr}"))
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn restart_processes(process_names: &[String]) -> Result<bool, String> {
+    if process_names.is_empty() {
+        return Ok(false);
+    }
+    let quoted = powershell_list(process_names);
+
+    let script = format!(
+        "$ErrorActionPreference='SilentlyContinue'; \
+$names=@({quoted}); \
+$all=@(); \
+foreach ($name in $names) {{ \
+  $all += Get-Process -Name $name -ErrorAction SilentlyContinue; \
+}}; \
+if (-not $all -or $all.Count -eq 0) {{ exit 2 }}; \
+$launchPath=$null; \
+$main=$all | Where-Object {{ $_.MainWindowHandle -ne 0 -and $_.Path }} | Select-Object -First 1; \
+if ($main) {{ $launchPath=$main.Path }} else {{ \
+  $any=$all | Where-Object {{ $_.Path }} | Select-Object -First 1; \
+  if ($any) {{ $launchPath=$any.Path }}; \
+}}; \
+foreach ($p in $all) {{ Stop-Process -Id $p.Id -Force -ErrorAction SilentlyContinue }}; \
+if ($launchPath) {{ \
+  Start-Process -WindowStyle Hidden -FilePath $launchPath | Out-Null; \
+  exit 0 \
+}} else {{ \
+  exit 3 \
+}}"
+    );
+
+    let output = run_hidden_powershell(&script)?;
+    match output.status.code() {
+        Some(0) => Ok(true),
+        Some(2) => Ok(false),
+        Some(3) => Err("IDE process path was not found for restart".to_string()),
+        _ => {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            if stderr.is_empty() {
+                Err("IDE process restart command failed".to_string())
+            } else {
+                Err(format!("IDE process restart command failed: {stderr}"))
+            }
+        }
+    }
+}
+
+/// A running IDE process and the path it should be relaunched from.
+#[cfg(not(target_os = "windows"))]
+struct IdeProcess {
+    pid: u32,
+    launch_path: PathBuf,
+}
+
+#[cfg(not(target_os = "windows"))]
+fn process_matches(exe: &std::path::Path, process_names: &[String]) -> bool {
+    let file_name = exe
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    process_names.iter().any(|name| {
+        file_name.eq_ignore_ascii_case(name)
+            || exe.components().any(|part| {
+                part.as_os_str()
+                    .to_string_lossy()
+                    .eq_ignore_ascii_case(&format!("{name}.app"))
+            })
+    })
+}
+
+/// AppImages run from a temporary mount that disappears with the process, so
+/// relaunch the image itself when the process advertises one.
+#[cfg(target_os = "linux")]
+fn appimage_path(pid: u32) -> Option<PathBuf> {
+    let environ = std::fs::read(format!("/proc/{pid}/environ")).ok()?;
+    environ
+        .split(|byte| *byte == 0)
+        .find_map(|entry| entry.strip_prefix(b"APPIMAGE="))
+        .map(|value| PathBuf::from(String::from_utf8_lossy(value).to_string()))
+}
+
+#[cfg(target_os = "linux")]
+fn find_processes(process_names: &[String]) -> Result<Vec<IdeProcess>, String> {
+    let entries = std::fs::read_dir("/proc").map_err(|e| format!("Failed to read /proc: {e}"))?;
+    let own_pid = std::process::id();
+
+    let mut found = Vec::new();
+    for entry in entries.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if pid == own_pid {
+            continue;
+        }
+        // Processes owned by other users (or already gone) are unreadable.
+        let Ok(exe) = std::fs::read_link(entry.path().join("exe")) else {
+            continue;
+        };
+        if process_matches(&exe, process_names) {
+            let launch_path = appimage_path(pid).unwrap_or(exe);
+            found.push(IdeProcess { pid, launch_path });
+        }
+    }
+
+    found.sort_by_key(|process| process.pid);
+    Ok(found)
+}
+
+#[cfg(target_os = "macos")]
+fn find_processes(process_names: &[String]) -> Result<Vec<IdeProcess>, String> {
+    let output = Command::new("ps")
+        .args(["-axo", "pid=,comm="])
+        .output()
+        .map_err(|e| format!("Failed to list processes: {e}"))?;
+    let own_pid = std::process::id();
+
+    let mut found = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Some((pid, exe)) = line.trim().split_once(' ') else {
+            continue;
+        };
+        let Ok(pid) = pid.parse::<u32>() else {
+            continue;
+        };
+        let exe = PathBuf::from(exe.trim());
+        if pid != own_pid && process_matches(&exe, process_names) {
+            // Relaunch the bundle rather than the inner executable so macOS
+            // restores windows and the Dock entry.
+            let launch_path = exe
+                .ancestors()
+                .find(|path| path.extension().is_some_and(|ext| ext == "app"))
+                .map(PathBuf::from)
+                .unwrap_or(exe);
+            found.push(IdeProcess { pid, launch_path });
+        }
+    }
+
+    found.sort_by_key(|process| process.pid);
+    Ok(found)
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+fn find_processes(_process_names: &[String]) -> Result<Vec<IdeProcess>, String> {
+    Ok(Vec::new())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn process_alive(pid: u32) -> bool {
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn launch_detached(path: &std::path::Path) -> Result<(), String> {
+    use std::process::Stdio;
+
+    let mut command = if cfg!(target_os = "macos") && path.extension().is_some_and(|e| e == "app") {
+        let mut open = Command::new("open");
+        open.arg(path);
+        open
+    } else {
+        Command::new(path)
+    };
+
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("Failed to relaunch {}: {e}", path.display()))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn restart_processes(process_names: &[String]) -> Result<bool, String> {
+    let processes = find_processes(process_names)?;
+    let Some(launch_path) = processes.first().map(|p| p.launch_path.clone()) else {
+        return Ok(false);
+    };
+
+    let pids: Vec<String> = processes.iter().map(|p| p.pid.to_string()).collect();
+    let _ = Command::new("kill").arg("-TERM").args(&pids).status();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while processes.iter().any(|p| process_alive(p.pid)) {
+        if Instant::now() >= deadline {
+            let _ = Command::new("kill").arg("-KILL").args(&pids).status();
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+
+    if !launch_path.exists() {
+        return Err("IDE process path was not found for restart".to_string());
+    }
+    launch_detached(&launch_path)?;
+    Ok(true)
+}
+
+/// Makes the IDE pick up the new auth.json. `Ok(false)` means no running
+/// instance was found.
+pub(crate) fn reload(target: &IdeTarget) -> Result<bool, String> {
+    let triggered = match target.reload {
+        IdeReloadStrategy::ReloadWindow => trigger_reload_command(&target.cli_candidates),
+        IdeReloadStrategy::Restart => Ok(false),
+    };
+
+    match triggered {
+        Ok(true) => Ok(true),
+        Ok(false) => restart_processes(&target.process_names),
+        Err(reload_err) => match restart_processes(&target.process_names) {
+            Ok(result) => Ok(result),
+            Err(restart_err) => Err(format!(
+                "IDE reload failed ({reload_err}) and restart fallback failed ({restart_err})"
+            )),
+        },
+    }
+}
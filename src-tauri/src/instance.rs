@@ -0,0 +1,215 @@
+//! Keeps a single GUI process per user. The first instance listens on a
+//! loopback port; a later launch hands its arguments to it and exits, so only
+//! one process owns the OAuth callback port and writes state from the UI.
+//!
+//! Forwarded messages must carry the token the primary wrote to
+//! `instance.token` in the data directory, so only processes that can read
+//! the user's files are able to drive the app.
+
+use crate::error::AppError;
+use crate::{
+    app_storage_dir, complete_flow_with_callback, lock_callback_origin, lock_data, lock_flows,
+    parse_callback_input, random_urlsafe, resolve_account, storage, switch_account, SharedState,
+    CALLBACK_PATH,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+const INSTANCE_ADDR: &str = "127.0.0.1:1457";
+const TOKEN_FILE: &str = "instance.token";
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(3);
+const MAX_MESSAGE_BYTES: u64 = 64 * 1024;
+const FORWARD_ACK: &str = "ok";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Forwarded {
+    token: String,
+    args: Vec<String>,
+}
+
+pub(crate) enum Instance {
+    /// This process is the first one. `None` when the port is held by
+    /// something that is not another instance; the app then runs unguarded.
+    Primary(Option<(TcpListener, String)>),
+    /// The arguments went to the running instance; this process should exit.
+    Forwarded,
+}
+
+fn token_path() -> Result<std::path::PathBuf, AppError> {
+    Ok(app_storage_dir()?.join(TOKEN_FILE))
+}
+
+fn forward(args: &[String]) -> Result<(), String> {
+    let token = fs::read_to_string(token_path()?)
+        .map_err(|e| format!("Failed to read instance token: {e}"))?;
+    let addr: SocketAddr = INSTANCE_ADDR
+        .parse()
+        .map_err(|e| format!("Invalid instance address: {e}"))?;
+    let mut stream = TcpStream::connect_timeout(&addr, FORWARD_TIMEOUT)
+        .map_err(|e| format!("Failed to reach running instance: {e}"))?;
+    stream
+        .set_read_timeout(Some(FORWARD_TIMEOUT))
+        .map_err(|e| format!("Failed to configure instance socket: {e}"))?;
+
+    let message = serde_json::to_string(&Forwarded {
+        token: token.trim().to_string(),
+        args: args.to_vec(),
+    })
+    .map_err(|e| format!("Failed to encode arguments: {e}"))?;
+    stream
+        .write_all(format!("{message}\n").as_bytes())
+        .map_err(|e| format!("Failed to forward arguments: {e}"))?;
+
+    let mut reply = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut reply)
+        .map_err(|e| format!("Running instance did not answer: {e}"))?;
+    if reply.trim() != FORWARD_ACK {
+        return Err("Running instance rejected the arguments".to_string());
+    }
+    Ok(())
+}
+
+/// Becomes the primary instance, or forwards `args` to the one already
+/// running.
+pub(crate) fn acquire(args: &[String]) -> Instance {
+    match TcpListener::bind(INSTANCE_ADDR) {
+        Ok(listener) => {
+            let token = random_urlsafe(32);
+            let written = token_path().and_then(|path| {
+                storage::write_atomic(&path, token.as_bytes()).map_err(AppError::Storage)
+            });
+            match written {
+                Ok(()) => Instance::Primary(Some((listener, token))),
+                Err(err) => {
+                    log::warn!("Failed to write instance token: {}", err);
+                    Instance::Primary(None)
+                }
+            }
+        }
+        Err(err) if err.kind() == ErrorKind::AddrInUse => match forward(args) {
+            Ok(()) => Instance::Forwarded,
+            Err(err) => {
+                log::warn!("Another process holds {}: {}", INSTANCE_ADDR, err);
+                Instance::Primary(None)
+            }
+        },
+        Err(err) => {
+            log::warn!("Failed to bind {}: {}", INSTANCE_ADDR, err);
+            Instance::Primary(None)
+        }
+    }
+}
+
+fn read_forwarded(stream: &TcpStream, token: &str) -> Result<Vec<String>, String> {
+    stream
+        .set_read_timeout(Some(FORWARD_TIMEOUT))
+        .map_err(|e| format!("Failed to configure instance socket: {e}"))?;
+    let mut line = String::new();
+    BufReader::new(stream.take(MAX_MESSAGE_BYTES))
+        .read_line(&mut line)
+        .map_err(|e| format!("Failed to read forwarded arguments: {e}"))?;
+    let message: Forwarded =
+        serde_json::from_str(&line).map_err(|e| format!("Invalid forwarded arguments: {e}"))?;
+    if message.token != token {
+        return Err("Forwarded arguments carried a wrong token".to_string());
+    }
+    Ok(message.args)
+}
+
+/// Accepts forwarded launches for the lifetime of the app.
+pub(crate) fn serve(listener: TcpListener, token: String, app: AppHandle, state: Arc<SharedState>) {
+    std::thread::spawn(move || {
+        for incoming in listener.incoming() {
+            let mut stream = match incoming {
+                Ok(stream) => stream,
+                Err(err) => {
+                    log::warn!("Instance connection failed: {}", err);
+                    continue;
+                }
+            };
+            match read_forwarded(&stream, &token) {
+                Ok(args) => {
+                    let _ = stream.write_all(format!("{FORWARD_ACK}\n").as_bytes());
+                    drop(stream);
+                    focus_main_window(&app);
+                    handle_args(&state, &args);
+                }
+                Err(err) => log::warn!("{}", err),
+            }
+        }
+    });
+}
+
+fn focus_main_window(app: &AppHandle) {
+    let Some(window) = app.get_webview_window("main") else {
+        return;
+    };
+    let _ = window.unminimize();
+    let _ = window.show();
+    let _ = window.set_focus();
+}
+
+fn complete_callback(state: &Arc<SharedState>, callback_url: &str) -> Result<(), AppError> {
+    // Flows only exist while the listener runs, so its origin completes bare
+    // queries.
+    let origin = lock_callback_origin(state)?
+        .clone()
+        .ok_or(AppError::OAuthFlowNotFound)?;
+    let redirect_uri = format!("{origin}{CALLBACK_PATH}");
+    let (params, _) = parse_callback_input(callback_url, &redirect_uri)?;
+    let callback_state = params
+        .state()
+        .ok_or_else(|| AppError::OAuthCallback("Callback does not contain state".to_string()))?;
+    let flow_id = {
+        let flows = lock_flows(state)?;
+        flows
+            .iter()
+            .find(|(_, flow)| flow.state == callback_state && !flow.is_finished())
+            .map(|(id, _)| id.clone())
+            .ok_or(AppError::OAuthFlowNotFound)?
+    };
+    complete_flow_with_callback(state, &flow_id, callback_url)
+}
+
+fn switch_to(state: &Arc<SharedState>, needle: &str) -> Result<(), AppError> {
+    let account_id = {
+        let data = lock_data(state)?;
+        resolve_account(&data, needle)?
+    };
+    let response = switch_account(state, account_id, None)?;
+    if let Some(warning) = response.warning {
+        log::info!("{}", warning);
+    }
+    Ok(())
+}
+
+/// Acts on launch arguments: `--switch <email|id>` makes an account active
+/// and an OAuth callback URL completes the login it belongs to. Anything else
+/// is ignored; the window has already been focused.
+pub(crate) fn handle_args(state: &Arc<SharedState>, args: &[String]) {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let result = if arg == "--switch" {
+            match args.next() {
+                Some(needle) => switch_to(state, needle),
+                None => Err(AppError::InvalidInput("--switch needs a value".to_string())),
+            }
+        } else if arg.contains("/auth/callback")
+            || (arg.contains("code=") && arg.contains("state="))
+        {
+            complete_callback(state, arg)
+        } else {
+            continue;
+        };
+        // Callback URLs carry the authorization code, so only the error is logged.
+        if let Err(err) = result {
+            log::warn!("Failed to handle launch argument: {}", err);
+        }
+    }
+}
@@ -1,34 +1,69 @@
 use base64::engine::general_purpose::URL_SAFE_NO_PAD;
 use base64::Engine;
-use chrono::Utc;
+use chrono::{DateTime, TimeZone, Utc};
+use error::AppError;
+use health::{AccountHealth, AccountStatus};
+use http::{HttpError, HttpErrorKind};
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
 use std::io::{Read, Write};
 use std::net::{TcpListener, TcpStream, ToSocketAddrs};
 use std::path::PathBuf;
-#[cfg(target_os = "windows")]
-use std::os::windows::process::CommandExt;
-#[cfg(target_os = "windows")]
-use std::process::{Command, Output};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
 use std::time::{Duration, Instant};
-use tauri::State;
+use tauri::{AppHandle, State};
 use url::Url;
 use uuid::Uuid;
+use view::{AccountView, AppDataView};
+use zeroize::Zeroize;
+
+mod auth_watcher;
+pub mod cli;
+mod error;
+mod events;
+mod forecast;
+mod health;
+mod history;
+mod http;
+mod ide;
+mod instance;
+mod migrate;
+mod relay;
+mod rotation;
+mod scheduler;
+mod state_watcher;
+mod storage;
+mod vault;
+mod view;
 
 const OAUTH_CLIENT_ID: &str = "app_EMoamEEZ73f0CkXaXp7hrann";
 const OAUTH_ISSUER: &str = "https://auth.openai.com";
 const OAUTH_SCOPE: &str = "openid profile email offline_access";
-const OAUTH_REDIRECT_URI: &str = "http://localhost:1455/auth/callback";
 const OAUTH_ORIGINATOR: &str = "codex_cli_rs";
-const CALLBACK_ADDR: &str = "127.0.0.1:1455";
-#[cfg(target_os = "windows")]
-const CREATE_NO_WINDOW: u32 = 0x08000000;
+const CALLBACK_PATH: &str = "/auth/callback";
+/// The callback receives authorization codes, so it never listens beyond
+/// loopback whatever host the redirect URI names.
+const CALLBACK_BIND_HOST: &str = "127.0.0.1";
+/// A login that has not received its callback by then is abandoned.
+const OAUTH_FLOW_TTL_SECS: i64 = 15 * 60;
+/// Finished flows stay readable this long so the UI and CLI see the outcome.
+const OAUTH_FLOW_RETENTION_SECS: i64 = 2 * 60;
+const OAUTH_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+const CALLBACK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const TOKEN_REFRESH_SKEW_SECS: i64 = 300;
+const OAUTH_REQUEST_TIMEOUT: Duration = Duration::from_secs(45);
+const QUOTA_REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+/// Quota fetches running at once during a bulk refresh.
+const QUOTA_REFRESH_WORKERS: usize = 6;
+/// How long a bulk refresh waits before returning whatever has finished.
+const QUOTA_REFRESH_DEADLINE: Duration = Duration::from_secs(60);
+/// Authentication failures in a row before quota checks for an account stop.
+const AUTH_BREAKER_THRESHOLD: u32 = 3;
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct Tokens {
@@ -65,7 +100,15 @@ pub struct Account {
     pub quota: Option<QuotaInfo>,
     pub created_at: i64,
     pub last_login_at: i64,
+    /// Message for the most recent failure; `health` classifies it.
     pub last_error: Option<String>,
+    #[serde(default)]
+    pub last_refresh_at: Option<i64>,
+    /// Consecutive 401/403 responses; see [`fetch_quota_with_refresh`].
+    #[serde(default)]
+    pub auth_failures: u32,
+    #[serde(default)]
+    pub health: AccountHealth,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -82,27 +125,175 @@ pub struct ProxyEntry {
     pub last_checked_at: Option<i64>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RotationPolicy {
+    #[default]
+    MostRemaining,
+    SoonestReset,
+    RoundRobin,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RotationSettings {
+    pub enabled: bool,
+    pub policy: RotationPolicy,
+    /// Switch away once the 5-hour window has less than this much left (percent).
+    pub primary_threshold: f64,
+    /// Switch away once the weekly window has less than this much left (percent).
+    pub secondary_threshold: f64,
+}
+
+impl Default for RotationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            policy: RotationPolicy::MostRemaining,
+            primary_threshold: 10.0,
+            secondary_threshold: 5.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RelaySettings {
+    pub enabled: bool,
+    /// Loopback port Codex is pointed at.
+    pub port: u16,
+    /// Where relayed requests are forwarded; the request path is appended.
+    pub upstream_url: String,
+}
+
+impl Default for RelaySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 1456,
+            upstream_url: "https://chatgpt.com/backend-api/codex".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OAuthCallbackSettings {
+    /// Host in the redirect URI. The listener itself always binds
+    /// 127.0.0.1, so the host has to resolve to it.
+    pub host: String,
+    pub port: u16,
+    /// Tried in order when `port` is taken, e.g. by a Codex CLI login. The
+    /// provider has to accept the redirect URI built from them.
+    pub fallback_ports: Vec<u16>,
+}
+
+impl Default for OAuthCallbackSettings {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 1455,
+            fallback_ports: Vec::new(),
+        }
+    }
+}
+
+impl OAuthCallbackSettings {
+    fn ports(&self) -> impl Iterator<Item = u16> + '_ {
+        std::iter::once(self.port).chain(self.fallback_ports.iter().copied())
+    }
+
+    fn origin(&self, port: u16) -> String {
+        format!("http://{}:{port}", self.host)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct QuotaRefreshSettings {
+    pub enabled: bool,
+    pub interval_minutes: u32,
+    /// Each scheduled refresh is moved by up to this many seconds either way.
+    pub jitter_seconds: u32,
+}
+
+impl Default for QuotaRefreshSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_minutes: 5,
+            jitter_seconds: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RotationEvent {
+    pub at: i64,
+    pub from_account_id: Option<String>,
+    pub to_account_id: String,
+    pub policy: RotationPolicy,
+    pub reason: String,
+    pub ide: Option<String>,
+    pub reloaded: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AppData {
+    /// See [`migrate`]; files without it predate versioning.
+    #[serde(default)]
+    pub schema_version: u32,
     pub accounts: Vec<Account>,
     pub active_account_id: Option<String>,
     pub proxies: Vec<ProxyEntry>,
     pub active_proxy_id: Option<String>,
     pub limits_base_url: String,
+    /// Bumped by every save so writers can tell when the file changed under
+    /// them.
+    #[serde(default)]
+    pub revision: u64,
     #[serde(default)]
     pub preferred_ide: Option<String>,
+    #[serde(default)]
+    pub rotation: RotationSettings,
+    #[serde(default)]
+    pub rotation_log: Vec<RotationEvent>,
+    #[serde(default = "default_vault_auto_lock_minutes")]
+    pub vault_auto_lock_minutes: u32,
+    #[serde(default)]
+    pub custom_ides: Vec<ide::IdeTarget>,
+    #[serde(default)]
+    pub relay: RelaySettings,
+    #[serde(default)]
+    pub quota_refresh: QuotaRefreshSettings,
+    #[serde(default)]
+    pub oauth_callback: OAuthCallbackSettings,
+}
+
+fn default_vault_auto_lock_minutes() -> u32 {
+    15
 }
 
 impl Default for AppData {
     fn default() -> Self {
         Self {
+            schema_version: migrate::CURRENT_SCHEMA_VERSION,
             accounts: Vec::new(),
             active_account_id: None,
             proxies: Vec::new(),
             active_proxy_id: None,
             limits_base_url: "https://chatgpt.com/backend-api".to_string(),
+            revision: 0,
             preferred_ide: None,
+            rotation: RotationSettings::default(),
+            rotation_log: Vec::new(),
+            vault_auto_lock_minutes: default_vault_auto_lock_minutes(),
+            custom_ides: Vec::new(),
+            relay: RelaySettings::default(),
+            quota_refresh: QuotaRefreshSettings::default(),
+            oauth_callback: OAuthCallbackSettings::default(),
         }
     }
 }
@@ -113,6 +304,8 @@ enum OauthFlowStatus {
     Exchanging,
     Completed,
     Error(String),
+    Expired,
+    Cancelled,
 }
 
 #[derive(Debug, Clone)]
@@ -121,10 +314,28 @@ struct OauthFlow {
     state: String,
     code_verifier: String,
     created_at: i64,
+    /// Sent with the authorize request and again with the code exchange.
+    redirect_uri: String,
     authorization_url: String,
     callback_url: Option<String>,
     result_account_id: Option<String>,
     status: OauthFlowStatus,
+    /// Set once the flow can no longer complete; see [`OauthFlow::finish`].
+    finished_at: Option<i64>,
+}
+
+impl OauthFlow {
+    fn is_finished(&self) -> bool {
+        self.finished_at.is_some()
+    }
+
+    /// Moves the flow to a final status and wipes the PKCE verifier, which is
+    /// not needed any more.
+    fn finish(&mut self, status: OauthFlowStatus) {
+        self.status = status;
+        self.code_verifier.zeroize();
+        self.finished_at = Some(now_ts());
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -144,7 +355,7 @@ struct OauthFlowResponse {
     created_at: i64,
     status: String,
     error: Option<String>,
-    account: Option<Account>,
+    account: Option<AccountView>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -157,10 +368,29 @@ struct ProxyTestResult {
     error: Option<String>,
 }
 
+/// Outcome of a bulk quota refresh, by account id.
+#[derive(Debug, Clone, Default)]
+struct QuotaRefreshReport {
+    refreshed: Vec<String>,
+    failed: Vec<String>,
+    /// Still running when the deadline passed, or already being refreshed by
+    /// another caller. Their results arrive as `quota-updated` events.
+    pending: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RefreshAllResponse {
+    state: AppDataView,
+    refreshed_account_ids: Vec<String>,
+    failed_account_ids: Vec<String>,
+    pending_account_ids: Vec<String>,
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct SwitchAccountResponse {
-    state: AppData,
+    state: AppDataView,
     ide: Option<String>,
     reloaded: bool,
     warning: Option<String>,
@@ -177,7 +407,15 @@ struct ParsedProxy {
 struct SharedState {
     data: Mutex<AppData>,
     flows: Mutex<HashMap<String, OauthFlow>>,
-    callback_server_started: AtomicBool,
+    /// Origin of the running OAuth callback listener, e.g.
+    /// `http://localhost:1455`.
+    callback_origin: Mutex<Option<String>>,
+    relay: Mutex<relay::RelayRuntime>,
+    scheduler: scheduler::Scheduler,
+    /// Accounts with a quota fetch running on a refresh worker.
+    quota_in_flight: Mutex<HashSet<String>>,
+    /// Set once the Tauri app is running; see [`events::attach`].
+    app: OnceLock<AppHandle>,
 }
 
 impl SharedState {
@@ -185,7 +423,11 @@ impl SharedState {
         Self {
             data: Mutex::new(initial),
             flows: Mutex::new(HashMap::new()),
-            callback_server_started: AtomicBool::new(false),
+            callback_origin: Mutex::new(None),
+            relay: Mutex::new(relay::RelayRuntime::default()),
+            scheduler: scheduler::Scheduler::default(),
+            quota_in_flight: Mutex::new(HashSet::new()),
+            app: OnceLock::new(),
         }
     }
 }
@@ -194,54 +436,70 @@ fn now_ts() -> i64 {
     Utc::now().timestamp()
 }
 
-fn app_storage_dir() -> Result<PathBuf, String> {
+fn app_storage_dir() -> Result<PathBuf, AppError> {
     let base = dirs::data_local_dir()
         .or_else(dirs::home_dir)
-        .ok_or_else(|| "Cannot determine data directory".to_string())?;
+        .ok_or_else(|| AppError::Storage("Cannot determine data directory".to_string()))?;
     let dir = base.join("CodexAccountManager");
-    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create data directory: {e}"))?;
+    fs::create_dir_all(&dir)
+        .map_err(|e| AppError::Storage(format!("Failed to create data directory: {e}")))?;
     Ok(dir)
 }
 
-fn app_storage_file() -> Result<PathBuf, String> {
+fn app_storage_file() -> Result<PathBuf, AppError> {
     Ok(app_storage_dir()?.join("state.json"))
 }
 
-fn load_app_data() -> Result<AppData, String> {
+fn load_app_data() -> Result<AppData, AppError> {
     let path = app_storage_file()?;
     if !path.exists() {
         return Ok(AppData::default());
     }
-    let text = fs::read_to_string(&path).map_err(|e| format!("Failed to read state file: {e}"))?;
-    let parsed: AppData =
-        serde_json::from_str(&text).map_err(|e| format!("Failed to parse state file: {e}"))?;
-    Ok(parsed)
+    let text = fs::read_to_string(&path)
+        .map_err(|e| AppError::Storage(format!("Failed to read state file: {e}")))?;
+    let text = vault::decode_state_text(text).map_err(AppError::vault)?;
+    parse_app_data(&text)
 }
 
-fn save_app_data(data: &AppData) -> Result<(), String> {
-    let path = app_storage_file()?;
-    let text = serde_json::to_string_pretty(data)
-        .map_err(|e| format!("Failed to serialize state: {e}"))?;
-    fs::write(&path, text).map_err(|e| format!("Failed to write state file: {e}"))?;
-    Ok(())
+fn parse_app_data(text: &str) -> Result<AppData, AppError> {
+    let value: Value = serde_json::from_str(text)
+        .map_err(|e| AppError::Parse(format!("Failed to parse state file: {e}")))?;
+    serde_json::from_value(migrate::migrate(value)?)
+        .map_err(|e| AppError::Parse(format!("Failed to parse state file: {e}")))
+}
+
+/// Persists `data`, first merging in anything another process saved since it
+/// was loaded; `data` holds the merged state afterwards.
+fn save_app_data(data: &mut AppData) -> Result<(), AppError> {
+    storage::save(data)
 }
 
 fn lock_data<'a>(
     state: &'a Arc<SharedState>,
-) -> Result<std::sync::MutexGuard<'a, AppData>, String> {
+) -> Result<std::sync::MutexGuard<'a, AppData>, AppError> {
+    vault::ensure_unlocked().map_err(AppError::vault)?;
     state
         .data
         .lock()
-        .map_err(|_| "State lock poisoned (data)".to_string())
+        .map_err(|_| AppError::Internal("State lock poisoned (data)".to_string()))
 }
 
 fn lock_flows<'a>(
     state: &'a Arc<SharedState>,
-) -> Result<std::sync::MutexGuard<'a, HashMap<String, OauthFlow>>, String> {
+) -> Result<std::sync::MutexGuard<'a, HashMap<String, OauthFlow>>, AppError> {
     state
         .flows
         .lock()
-        .map_err(|_| "State lock poisoned (oauth flows)".to_string())
+        .map_err(|_| AppError::Internal("State lock poisoned (oauth flows)".to_string()))
+}
+
+fn lock_quota_in_flight<'a>(
+    state: &'a Arc<SharedState>,
+) -> Result<std::sync::MutexGuard<'a, HashSet<String>>, AppError> {
+    state
+        .quota_in_flight
+        .lock()
+        .map_err(|_| AppError::Internal("State lock poisoned (quota refresh)".to_string()))
 }
 
 fn random_urlsafe(byte_len: usize) -> String {
@@ -256,14 +514,18 @@ fn build_pkce() -> (String, String) {
     (verifier, challenge)
 }
 
-fn build_authorize_url(state: &str, challenge: &str) -> Result<String, String> {
+fn build_authorize_url(
+    state: &str,
+    challenge: &str,
+    redirect_uri: &str,
+) -> Result<String, AppError> {
     let mut url = Url::parse(&format!("{OAUTH_ISSUER}/oauth/authorize"))
-        .map_err(|e| format!("Failed to build OAuth URL: {e}"))?;
+        .map_err(|e| AppError::Internal(format!("Failed to build OAuth URL: {e}")))?;
 
     url.query_pairs_mut()
         .append_pair("response_type", "code")
         .append_pair("client_id", OAUTH_CLIENT_ID)
-        .append_pair("redirect_uri", OAUTH_REDIRECT_URI)
+        .append_pair("redirect_uri", redirect_uri)
         .append_pair("scope", OAUTH_SCOPE)
         .append_pair("state", state)
         .append_pair("code_challenge", challenge)
@@ -274,7 +536,7 @@ fn build_authorize_url(state: &str, challenge: &str) -> Result<String, String> {
 
     Ok(url.to_string())
 }
-fn parse_proxy_input(raw: &str) -> Result<ParsedProxy, String> {
+fn parse_proxy_input(raw: &str) -> Result<ParsedProxy, AppError> {
     let mut text = raw.trim().to_string();
     if text.starts_with("http://") {
         text = text.trim_start_matches("http://").to_string();
@@ -285,13 +547,13 @@ fn parse_proxy_input(raw: &str) -> Result<ParsedProxy, String> {
 
     let (credentials, host_part) = text
         .split_once('@')
-        .ok_or_else(|| "Proxy must be in login:pass@ip:port format".to_string())?;
+        .ok_or_else(|| AppError::Proxy("Proxy must be in login:pass@ip:port format".to_string()))?;
     let (login, password) = credentials
         .split_once(':')
-        .ok_or_else(|| "Proxy must include login and password".to_string())?;
+        .ok_or_else(|| AppError::Proxy("Proxy must include login and password".to_string()))?;
     let (host, port_text) = host_part
         .rsplit_once(':')
-        .ok_or_else(|| "Proxy must include ip and port".to_string())?;
+        .ok_or_else(|| AppError::Proxy("Proxy must include ip and port".to_string()))?;
 
     let login = login.trim();
     let password = password.trim();
@@ -299,10 +561,10 @@ fn parse_proxy_input(raw: &str) -> Result<ParsedProxy, String> {
     let port: u16 = port_text
         .trim()
         .parse()
-        .map_err(|_| "Proxy port must be a valid number".to_string())?;
+        .map_err(|_| AppError::Proxy("Proxy port must be a valid number".to_string()))?;
 
     if login.is_empty() || password.is_empty() || host.is_empty() {
-        return Err("Proxy fields cannot be empty".to_string());
+        return Err(AppError::Proxy("Proxy fields cannot be empty".to_string()));
     }
 
     Ok(ParsedProxy {
@@ -328,190 +590,6 @@ fn active_proxy(data: &AppData) -> Option<ProxyEntry> {
     data.proxies.iter().find(|p| &p.id == active_id).cloned()
 }
 
-fn normalize_ide_target(input: &str) -> Option<String> {
-    match input.trim().to_ascii_lowercase().as_str() {
-        "vscode" | "code" => Some("vscode".to_string()),
-        "cursor" => Some("cursor".to_string()),
-        "windsurf" => Some("windsurf".to_string()),
-        "trae" => Some("trae".to_string()),
-        "vscodium" | "codium" => Some("vscodium".to_string()),
-        "zed" => Some("zed".to_string()),
-        _ => None,
-    }
-}
-
-#[cfg(target_os = "windows")]
-fn run_hidden_powershell(script: &str) -> Result<Output, String> {
-    Command::new("powershell")
-        .args([
-            "-NoProfile",
-            "-ExecutionPolicy",
-            "Bypass",
-            "-WindowStyle",
-            "Hidden",
-            "-Command",
-            script,
-        ])
-        .creation_flags(CREATE_NO_WINDOW)
-        .output()
-        .map_err(|e| format!("Failed to execute PowerShell command: {e}"))
-}
-
-#[cfg(target_os = "windows")]
-fn trigger_ide_reload_command(ide: &str) -> Result<bool, String> {
-    let cli_candidates: &[&str] = match ide {
-        "vscode" => &["code", "code-insiders"],
-        "cursor" => &["cursor"],
-        "windsurf" => &["windsurf"],
-        "trae" => &["trae"],
-        "vscodium" => &["codium"],
-        "zed" => &["zed"],
-        _ => return Err("Unsupported IDE target".to_string()),
-    };
-
-    let quoted = cli_candidates
-        .iter()
-        .map(|name| format!("'{}'", name))
-        .collect::<Vec<_>>()
-        .join(",");
-
-    let script = format!(
-        "$ErrorActionPreference='SilentlyContinue'; \
-$cmds=@({quoted}); \
-$ok=$false; \
-foreach ($cmd in $cmds) {{ \
-  if (Get-Command $cmd -ErrorAction SilentlyContinue) {{ \
-    & $cmd --reuse-window --command workbench.action.reloadWindow | Out-Null; \
-    $ok=$true; \
-    break; \
-  }} \
-}}; \
-if ($ok) {{ exit 0 }} else {{ exit 2 }}"
-    );
-
-    let output = run_hidden_powershell(&script)?;
-    match output.status.code() {
-        Some(0) => Ok(true),
-        Some(2) => Ok(false),
-        _ => {
-            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
-            if stderr.is_empty() {
-                Err("IDE reload command failed".to_string())
-            } else {
-                Err(format!("IDE reload command failed: {stderr}"))
-            }
-        }
-    }
-}
-
-#[cfg(not(target_os = "windows"))]
-fn trigger_ide_reload_command(_ide: &str) -> Result<bool, String> {
-    Ok(false)
-}
-
-#[cfg(target_os = "windows")]
-fn restart_ide_processes(process_names: &[&str]) -> Result<bool, String> {
-    let quoted = process_names
-        .iter()
-        .map(|name| format!("'{}'", name))
-        .collect::<Vec<_>>()
-        .join(",");
-
-    let script = format!(
-        "$ErrorActionPreference='SilentlyContinue'; \
-$names=@({quoted}); \
-$all=@(); \
-foreach ($name in $names) {{ \
-  $all += Get-Process -Name $name -ErrorAction SilentlyContinue; \
-}}; \
-if (-not $all -or $all.Count -eq 0) {{ exit 2 }}; \
-$launchPath=$null; \
-$main=$all | Where-Object {{ $_.MainWindowHandle -ne 0 -and $_.Path }} | Select-Object -First 1; \
-if ($main) {{ $launchPath=$main.Path }} else {{ \
-  $any=$all | Where-Object {{ $_.Path }} | Select-Object -First 1; \
-  if ($any) {{ $launchPath=$any.Path }}; \
-}}; \
-foreach ($p in $all) {{ Stop-Process -Id $p.Id -Force -ErrorAction SilentlyContinue }}; \
-if ($launchPath) {{ \
-  Start-Process -WindowStyle Hidden -FilePath $launchPath | Out-Null; \
-  exit 0 \
-}} else {{ \
-  exit 3 \
-}}"
-    );
-
-    let output = run_hidden_powershell(&script)?;
-    match output.status.code() {
-        Some(0) => Ok(true),
-        Some(2) => Ok(false),
-        Some(3) => Err("IDE process path was not found for restart".to_string()),
-        _ => {
-            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
-            if stderr.is_empty() {
-                Err("IDE process restart command failed".to_string())
-            } else {
-                Err(format!("IDE process restart command failed: {stderr}"))
-            }
-        }
-    }
-}
-
-#[cfg(not(target_os = "windows"))]
-fn restart_ide_processes(_process_names: &[&str]) -> Result<bool, String> {
-    Ok(false)
-}
-fn reload_ide_windows(ide: &str) -> Result<bool, String> {
-    match trigger_ide_reload_command(ide) {
-        Ok(true) => Ok(true),
-        Ok(false) => {
-            let process_names: &[&str] = match ide {
-                "vscode" => &["Code", "Code - Insiders"],
-                "cursor" => &["Cursor"],
-                "windsurf" => &["Windsurf"],
-                "trae" => &["Trae"],
-                "vscodium" => &["VSCodium"],
-                "zed" => &["Zed"],
-                _ => return Err("Unsupported IDE target".to_string()),
-            };
-            restart_ide_processes(process_names)
-        }
-        Err(reload_err) => {
-            let process_names: &[&str] = match ide {
-                "vscode" => &["Code", "Code - Insiders"],
-                "cursor" => &["Cursor"],
-                "windsurf" => &["Windsurf"],
-                "trae" => &["Trae"],
-                "vscodium" => &["VSCodium"],
-                "zed" => &["Zed"],
-                _ => return Err("Unsupported IDE target".to_string()),
-            };
-
-            match restart_ide_processes(process_names) {
-                Ok(result) => Ok(result),
-                Err(restart_err) => Err(format!(
-                    "IDE reload failed ({reload_err}) and restart fallback failed ({restart_err})"
-                )),
-            }
-        }
-    }
-}
-fn build_http_client(
-    timeout: Duration,
-    proxy: Option<&ProxyEntry>,
-) -> Result<reqwest::blocking::Client, String> {
-    let mut builder = reqwest::blocking::Client::builder().timeout(timeout);
-    if let Some(proxy_entry) = proxy {
-        let proxy_url = proxy_to_url(proxy_entry)?;
-        let proxy_cfg =
-            reqwest::Proxy::all(proxy_url).map_err(|e| format!("Invalid proxy: {e}"))?;
-        builder = builder.proxy(proxy_cfg);
-    }
-
-    builder
-        .build()
-        .map_err(|e| format!("Failed to create HTTP client: {e}"))
-}
-
 fn parse_quota_window(win: Option<&Value>) -> QuotaWindow {
     let Some(win) = win else {
         return QuotaWindow::default();
@@ -530,9 +608,9 @@ fn fetch_quota(
     tokens: &Tokens,
     account_id: Option<&str>,
     proxy: Option<&ProxyEntry>,
-) -> Result<QuotaInfo, String> {
+) -> Result<QuotaInfo, HttpError> {
     if tokens.access_token.trim().is_empty() {
-        return Err("Missing access_token".to_string());
+        return Err(HttpError::new(HttpErrorKind::Auth, "Missing access_token"));
     }
 
     let base = base_url.trim_end_matches('/');
@@ -542,37 +620,24 @@ fn fetch_quota(
         format!("{base}/api/codex/usage")
     };
 
-    let client = build_http_client(Duration::from_secs(30), proxy)?;
-
-    let mut request = client
-        .get(endpoint)
-        .header("Accept", "application/json")
-        .header("Authorization", format!("Bearer {}", tokens.access_token))
-        .header("User-Agent", "codex-cli");
-
-    if let Some(account_id) = account_id {
-        if !account_id.trim().is_empty() {
+    let client = http::shared_client(proxy).map_err(|e| HttpError::new(HttpErrorKind::Other, e))?;
+    let account_id = account_id.filter(|id| !id.trim().is_empty());
+
+    let body = http::send(&http::READ_POLICY, "Quota request", || {
+        let mut request = client
+            .get(&endpoint)
+            .timeout(QUOTA_REQUEST_TIMEOUT)
+            .header("Accept", "application/json")
+            .header("Authorization", format!("Bearer {}", tokens.access_token))
+            .header("User-Agent", "codex-cli");
+        if let Some(account_id) = account_id {
             request = request.header("ChatGPT-Account-Id", account_id);
         }
-    }
-
-    let response = request
-        .send()
-        .map_err(|e| format!("Quota request failed: {e}"))?;
-    let status = response.status();
-    let body = response
-        .text()
-        .map_err(|e| format!("Failed to read quota response: {e}"))?;
+        request
+    })?;
 
-    if !status.is_success() {
-        return Err(format!(
-            "Quota request failed ({status}): {}",
-            body.chars().take(240).collect::<String>()
-        ));
-    }
-
-    let payload: Value =
-        serde_json::from_str(&body).map_err(|e| format!("Invalid quota payload: {e}"))?;
+    let payload: Value = serde_json::from_str(&body)
+        .map_err(|e| HttpError::new(HttpErrorKind::Other, format!("Invalid quota payload: {e}")))?;
 
     let rate_limit = payload.get("rate_limit");
     let primary = parse_quota_window(rate_limit.and_then(|v| v.get("primary_window")));
@@ -589,6 +654,113 @@ fn fetch_quota(
     })
 }
 
+/// How a quota fetch made on an account snapshot ended.
+struct QuotaFetch {
+    result: Result<QuotaInfo, HttpError>,
+    /// The auth circuit breaker was open, so upstream was not contacted.
+    paused: bool,
+    refresh_failed: bool,
+}
+
+/// Whether the auth circuit breaker keeps the account from being contacted.
+fn auth_paused(account: &Account) -> bool {
+    account.auth_failures >= AUTH_BREAKER_THRESHOLD
+}
+
+/// Fetches quota for an account snapshot, renewing its tokens once if the
+/// usage endpoint rejects the access token. Rotated tokens are left on
+/// `account`; the auth failure count and health are applied to the stored
+/// account afterwards by [`record_quota_outcome`].
+///
+/// After [`AUTH_BREAKER_THRESHOLD`] authentication failures in a row the
+/// account is not contacted again until it is signed in anew.
+fn fetch_quota_with_refresh(
+    base_url: &str,
+    account: &mut Account,
+    proxy: Option<&ProxyEntry>,
+) -> QuotaFetch {
+    if auth_paused(account) {
+        return QuotaFetch {
+            result: Err(HttpError::new(
+                HttpErrorKind::Auth,
+                format!(
+                    "Quota checks paused after {} authentication failures in a row. Sign in again to resume.",
+                    account.auth_failures
+                ),
+            )),
+            paused: true,
+            refresh_failed: false,
+        };
+    }
+
+    let mut refresh_failed = false;
+    let result = match fetch_quota(
+        base_url,
+        &account.tokens,
+        account.account_id.as_deref(),
+        proxy,
+    ) {
+        Err(err)
+            if err.kind == HttpErrorKind::Auth
+                && !account.tokens.refresh_token.trim().is_empty() =>
+        {
+            match refresh_tokens(account, proxy) {
+                Ok(()) => fetch_quota(
+                    base_url,
+                    &account.tokens,
+                    account.account_id.as_deref(),
+                    proxy,
+                ),
+                Err(refresh_err) => {
+                    refresh_failed = true;
+                    Err(HttpError {
+                        message: format!("{err}; token refresh failed: {refresh_err}"),
+                        ..refresh_err
+                    })
+                }
+            }
+        }
+        other => other,
+    };
+
+    QuotaFetch {
+        result,
+        paused: false,
+        refresh_failed,
+    }
+}
+
+/// Updates the auth failure count and health of the stored account from the
+/// outcome of a fetch. The outcome is applied as a change rather than copied
+/// from the snapshot, so a re-login or breaker reset made meanwhile survives.
+fn record_quota_outcome(account: &mut Account, fetch: &QuotaFetch) {
+    match &fetch.result {
+        Ok(_) => {
+            account.auth_failures = 0;
+            account.health.mark_healthy();
+        }
+        Err(_) if fetch.paused => {
+            if account.auth_failures >= AUTH_BREAKER_THRESHOLD
+                && account.health.status != AccountStatus::Deactivated
+            {
+                account
+                    .health
+                    .set(AccountStatus::NeedsReauth, account.health.http_status);
+            }
+        }
+        Err(err) => {
+            if err.kind == HttpErrorKind::Auth {
+                account.auth_failures += 1;
+            }
+            if fetch.refresh_failed {
+                account.health.mark_refresh_error(err);
+            } else {
+                account.health.mark_error(err);
+            }
+        }
+    }
+}
+
 fn decode_jwt_payload(token: &str) -> Option<Value> {
     let payload = token.split('.').nth(1)?;
     let mut padded = payload.to_string();
@@ -633,46 +805,41 @@ fn extract_email(id_token: &str) -> Option<String> {
 fn exchange_code_for_tokens(
     code: &str,
     code_verifier: &str,
+    redirect_uri: &str,
     proxy: Option<&ProxyEntry>,
-) -> Result<Tokens, String> {
-    let client = build_http_client(Duration::from_secs(45), proxy)
-        .map_err(|e| format!("Failed to create OAuth client: {e}"))?;
+) -> Result<Tokens, AppError> {
+    let client = http::shared_client(proxy)
+        .map_err(|e| AppError::Proxy(format!("Failed to create OAuth client: {e}")))?;
 
     let form = [
         ("grant_type", "authorization_code"),
         ("client_id", OAUTH_CLIENT_ID),
         ("code", code),
         ("code_verifier", code_verifier),
-        ("redirect_uri", OAUTH_REDIRECT_URI),
+        ("redirect_uri", redirect_uri),
     ];
 
-    let response = client
-        .post(format!("{OAUTH_ISSUER}/oauth/token"))
-        .header("Accept", "application/json")
-        .header("User-Agent", "codex-cli")
-        .form(&form)
-        .send()
-        .map_err(|e| format!("OAuth token request failed: {e}"))?;
-
-    let status = response.status();
-    let body = response
-        .text()
-        .map_err(|e| format!("Failed to read OAuth response: {e}"))?;
-
-    if !status.is_success() {
-        return Err(format!(
-            "OAuth exchange failed ({status}): {}",
-            body.chars().take(240).collect::<String>()
-        ));
-    }
+    let body = http::send(&http::GRANT_POLICY, "OAuth exchange", || {
+        client
+            .post(format!("{OAUTH_ISSUER}/oauth/token"))
+            .timeout(OAUTH_REQUEST_TIMEOUT)
+            .header("Accept", "application/json")
+            .header("User-Agent", "codex-cli")
+            .form(&form)
+    })
+    .map_err(AppError::oauth_exchange)?;
 
-    let payload: Value =
-        serde_json::from_str(&body).map_err(|e| format!("Invalid OAuth payload: {e}"))?;
+    let invalid_payload = |message: String| AppError::OAuthExchange {
+        message,
+        status: None,
+    };
+    let payload: Value = serde_json::from_str(&body)
+        .map_err(|e| invalid_payload(format!("Invalid OAuth payload: {e}")))?;
 
     let access_token = payload
         .get("access_token")
         .and_then(Value::as_str)
-        .ok_or_else(|| "OAuth payload missing access_token".to_string())?
+        .ok_or_else(|| invalid_payload("OAuth payload missing access_token".to_string()))?
         .to_string();
 
     Ok(Tokens {
@@ -690,127 +857,567 @@ fn exchange_code_for_tokens(
     })
 }
 
-fn codex_auth_path() -> Result<PathBuf, String> {
-    let home =
-        dirs::home_dir().ok_or_else(|| "Cannot determine user home directory".to_string())?;
-    let codex_dir = home.join(".codex");
-    fs::create_dir_all(&codex_dir)
-        .map_err(|e| format!("Failed to create .codex directory: {e}"))?;
-    Ok(codex_dir.join("auth.json"))
+fn token_expires_at(token: &str) -> Option<i64> {
+    decode_jwt_payload(token)?
+        .get("exp")
+        .and_then(Value::as_i64)
 }
 
-fn write_codex_auth(tokens: &Tokens, account_id: Option<&str>) -> Result<(), String> {
-    let path = codex_auth_path()?;
-    let data = json!({
-        "OPENAI_API_KEY": Value::Null,
-        "tokens": {
-            "id_token": tokens.id_token,
-            "access_token": tokens.access_token,
-            "refresh_token": tokens.refresh_token,
-            "account_id": account_id,
-        },
-        "last_refresh": Utc::now().to_rfc3339(),
-    });
-
-    let text = serde_json::to_string_pretty(&data)
-        .map_err(|e| format!("Failed to serialize auth.json: {e}"))?;
-    fs::write(path, text).map_err(|e| format!("Failed to write auth.json: {e}"))?;
-    Ok(())
+fn tokens_need_refresh(tokens: &Tokens) -> bool {
+    match token_expires_at(&tokens.access_token) {
+        Some(expires_at) => expires_at - TOKEN_REFRESH_SKEW_SECS <= now_ts(),
+        None => tokens.access_token.trim().is_empty(),
+    }
 }
-fn upsert_account(
-    data: &mut AppData,
-    tokens: Tokens,
-    email: Option<String>,
-    account_id: Option<String>,
-) -> Account {
-    let now = now_ts();
 
-    let account = Account {
-        id: Uuid::new_v4().to_string(),
-        email,
-        account_id,
-        tokens,
-        quota: None,
-        created_at: now,
-        last_login_at: now,
-        last_error: None,
-    };
+fn refresh_tokens(account: &mut Account, proxy: Option<&ProxyEntry>) -> Result<(), HttpError> {
+    let refresh_token = account.tokens.refresh_token.trim().to_string();
+    if refresh_token.is_empty() {
+        return Err(HttpError::new(HttpErrorKind::Auth, "Missing refresh_token"));
+    }
 
-    data.accounts.push(account.clone());
+    let client = http::shared_client(proxy).map_err(|e| {
+        HttpError::new(
+            HttpErrorKind::Other,
+            format!("Failed to create OAuth client: {e}"),
+        )
+    })?;
 
-    if data.active_account_id.is_none() {
-        data.active_account_id = Some(account.id.clone());
+    let form = [
+        ("grant_type", "refresh_token"),
+        ("client_id", OAUTH_CLIENT_ID),
+        ("refresh_token", refresh_token.as_str()),
+        ("scope", OAUTH_SCOPE),
+    ];
+
+    let body = http::send(&http::GRANT_POLICY, "Token refresh", || {
+        client
+            .post(format!("{OAUTH_ISSUER}/oauth/token"))
+            .timeout(OAUTH_REQUEST_TIMEOUT)
+            .header("Accept", "application/json")
+            .header("User-Agent", "codex-cli")
+            .form(&form)
+    })
+    .map_err(|mut err| {
+        // The token endpoint answers a revoked or reused refresh token with 400.
+        if err.status == Some(400) {
+            err.kind = HttpErrorKind::Auth;
+        }
+        err
+    })?;
+
+    let payload: Value = serde_json::from_str(&body).map_err(|e| {
+        HttpError::new(
+            HttpErrorKind::Other,
+            format!("Invalid token refresh payload: {e}"),
+        )
+    })?;
+
+    let access_token = payload
+        .get("access_token")
+        .and_then(Value::as_str)
+        .filter(|value| !value.trim().is_empty())
+        .ok_or_else(|| {
+            HttpError::new(
+                HttpErrorKind::Other,
+                "Token refresh payload missing access_token",
+            )
+        })?;
+    account.tokens.access_token = access_token.to_string();
+
+    if let Some(id_token) = payload
+        .get("id_token")
+        .and_then(Value::as_str)
+        .filter(|value| !value.trim().is_empty())
+    {
+        account.tokens.id_token = id_token.to_string();
+        if let Some(email) = extract_email(id_token) {
+            account.email = Some(email);
+        }
+        if let Some(account_id) = extract_account_id(id_token) {
+            account.account_id = Some(account_id);
+        }
     }
 
-    account
+    if let Some(rotated) = payload
+        .get("refresh_token")
+        .and_then(Value::as_str)
+        .filter(|value| !value.trim().is_empty())
+    {
+        account.tokens.refresh_token = rotated.to_string();
+    }
+
+    account.last_refresh_at = Some(now_ts());
+    Ok(())
 }
 
-fn flow_status_text(status: &OauthFlowStatus) -> String {
-    match status {
-        OauthFlowStatus::WaitingCallback => "waiting_callback".to_string(),
-        OauthFlowStatus::Exchanging => "exchanging".to_string(),
-        OauthFlowStatus::Completed => "completed".to_string(),
-        OauthFlowStatus::Error(_) => "error".to_string(),
+/// Copies renewed credentials from a refreshed snapshot onto the stored
+/// account. Returns `true` when anything actually changed.
+///
+/// Tokens from an older refresh are ignored: a snapshot taken before tokens
+/// were renewed elsewhere (relay, another worker, auth.json) would otherwise
+/// put a refresh token that was already rotated out back into the store.
+/// Timestamps have one-second resolution, so a rotation in the same second
+/// as the stored one still counts.
+fn apply_refreshed_tokens(target: &mut Account, source: &Account) -> bool {
+    let differs = source.tokens.refresh_token != target.tokens.refresh_token
+        || source.tokens.access_token != target.tokens.access_token;
+    if !differs || source.last_refresh_at < target.last_refresh_at {
+        return false;
     }
-}
 
-fn flow_to_response(flow: &OauthFlow, data: &AppData) -> OauthFlowResponse {
-    let error = match &flow.status {
-        OauthFlowStatus::Error(msg) => Some(msg.clone()),
-        _ => None,
-    };
+    target.tokens = source.tokens.clone();
+    // New credentials get a fresh chance past the auth circuit breaker.
+    target.auth_failures = 0;
+    target.health.credentials_renewed();
+    target.email = source.email.clone().or(target.email.take());
+    target.account_id = source.account_id.clone().or(target.account_id.take());
+    target.last_refresh_at = source.last_refresh_at;
+    true
+}
+
+/// Stores a refreshed snapshot and, when it is the active account, rewrites
+/// auth.json so Codex does not keep a refresh token that was just rotated out.
+fn persist_refreshed_account(
+    state: &Arc<SharedState>,
+    refreshed: &Account,
+) -> Result<Account, AppError> {
+    let mut data = lock_data(state)?;
+    let is_active = data.active_account_id.as_deref() == Some(refreshed.id.as_str());
+    let account = data
+        .accounts
+        .iter_mut()
+        .find(|a| a.id == refreshed.id)
+        .ok_or_else(|| AppError::account_not_found(&refreshed.id))?;
 
-    let account = flow
-        .result_account_id
-        .as_ref()
-        .and_then(|account_id| data.accounts.iter().find(|a| &a.id == account_id).cloned());
+    let changed = apply_refreshed_tokens(account, refreshed);
+    let updated = account.clone();
 
-    OauthFlowResponse {
-        flow_id: flow.id.clone(),
-        authorization_url: flow.authorization_url.clone(),
-        callback_url: flow.callback_url.clone(),
-        created_at: flow.created_at,
-        status: flow_status_text(&flow.status),
-        error,
-        account,
+    if changed && is_active {
+        write_codex_auth(&updated)?;
     }
+
+    save_app_data(&mut data)?;
+    drop(data);
+
+    events::account_updated(state, &updated);
+    Ok(updated)
 }
 
-fn parse_callback_input(input: &str) -> Result<(String, String, String), String> {
-    let trimmed = input.trim();
-    if trimmed.is_empty() {
-        return Err("Callback URL is empty".to_string());
-    }
+/// Records a failed token renewal on the stored account.
+fn record_refresh_failure(
+    state: &Arc<SharedState>,
+    account_id: &str,
+    err: &HttpError,
+) -> Result<(), AppError> {
+    let mut data = lock_data(state)?;
+    let Some(stored) = data.accounts.iter_mut().find(|a| a.id == account_id) else {
+        return Ok(());
+    };
+    stored.health.mark_refresh_error(err);
+    stored.last_error = Some(err.message.clone());
+    let updated = stored.clone();
+    save_app_data(&mut data)?;
+    drop(data);
 
-    let normalized = if trimmed.starts_with("http://") || trimmed.starts_with("https://") {
-        trimmed.to_string()
-    } else if trimmed.starts_with("/auth/callback") {
-        format!("http://localhost:1455{trimmed}")
-    } else if trimmed.contains("code=") && trimmed.contains("state=") {
-        let query = trimmed.trim_start_matches('?');
-        format!("{OAUTH_REDIRECT_URI}?{query}")
-    } else {
-        return Err(
-            "Invalid callback format. Paste full callback URL or query with code/state".to_string(),
-        );
+    events::account_updated(state, &updated);
+    Ok(())
+}
+
+/// Loads an account and renews its tokens when the access token is expired or
+/// about to expire. A failed renewal is reported as a warning so the caller
+/// can still proceed with the stored tokens.
+fn ensure_fresh_tokens(
+    state: &Arc<SharedState>,
+    account_id: &str,
+) -> Result<(Account, Option<String>), AppError> {
+    sync_tokens_from_codex_auth(state)?;
+
+    let (mut account, proxy) = {
+        let data = lock_data(state)?;
+        let account = data
+            .accounts
+            .iter()
+            .find(|a| a.id == account_id)
+            .cloned()
+            .ok_or_else(|| AppError::account_not_found(account_id))?;
+        (account, active_proxy(&data))
+    };
+
+    if !tokens_need_refresh(&account.tokens) {
+        return Ok((account, None));
+    }
+
+    match refresh_tokens(&mut account, proxy.as_ref()) {
+        Ok(()) => {
+            let updated = persist_refreshed_account(state, &account)?;
+            Ok((updated, None))
+        }
+        Err(err) => {
+            log::warn!(
+                "Token refresh before switch failed for {}: {}",
+                account_id,
+                err
+            );
+            record_refresh_failure(state, account_id, &err)?;
+            Ok((account, Some(err.message)))
+        }
+    }
+}
+
+fn codex_auth_path() -> Result<PathBuf, AppError> {
+    let home = dirs::home_dir()
+        .ok_or_else(|| AppError::Storage("Cannot determine user home directory".to_string()))?;
+    let codex_dir = home.join(".codex");
+    fs::create_dir_all(&codex_dir)
+        .map_err(|e| AppError::Storage(format!("Failed to create .codex directory: {e}")))?;
+    Ok(codex_dir.join("auth.json"))
+}
+
+#[derive(Debug, Clone)]
+struct CodexAuthSnapshot {
+    tokens: Tokens,
+    account_id: Option<String>,
+    email: Option<String>,
+    last_refresh: Option<i64>,
+}
+
+fn read_codex_auth() -> Result<Option<CodexAuthSnapshot>, String> {
+    let path = codex_auth_path()?;
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let text = fs::read_to_string(&path).map_err(|e| format!("Failed to read auth.json: {e}"))?;
+    let payload: Value =
+        serde_json::from_str(&text).map_err(|e| format!("Failed to parse auth.json: {e}"))?;
+
+    let Some(token_obj) = payload.get("tokens").and_then(Value::as_object) else {
+        return Ok(None);
+    };
+    let field = |name: &str| {
+        token_obj
+            .get(name)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    let tokens = Tokens {
+        id_token: field("id_token"),
+        access_token: field("access_token"),
+        refresh_token: field("refresh_token"),
+    };
+    if tokens.access_token.is_empty() && tokens.refresh_token.is_empty() {
+        return Ok(None);
+    }
+
+    let account_id = token_obj
+        .get("account_id")
+        .and_then(Value::as_str)
+        .filter(|value| !value.trim().is_empty())
+        .map(ToOwned::to_owned)
+        .or_else(|| extract_account_id(&tokens.id_token));
+    let email = extract_email(&tokens.id_token);
+    let last_refresh = payload
+        .get("last_refresh")
+        .and_then(Value::as_str)
+        .and_then(|text| DateTime::parse_from_rfc3339(text).ok())
+        .map(|ts| ts.timestamp());
+
+    Ok(Some(CodexAuthSnapshot {
+        tokens,
+        account_id,
+        email,
+        last_refresh,
+    }))
+}
+
+/// Finds the stored account that owns the identity in auth.json.
+fn find_account_for_auth<'a>(
+    accounts: &'a mut [Account],
+    auth: &CodexAuthSnapshot,
+) -> Option<&'a mut Account> {
+    find_account_by_identity(accounts, auth.email.as_deref(), auth.account_id.as_deref())
+}
+
+/// Finds the stored account for a login identity. Team workspaces share
+/// `account_id`, so email is preferred and `account_id` on its own only
+/// matches when it is unambiguous.
+fn find_account_by_identity<'a>(
+    accounts: &'a mut [Account],
+    email: Option<&str>,
+    account_id: Option<&str>,
+) -> Option<&'a mut Account> {
+    if let Some(email) = email {
+        return accounts.iter_mut().find(|account| {
+            account
+                .email
+                .as_deref()
+                .is_some_and(|stored| stored.eq_ignore_ascii_case(email))
+                && match (account.account_id.as_deref(), account_id) {
+                    (Some(stored), Some(external)) => stored == external,
+                    _ => true,
+                }
+        });
+    }
+
+    let account_id = account_id?;
+    let mut matches = accounts
+        .iter_mut()
+        .filter(|account| account.account_id.as_deref() == Some(account_id));
+    let first = matches.next()?;
+    if matches.next().is_some() {
+        return None;
+    }
+    Some(first)
+}
+
+fn absorb_codex_auth(data: &mut AppData) -> Result<Option<String>, String> {
+    match read_codex_auth()? {
+        Some(auth) => Ok(absorb_auth_snapshot(data, &auth)),
+        None => Ok(None),
+    }
+}
+
+/// Pulls tokens that Codex rotated on its own out of auth.json and into the
+/// matching account, as long as they are newer than what the store holds.
+/// Returns the id of the updated account.
+fn absorb_auth_snapshot(data: &mut AppData, auth: &CodexAuthSnapshot) -> Option<String> {
+    let external_refresh = auth.last_refresh?;
+    let account = find_account_for_auth(&mut data.accounts, auth)?;
+
+    let is_newer = account
+        .last_refresh_at
+        .map_or(true, |stored| external_refresh > stored);
+    let differs = account.tokens.access_token != auth.tokens.access_token
+        || account.tokens.refresh_token != auth.tokens.refresh_token;
+    if !is_newer || !differs {
+        return None;
+    }
+
+    account.tokens = auth.tokens.clone();
+    account.last_refresh_at = Some(external_refresh);
+    account.auth_failures = 0;
+    account.health.credentials_renewed();
+    if account.account_id.is_none() {
+        account.account_id = auth.account_id.clone();
+    }
+    Some(account.id.clone())
+}
+
+fn sync_tokens_from_codex_auth(state: &Arc<SharedState>) -> Result<(), AppError> {
+    let mut data = lock_data(state)?;
+    match absorb_codex_auth(&mut data) {
+        Ok(Some(account_id)) => {
+            log::info!(
+                "Absorbed rotated tokens from auth.json into account {}",
+                account_id
+            );
+            save_app_data(&mut data)?;
+        }
+        Ok(None) => {}
+        Err(err) => log::warn!("Failed to sync tokens from auth.json: {}", err),
+    }
+    Ok(())
+}
+
+fn write_codex_auth(account: &Account) -> Result<(), AppError> {
+    let path = codex_auth_path()?;
+    let last_refresh = account
+        .last_refresh_at
+        .and_then(|ts| Utc.timestamp_opt(ts, 0).single())
+        .unwrap_or_else(Utc::now);
+    let data = json!({
+        "OPENAI_API_KEY": Value::Null,
+        "tokens": {
+            "id_token": account.tokens.id_token,
+            "access_token": account.tokens.access_token,
+            "refresh_token": account.tokens.refresh_token,
+            "account_id": account.account_id,
+        },
+        "last_refresh": last_refresh.to_rfc3339(),
+    });
+
+    let text = serde_json::to_string_pretty(&data)
+        .map_err(|e| AppError::Internal(format!("Failed to serialize auth.json: {e}")))?;
+    storage::write_atomic(&path, text.as_bytes())
+        .map_err(|e| AppError::Storage(format!("Failed to write auth.json: {e}")))
+}
+/// Stores the tokens of a new login. Signing in to an account that is
+/// already stored updates it in place and closes its auth circuit breaker,
+/// which resumes paused quota checks.
+fn upsert_account(
+    data: &mut AppData,
+    tokens: Tokens,
+    email: Option<String>,
+    account_id: Option<String>,
+) -> Account {
+    let now = now_ts();
+
+    if let Some(existing) =
+        find_account_by_identity(&mut data.accounts, email.as_deref(), account_id.as_deref())
+    {
+        existing.tokens = tokens;
+        existing.email = email.or(existing.email.take());
+        existing.account_id = account_id.or(existing.account_id.take());
+        existing.last_login_at = now;
+        existing.last_error = None;
+        existing.last_refresh_at = Some(now);
+        existing.auth_failures = 0;
+        existing.health.signed_in();
+        let account = existing.clone();
+        if data.active_account_id.is_none() {
+            data.active_account_id = Some(account.id.clone());
+        }
+        return account;
+    }
+
+    let account = Account {
+        id: Uuid::new_v4().to_string(),
+        email,
+        account_id,
+        tokens,
+        quota: None,
+        created_at: now,
+        last_login_at: now,
+        last_error: None,
+        last_refresh_at: Some(now),
+        auth_failures: 0,
+        health: AccountHealth::default(),
+    };
+
+    data.accounts.push(account.clone());
+
+    if data.active_account_id.is_none() {
+        data.active_account_id = Some(account.id.clone());
+    }
+
+    account
+}
+
+fn flow_status_text(status: &OauthFlowStatus) -> String {
+    match status {
+        OauthFlowStatus::WaitingCallback => "waiting_callback".to_string(),
+        OauthFlowStatus::Exchanging => "exchanging".to_string(),
+        OauthFlowStatus::Completed => "completed".to_string(),
+        OauthFlowStatus::Error(_) => "error".to_string(),
+        OauthFlowStatus::Expired => "expired".to_string(),
+        OauthFlowStatus::Cancelled => "cancelled".to_string(),
+    }
+}
+
+fn flow_to_response(flow: &OauthFlow, data: &AppData) -> OauthFlowResponse {
+    let error = match &flow.status {
+        OauthFlowStatus::Error(msg) => Some(msg.clone()),
+        OauthFlowStatus::Expired => {
+            Some("Login expired before the callback arrived. Start a new one.".to_string())
+        }
+        _ => None,
     };
 
-    let parsed = Url::parse(&normalized).map_err(|e| format!("Invalid callback URL: {e}"))?;
-    let mut code: Option<String> = None;
-    let mut state: Option<String> = None;
+    let account = flow
+        .result_account_id
+        .as_ref()
+        .and_then(|account_id| data.accounts.iter().find(|a| &a.id == account_id))
+        .map(AccountView::from);
+
+    OauthFlowResponse {
+        flow_id: flow.id.clone(),
+        authorization_url: flow.authorization_url.clone(),
+        callback_url: flow.callback_url.clone(),
+        created_at: flow.created_at,
+        status: flow_status_text(&flow.status),
+        error,
+        account,
+    }
+}
+
+/// Query parameters the provider sends back to the redirect URI.
+enum CallbackParams {
+    Code {
+        code: String,
+        state: String,
+    },
+    /// An RFC 6749 error response such as `error=access_denied`. `state` is
+    /// echoed back when the authorize request carried one.
+    Error {
+        message: String,
+        state: Option<String>,
+    },
+}
+
+impl CallbackParams {
+    fn state(&self) -> Option<&str> {
+        match self {
+            Self::Code { state, .. } => Some(state),
+            Self::Error { state, .. } => state.as_deref(),
+        }
+    }
+}
 
-    for (k, v) in parsed.query_pairs() {
-        if k == "code" {
-            code = Some(v.to_string());
-        } else if k == "state" {
-            state = Some(v.to_string());
+fn provider_error_message(error: &str, description: Option<&str>) -> String {
+    match description.map(str::trim).filter(|text| !text.is_empty()) {
+        Some(description) => format!("Sign-in failed: {description} ({error})"),
+        None if error == "access_denied" => {
+            "Sign-in was cancelled or access was denied (access_denied)".to_string()
         }
+        None => format!("Sign-in failed with error {error}"),
+    }
+}
+
+fn read_callback_params(url: &Url) -> Result<CallbackParams, AppError> {
+    let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+    if let Some(error) = query.get("error") {
+        return Ok(CallbackParams::Error {
+            message: provider_error_message(
+                error,
+                query.get("error_description").map(String::as_str),
+            ),
+            state: query.get("state").cloned(),
+        });
     }
 
-    let code = code.ok_or_else(|| "Callback does not contain code".to_string())?;
-    let state = state.ok_or_else(|| "Callback does not contain state".to_string())?;
+    let code = query
+        .get("code")
+        .cloned()
+        .ok_or_else(|| AppError::OAuthCallback("Callback does not contain code".to_string()))?;
+    let state = query
+        .get("state")
+        .cloned()
+        .ok_or_else(|| AppError::OAuthCallback("Callback does not contain state".to_string()))?;
+    Ok(CallbackParams::Code { code, state })
+}
+
+/// Reads a pasted callback. Bare paths and queries are completed with
+/// `redirect_uri`; the normalized URL is returned alongside.
+fn parse_callback_input(
+    input: &str,
+    redirect_uri: &str,
+) -> Result<(CallbackParams, String), AppError> {
+    let trimmed = input.trim();
+    if trimmed.is_empty() {
+        return Err(AppError::OAuthCallback("Callback URL is empty".to_string()));
+    }
+
+    let normalized = if trimmed.starts_with("http://") || trimmed.starts_with("https://") {
+        trimmed.to_string()
+    } else if let Some(rest) = trimmed.strip_prefix(CALLBACK_PATH) {
+        format!("{redirect_uri}{rest}")
+    } else if (trimmed.contains("code=") && trimmed.contains("state="))
+        || trimmed.contains("error=")
+    {
+        let query = trimmed.trim_start_matches('?');
+        format!("{redirect_uri}?{query}")
+    } else {
+        return Err(AppError::OAuthCallback(
+            "Invalid callback format. Paste full callback URL or query with code/state".to_string(),
+        ));
+    };
 
-    Ok((code, state, normalized))
+    let parsed = Url::parse(&normalized)
+        .map_err(|e| AppError::OAuthCallback(format!("Invalid callback URL: {e}")))?;
+    Ok((read_callback_params(&parsed)?, normalized))
 }
 
 fn test_proxy_latency(proxy: &ProxyEntry) -> Result<u64, String> {
@@ -827,38 +1434,130 @@ fn test_proxy_latency(proxy: &ProxyEntry) -> Result<u64, String> {
     Ok(started.elapsed().as_millis() as u64)
 }
 
-fn ensure_callback_server(state: &Arc<SharedState>) {
-    if state.callback_server_started.swap(true, Ordering::SeqCst) {
-        return;
+fn lock_callback_origin(
+    state: &Arc<SharedState>,
+) -> Result<std::sync::MutexGuard<'_, Option<String>>, AppError> {
+    state
+        .callback_origin
+        .lock()
+        .map_err(|_| AppError::Internal("State lock poisoned (callback)".to_string()))
+}
+
+/// Starts the OAuth callback listener on the first free configured port and
+/// returns its origin. A listener that is already running is reused, so new
+/// settings apply the next time it starts. Callers hold the `callback_origin`
+/// lock in `running` until their flow is registered, so the listener cannot
+/// shut down in between.
+fn ensure_callback_server(
+    state: &Arc<SharedState>,
+    settings: &OAuthCallbackSettings,
+    running: &mut Option<String>,
+) -> Result<String, AppError> {
+    if let Some(origin) = running.as_ref() {
+        return Ok(origin.clone());
     }
 
-    let shared = Arc::clone(state);
-    std::thread::spawn(move || {
-        let listener = match TcpListener::bind(CALLBACK_ADDR) {
+    let mut failures = Vec::new();
+    for port in settings.ports() {
+        let listener = match TcpListener::bind((CALLBACK_BIND_HOST, port)) {
             Ok(listener) => listener,
             Err(err) => {
-                log::error!(
-                    "OAuth callback listener failed to bind on {}: {}",
-                    CALLBACK_ADDR,
-                    err
-                );
-                return;
+                failures.push(format!("{port}: {err}"));
+                continue;
             }
         };
+        listener.set_nonblocking(true).map_err(|e| {
+            AppError::OAuthCallbackServer(format!("Failed to configure callback listener: {e}"))
+        })?;
+        let origin = settings.origin(port);
+        if !failures.is_empty() {
+            log::info!("OAuth callback listening on fallback {}", origin);
+        }
+        *running = Some(origin.clone());
 
-        for incoming in listener.incoming() {
-            match incoming {
-                Ok(stream) => {
-                    if let Err(err) = handle_callback_stream(stream, &shared) {
-                        log::warn!("OAuth callback handling failed: {}", err);
-                    }
-                }
-                Err(err) => {
-                    log::warn!("OAuth callback incoming connection failed: {}", err);
+        let shared = Arc::clone(state);
+        let stream_origin = origin.clone();
+        std::thread::spawn(move || serve_callbacks(listener, &shared, &stream_origin));
+        return Ok(origin);
+    }
+
+    Err(AppError::OAuthCallbackServer(format!(
+        "Could not listen for the OAuth callback on {} ({}). Another login, such as the Codex CLI, may be using the port.",
+        settings.host,
+        failures.join("; ")
+    )))
+}
+
+/// Accepts callbacks until no flow is waiting for one, expiring stale flows
+/// along the way.
+fn serve_callbacks(listener: TcpListener, state: &Arc<SharedState>, origin: &str) {
+    let mut last_sweep = Instant::now();
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let handled = stream
+                    .set_nonblocking(false)
+                    .map_err(|e| format!("Failed to configure callback stream: {e}"))
+                    .and_then(|_| handle_callback_stream(stream, state, origin));
+                if let Err(err) = handled {
+                    log::warn!("OAuth callback handling failed: {}", err);
                 }
             }
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(CALLBACK_POLL_INTERVAL);
+            }
+            Err(err) => log::warn!("OAuth callback incoming connection failed: {}", err),
         }
-    });
+
+        if last_sweep.elapsed() < OAUTH_SWEEP_INTERVAL {
+            continue;
+        }
+        last_sweep = Instant::now();
+        let Ok(mut running) = lock_callback_origin(state) else {
+            break;
+        };
+        match sweep_oauth_flows(state) {
+            Ok(0) => {
+                *running = None;
+                break;
+            }
+            Ok(_) => {}
+            Err(err) => log::warn!("Failed to expire OAuth flows: {}", err),
+        }
+    }
+    log::info!("OAuth callback listener on {} stopped", origin);
+}
+
+/// Expires flows that outlived [`OAUTH_FLOW_TTL_SECS`] and drops finished
+/// ones after [`OAUTH_FLOW_RETENTION_SECS`]. A flow in the middle of its token
+/// exchange is left to finish. Returns how many flows are still pending.
+fn sweep_oauth_flows(state: &Arc<SharedState>) -> Result<usize, AppError> {
+    let now = now_ts();
+    let (expired, pending) = {
+        let mut flows = lock_flows(state)?;
+        let mut expired = Vec::new();
+        for flow in flows.values_mut() {
+            if !flow.is_finished()
+                && !matches!(flow.status, OauthFlowStatus::Exchanging)
+                && now - flow.created_at >= OAUTH_FLOW_TTL_SECS
+            {
+                flow.finish(OauthFlowStatus::Expired);
+                expired.push(flow.id.clone());
+            }
+        }
+        flows.retain(|_, flow| {
+            flow.finished_at
+                .map_or(true, |at| now - at < OAUTH_FLOW_RETENTION_SECS)
+        });
+        let pending = flows.values().filter(|flow| !flow.is_finished()).count();
+        (expired, pending)
+    };
+
+    for flow_id in expired {
+        log::info!("OAuth flow {} expired", flow_id);
+        events::oauth_flow_updated(state, &flow_id);
+    }
+    Ok(pending)
 }
 
 fn write_http_response(stream: &mut TcpStream, status: &str, body: &str) -> Result<(), String> {
@@ -872,12 +1571,26 @@ fn write_http_response(stream: &mut TcpStream, status: &str, body: &str) -> Resu
         .map_err(|e| format!("HTTP response write failed: {e}"))
 }
 
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// `message` is escaped; callback pages can show text chosen by the provider.
 fn html_message(title: &str, message: &str) -> String {
+    let message = html_escape(message);
     format!(
         "<!doctype html><html><head><meta charset=\"utf-8\"><title>{title}</title><style>body{{font-family:Segoe UI,Arial,sans-serif;background:#f6f8fb;color:#1d2733;padding:30px}}.card{{max-width:640px;margin:0 auto;background:white;border-radius:14px;padding:24px;box-shadow:0 10px 30px rgba(20,37,63,.08)}}h1{{margin:0 0 12px 0;font-size:22px}}p{{margin:0;font-size:15px;line-height:1.45}}</style></head><body><div class=\"card\"><h1>{title}</h1><p>{message}</p></div></body></html>"
     )
 }
-fn handle_callback_stream(mut stream: TcpStream, shared: &Arc<SharedState>) -> Result<(), String> {
+fn handle_callback_stream(
+    mut stream: TcpStream,
+    shared: &Arc<SharedState>,
+    origin: &str,
+) -> Result<(), String> {
     let mut buffer = [0u8; 8192];
     let read = stream
         .read(&mut buffer)
@@ -900,7 +1613,7 @@ fn handle_callback_stream(mut stream: TcpStream, shared: &Arc<SharedState>) -> R
         return write_http_response(&mut stream, "405 Method Not Allowed", &body);
     }
 
-    let callback_url = format!("http://localhost:1455{path_with_query}");
+    let callback_url = format!("{origin}{path_with_query}");
     let parsed = match Url::parse(&callback_url) {
         Ok(url) => url,
         Err(_) => {
@@ -909,7 +1622,7 @@ fn handle_callback_stream(mut stream: TcpStream, shared: &Arc<SharedState>) -> R
         }
     };
 
-    if parsed.path() != "/auth/callback" {
+    if parsed.path() != CALLBACK_PATH {
         let body = html_message(
             "Not Found",
             "This endpoint is only used for OAuth callback.",
@@ -917,31 +1630,36 @@ fn handle_callback_stream(mut stream: TcpStream, shared: &Arc<SharedState>) -> R
         return write_http_response(&mut stream, "404 Not Found", &body);
     }
 
-    let mut code: Option<String> = None;
-    let mut state_value: Option<String> = None;
-    for (k, v) in parsed.query_pairs() {
-        if k == "code" {
-            code = Some(v.to_string());
-        } else if k == "state" {
-            state_value = Some(v.to_string());
+    let (code, state_value) = match read_callback_params(&parsed) {
+        Ok(CallbackParams::Code { code, state }) => (code, state),
+        Ok(CallbackParams::Error { message, state }) => {
+            log::warn!("OAuth provider returned an error: {}", message);
+            if let Some(state_value) = state {
+                let flow_id = {
+                    let flows = lock_flows(shared)?;
+                    flows
+                        .iter()
+                        .find(|(_, flow)| flow.state == state_value && !flow.is_finished())
+                        .map(|(id, _)| id.clone())
+                };
+                if let Some(flow_id) = flow_id {
+                    fail_flow(shared, &flow_id, &message)?;
+                }
+            }
+            let body = html_message("Login Failed", &message);
+            return write_http_response(&mut stream, "400 Bad Request", &body);
+        }
+        Err(err) => {
+            let body = html_message("Callback Error", &err.to_string());
+            return write_http_response(&mut stream, "400 Bad Request", &body);
         }
-    }
-
-    let Some(code) = code else {
-        let body = html_message("Callback Error", "Query does not contain OAuth code.");
-        return write_http_response(&mut stream, "400 Bad Request", &body);
-    };
-
-    let Some(state_value) = state_value else {
-        let body = html_message("Callback Error", "Query does not contain OAuth state.");
-        return write_http_response(&mut stream, "400 Bad Request", &body);
     };
 
     let flow_id = {
         let mut flows = lock_flows(shared)?;
         let target = flows
             .iter_mut()
-            .find(|(_, flow)| flow.state == state_value)
+            .find(|(_, flow)| flow.state == state_value && !flow.is_finished())
             .map(|(id, flow)| {
                 flow.callback_url = Some(callback_url.clone());
                 flow.status = OauthFlowStatus::Exchanging;
@@ -975,106 +1693,124 @@ fn complete_oauth_code(
     flow_id: &str,
     code: &str,
     callback_url: Option<String>,
-) -> Result<Account, String> {
-    let code_verifier = {
+) -> Result<Account, AppError> {
+    let (code_verifier, redirect_uri) = {
         let mut flows = lock_flows(shared)?;
         let flow = flows
             .get_mut(flow_id)
-            .ok_or_else(|| "OAuth flow not found".to_string())?;
+            .filter(|flow| !flow.is_finished())
+            .ok_or(AppError::OAuthFlowNotFound)?;
         flow.status = OauthFlowStatus::Exchanging;
         if let Some(callback_url) = &callback_url {
             flow.callback_url = Some(callback_url.clone());
         }
-        flow.code_verifier.clone()
+        (flow.code_verifier.clone(), flow.redirect_uri.clone())
     };
+    events::oauth_flow_updated(shared, flow_id);
+
+    // Every failure past this point has to finish the flow, or it would stay
+    // `Exchanging` and keep the callback listener alive.
+    let result = exchange_and_store(shared, code, &code_verifier, &redirect_uri);
+    {
+        let mut flows = lock_flows(shared)?;
+        if let Some(flow) = flows.get_mut(flow_id).filter(|flow| !flow.is_finished()) {
+            match &result {
+                Ok(account) => {
+                    flow.finish(OauthFlowStatus::Completed);
+                    flow.result_account_id = Some(account.id.clone());
+                }
+                Err(err) => flow.finish(OauthFlowStatus::Error(err.to_string())),
+            }
+        }
+    }
+
+    events::oauth_flow_updated(shared, flow_id);
+    result
+}
 
+/// Trades the authorization code for tokens and stores the account they
+/// belong to, with a first quota reading.
+fn exchange_and_store(
+    shared: &Arc<SharedState>,
+    code: &str,
+    code_verifier: &str,
+    redirect_uri: &str,
+) -> Result<Account, AppError> {
     let proxy = {
         let data = lock_data(shared)?;
         active_proxy(&data)
     };
 
-    let exchange = exchange_code_for_tokens(code, &code_verifier, proxy.as_ref());
-
-    let result = match exchange {
-        Ok(tokens) => {
-            let account_id = extract_account_id(&tokens.id_token);
-            let email = extract_email(&tokens.id_token);
+    let tokens = exchange_code_for_tokens(code, code_verifier, redirect_uri, proxy.as_ref())?;
+    let account_id = extract_account_id(&tokens.id_token);
+    let email = extract_email(&tokens.id_token);
 
-            let (limits_base_url, quota_proxy) = {
-                let data = lock_data(shared)?;
-                (data.limits_base_url.clone(), active_proxy(&data))
-            };
-
-            let quota_result = fetch_quota(
-                &limits_base_url,
-                &tokens,
-                account_id.as_deref(),
-                quota_proxy.as_ref(),
-            );
+    let (limits_base_url, quota_proxy) = {
+        let data = lock_data(shared)?;
+        (data.limits_base_url.clone(), active_proxy(&data))
+    };
 
-            let account = {
-                let mut data = lock_data(shared)?;
-                let account = upsert_account(&mut data, tokens, email, account_id);
-
-                let account_mut = data
-                    .accounts
-                    .iter_mut()
-                    .find(|entry| entry.id == account.id)
-                    .ok_or_else(|| "Account disappeared during OAuth completion".to_string())?;
-
-                match quota_result {
-                    Ok(quota) => {
-                        account_mut.quota = Some(quota);
-                        account_mut.last_error = None;
-                    }
-                    Err(err) => {
-                        account_mut.last_error = Some(err);
-                    }
-                }
+    let quota_result = fetch_quota(
+        &limits_base_url,
+        &tokens,
+        account_id.as_deref(),
+        quota_proxy.as_ref(),
+    );
 
-                let updated = account_mut.clone();
-                save_app_data(&data)?;
-                updated
-            };
+    let (account, previous_active, active) = {
+        let mut data = lock_data(shared)?;
+        let previous_active = data.active_account_id.clone();
+        let account = upsert_account(&mut data, tokens, email, account_id);
 
-            let mut flows = lock_flows(shared)?;
-            if let Some(flow) = flows.get_mut(flow_id) {
-                flow.status = OauthFlowStatus::Completed;
-                flow.result_account_id = Some(account.id.clone());
+        let account_mut = data
+            .accounts
+            .iter_mut()
+            .find(|entry| entry.id == account.id)
+            .ok_or_else(|| AppError::account_not_found(&account.id))?;
+
+        match quota_result {
+            Ok(quota) => {
+                account_mut.quota = Some(quota);
+                account_mut.last_error = None;
+                account_mut.health.mark_healthy();
+                history::record(account_mut);
             }
-
-            Ok(account)
-        }
-        Err(err) => {
-            let mut flows = lock_flows(shared)?;
-            if let Some(flow) = flows.get_mut(flow_id) {
-                flow.status = OauthFlowStatus::Error(err.clone());
+            Err(err) => {
+                account_mut.health.mark_error(&err);
+                account_mut.last_error = Some(err.message);
             }
-            Err(err)
         }
-    };
 
-    result
+        let updated = account_mut.clone();
+        save_app_data(&mut data)?;
+        (updated, previous_active, data.active_account_id.clone())
+    };
+    events::quota_updated(shared, &account);
+    events::active_account_changed(shared, previous_active, active);
+    Ok(account)
 }
 
 #[tauri::command]
-fn get_app_state(state: State<'_, Arc<SharedState>>) -> Result<AppData, String> {
+fn get_app_state(state: State<'_, Arc<SharedState>>) -> Result<AppDataView, AppError> {
+    storage::ensure_supported()?;
     let data = lock_data(state.inner())?;
-    Ok(data.clone())
+    Ok(AppDataView::from(&*data))
 }
 
 #[tauri::command]
-fn get_storage_path() -> Result<String, String> {
+fn get_storage_path() -> Result<String, AppError> {
     app_storage_file().map(|p| p.to_string_lossy().to_string())
 }
 
-#[tauri::command]
-fn start_oauth_flow(state: State<'_, Arc<SharedState>>) -> Result<OauthStartResponse, String> {
-    ensure_callback_server(state.inner());
-
+fn begin_oauth_flow(state: &Arc<SharedState>) -> Result<OauthStartResponse, AppError> {
+    let settings = lock_data(state)?.oauth_callback.clone();
+    sweep_oauth_flows(state)?;
+    let mut running = lock_callback_origin(state)?;
+    let origin = ensure_callback_server(state, &settings, &mut running)?;
+    let redirect_uri = format!("{origin}{CALLBACK_PATH}");
     let (code_verifier, code_challenge) = build_pkce();
     let flow_state = random_urlsafe(32);
-    let auth_url = build_authorize_url(&flow_state, &code_challenge)?;
+    let auth_url = build_authorize_url(&flow_state, &code_challenge, &redirect_uri)?;
     let flow_id = Uuid::new_v4().to_string();
 
     let flow = OauthFlow {
@@ -1082,184 +1818,322 @@ fn start_oauth_flow(state: State<'_, Arc<SharedState>>) -> Result<OauthStartResp
         state: flow_state,
         code_verifier,
         created_at: now_ts(),
+        redirect_uri: redirect_uri.clone(),
         authorization_url: auth_url.clone(),
         callback_url: None,
         result_account_id: None,
         status: OauthFlowStatus::WaitingCallback,
+        finished_at: None,
     };
 
-    let mut flows = lock_flows(state.inner())?;
-    flows.insert(flow_id.clone(), flow);
+    lock_flows(state)?.insert(flow_id.clone(), flow);
+    drop(running);
 
     Ok(OauthStartResponse {
         flow_id,
         authorization_url: auth_url,
-        redirect_uri: OAUTH_REDIRECT_URI.to_string(),
+        redirect_uri,
     })
 }
 
+#[tauri::command]
+fn start_oauth_flow(state: State<'_, Arc<SharedState>>) -> Result<OauthStartResponse, AppError> {
+    begin_oauth_flow(state.inner())
+}
+
 #[tauri::command]
 fn get_oauth_flow_status(
     flow_id: String,
     state: State<'_, Arc<SharedState>>,
-) -> Result<OauthFlowResponse, String> {
+) -> Result<OauthFlowResponse, AppError> {
     let flow = {
         let flows = lock_flows(state.inner())?;
         flows
             .get(&flow_id)
             .cloned()
-            .ok_or_else(|| "OAuth flow not found".to_string())?
+            .ok_or(AppError::OAuthFlowNotFound)?
     };
 
     let data = lock_data(state.inner())?;
     Ok(flow_to_response(&flow, &data))
 }
 
-#[tauri::command]
-fn complete_oauth_with_callback(
-    flow_id: String,
-    callback_url: String,
-    state: State<'_, Arc<SharedState>>,
-) -> Result<OauthFlowResponse, String> {
-    let (code, callback_state, normalized) = parse_callback_input(&callback_url)?;
-
-    {
-        let mut flows = lock_flows(state.inner())?;
-        let flow = flows
-            .get_mut(&flow_id)
-            .ok_or_else(|| "OAuth flow not found".to_string())?;
-
-        if flow.state != callback_state {
-            flow.status = OauthFlowStatus::Error(
+/// Validates a pasted callback against the flow and runs the code exchange.
+/// Exchange failures and provider error responses are recorded on the flow
+/// rather than returned.
+fn complete_flow_with_callback(
+    state: &Arc<SharedState>,
+    flow_id: &str,
+    callback_url: &str,
+) -> Result<(), AppError> {
+    let redirect_uri = {
+        let flows = lock_flows(state)?;
+        let flow = flows.get(flow_id).ok_or(AppError::OAuthFlowNotFound)?;
+        if flow.is_finished() {
+            return Err(AppError::OAuthCallback(
+                "This login has already finished. Start a new one.".to_string(),
+            ));
+        }
+        flow.redirect_uri.clone()
+    };
+    let (params, normalized) = parse_callback_input(callback_url, &redirect_uri)?;
+
+    let state_matches = {
+        let mut flows = lock_flows(state)?;
+        let flow = flows.get_mut(flow_id).ok_or(AppError::OAuthFlowNotFound)?;
+
+        // Error responses may lack `state`; the flow is known here anyway.
+        if params.state().map_or(true, |value| value == flow.state) {
+            flow.callback_url = Some(normalized.clone());
+            true
+        } else {
+            flow.finish(OauthFlowStatus::Error(
                 "State mismatch. Callback belongs to another session.".to_string(),
-            );
-            return Err(
-                "State mismatch. Ensure callback belongs to the current login session.".to_string(),
-            );
+            ));
+            false
         }
+    };
 
-        flow.callback_url = Some(normalized.clone());
-        flow.status = OauthFlowStatus::Exchanging;
+    if !state_matches {
+        events::oauth_flow_updated(state, flow_id);
+        return Err(AppError::OAuthStateMismatch);
+    }
+
+    match params {
+        CallbackParams::Code { code, .. } => {
+            let _ = complete_oauth_code(state, flow_id, &code, Some(normalized));
+        }
+        CallbackParams::Error { message, .. } => {
+            log::warn!("OAuth provider returned an error: {}", message);
+            fail_flow(state, flow_id, &message)?;
+        }
+    }
+    Ok(())
+}
+
+/// Ends a flow with an error reported by the provider.
+fn fail_flow(state: &Arc<SharedState>, flow_id: &str, message: &str) -> Result<(), AppError> {
+    {
+        let mut flows = lock_flows(state)?;
+        let Some(flow) = flows.get_mut(flow_id).filter(|flow| !flow.is_finished()) else {
+            return Ok(());
+        };
+        flow.finish(OauthFlowStatus::Error(message.to_string()));
+    }
+    events::oauth_flow_updated(state, flow_id);
+    Ok(())
+}
+
+/// Abandons a login. The callback listener stops once no flows are left.
+#[tauri::command]
+fn cancel_oauth_flow(flow_id: String, state: State<'_, Arc<SharedState>>) -> Result<(), AppError> {
+    {
+        let mut flows = lock_flows(state.inner())?;
+        let flow = flows.get_mut(&flow_id).ok_or(AppError::OAuthFlowNotFound)?;
+        if flow.is_finished() {
+            return Ok(());
+        }
+        flow.finish(OauthFlowStatus::Cancelled);
     }
+    events::oauth_flow_updated(state.inner(), &flow_id);
+    Ok(())
+}
 
-    let _ = complete_oauth_code(state.inner(), &flow_id, &code, Some(normalized));
+#[tauri::command]
+fn complete_oauth_with_callback(
+    flow_id: String,
+    callback_url: String,
+    state: State<'_, Arc<SharedState>>,
+) -> Result<OauthFlowResponse, AppError> {
+    complete_flow_with_callback(state.inner(), &flow_id, &callback_url)?;
 
     let flow = {
         let flows = lock_flows(state.inner())?;
         flows
             .get(&flow_id)
             .cloned()
-            .ok_or_else(|| "OAuth flow not found after completion".to_string())?
+            .ok_or(AppError::OAuthFlowNotFound)?
     };
     let data = lock_data(state.inner())?;
 
     Ok(flow_to_response(&flow, &data))
 }
-#[tauri::command]
-fn remove_account(
-    account_id: String,
-    state: State<'_, Arc<SharedState>>,
-) -> Result<AppData, String> {
-    let mut data = lock_data(state.inner())?;
+
+/// Finds an account by id, email (case-insensitive) or upstream account id.
+fn resolve_account(data: &AppData, needle: &str) -> Result<String, AppError> {
+    if let Some(account) = data.accounts.iter().find(|a| a.id == needle) {
+        return Ok(account.id.clone());
+    }
+
+    if let Some(account) = data.accounts.iter().find(|a| {
+        a.email
+            .as_deref()
+            .is_some_and(|email| email.eq_ignore_ascii_case(needle))
+    }) {
+        return Ok(account.id.clone());
+    }
+
+    let mut by_account_id = data
+        .accounts
+        .iter()
+        .filter(|a| a.account_id.as_deref() == Some(needle));
+    match (by_account_id.next(), by_account_id.next()) {
+        (Some(account), None) => Ok(account.id.clone()),
+        (Some(_), Some(_)) => Err(AppError::InvalidInput(format!(
+            "Several accounts share account id '{needle}'; use the email instead"
+        ))),
+        _ => Err(AppError::account_not_found(needle)),
+    }
+}
+
+fn remove_stored_account(
+    state: &Arc<SharedState>,
+    account_id: &str,
+) -> Result<AppDataView, AppError> {
+    let mut data = lock_data(state)?;
+    let previous_active = data.active_account_id.clone();
     data.accounts.retain(|a| a.id != account_id);
 
-    if data.active_account_id.as_ref() == Some(&account_id) {
+    if data.active_account_id.as_deref() == Some(account_id) {
         data.active_account_id = data.accounts.first().map(|a| a.id.clone());
     }
 
-    save_app_data(&data)?;
-    Ok(data.clone())
+    save_app_data(&mut data)?;
+    events::active_account_changed(state, previous_active, data.active_account_id.clone());
+    Ok(AppDataView::from(&*data))
+}
+
+#[tauri::command]
+fn remove_account(
+    account_id: String,
+    state: State<'_, Arc<SharedState>>,
+) -> Result<AppDataView, AppError> {
+    remove_stored_account(state.inner(), &account_id)
 }
 
 #[tauri::command]
 fn set_active_account(
     account_id: String,
     state: State<'_, Arc<SharedState>>,
-) -> Result<AppData, String> {
-    let account = {
-        let data = lock_data(state.inner())?;
-        data.accounts
-            .iter()
-            .find(|a| a.id == account_id)
-            .cloned()
-            .ok_or_else(|| "Account not found".to_string())?
-    };
+) -> Result<AppDataView, AppError> {
+    let (account, _) = ensure_fresh_tokens(state.inner(), &account_id)?;
 
-    write_codex_auth(&account.tokens, account.account_id.as_deref())?;
+    write_codex_auth(&account)?;
 
     let mut data = lock_data(state.inner())?;
-    data.active_account_id = Some(account_id);
-    save_app_data(&data)?;
+    let previous_active = data.active_account_id.replace(account_id.clone());
+    save_app_data(&mut data)?;
+    events::active_account_changed(state.inner(), previous_active, Some(account_id));
 
-    Ok(data.clone())
+    Ok(AppDataView::from(&*data))
 }
 
 #[tauri::command]
 fn set_preferred_ide(
     ide: Option<String>,
     state: State<'_, Arc<SharedState>>,
-) -> Result<AppData, String> {
-    let normalized = match ide {
-        Some(value) => Some(
-            normalize_ide_target(&value)
-                .ok_or_else(|| "Invalid IDE target".to_string())?,
-        ),
+) -> Result<AppDataView, AppError> {
+    let mut data = lock_data(state.inner())?;
+    data.preferred_ide = match ide {
+        Some(value) => Some(ide::normalize_target(&data, &value).map_err(AppError::InvalidInput)?),
         None => None,
     };
+    save_app_data(&mut data)?;
 
+    Ok(AppDataView::from(&*data))
+}
+
+#[tauri::command]
+fn list_ide_targets(state: State<'_, Arc<SharedState>>) -> Result<Vec<ide::IdeTarget>, AppError> {
+    let data = lock_data(state.inner())?;
+    Ok(ide::all_targets(&data))
+}
+
+#[tauri::command]
+fn save_custom_ide(
+    target: ide::IdeTarget,
+    state: State<'_, Arc<SharedState>>,
+) -> Result<AppDataView, AppError> {
     let mut data = lock_data(state.inner())?;
-    data.preferred_ide = normalized;
-    save_app_data(&data)?;
+    let target = ide::validate_custom(&data, target).map_err(AppError::InvalidInput)?;
+
+    match data.custom_ides.iter_mut().find(|t| t.id == target.id) {
+        Some(existing) => *existing = target,
+        None => data.custom_ides.push(target),
+    }
 
-    Ok(data.clone())
+    save_app_data(&mut data)?;
+    Ok(AppDataView::from(&*data))
 }
 
 #[tauri::command]
-fn switch_account_for_ide(
+fn delete_custom_ide(
+    ide_id: String,
+    state: State<'_, Arc<SharedState>>,
+) -> Result<AppDataView, AppError> {
+    let mut data = lock_data(state.inner())?;
+    data.custom_ides.retain(|target| target.id != ide_id);
+
+    if data.preferred_ide.as_ref() == Some(&ide_id) {
+        data.preferred_ide = None;
+    }
+
+    save_app_data(&mut data)?;
+    Ok(AppDataView::from(&*data))
+}
+
+/// Writes the account into auth.json, marks it active and reloads the selected
+/// IDE. Shared by the manual switch command and automatic rotation.
+fn switch_account(
+    state: &Arc<SharedState>,
     account_id: String,
     ide: Option<String>,
-    state: State<'_, Arc<SharedState>>,
-) -> Result<SwitchAccountResponse, String> {
+) -> Result<SwitchAccountResponse, AppError> {
     let requested_ide = match ide {
-        Some(value) => Some(
-            normalize_ide_target(&value)
-                .ok_or_else(|| "Invalid IDE target".to_string())?,
-        ),
+        Some(value) => {
+            let data = lock_data(state)?;
+            Some(ide::normalize_target(&data, &value).map_err(AppError::InvalidInput)?)
+        }
         None => None,
     };
 
-    let (account, fallback_ide) = {
-        let data = lock_data(state.inner())?;
-        let account = data
-            .accounts
-            .iter()
-            .find(|a| a.id == account_id)
-            .cloned()
-            .ok_or_else(|| "Account not found".to_string())?;
-        (account, data.preferred_ide.clone())
+    let (account, refresh_error) = ensure_fresh_tokens(state, &account_id)?;
+    let (selected_ide, target) = {
+        let data = lock_data(state)?;
+        let selected = requested_ide.clone().or(data.preferred_ide.clone());
+        let target = selected
+            .as_deref()
+            .and_then(|id| ide::find_target(&data, id));
+        (selected, target)
     };
 
-    write_codex_auth(&account.tokens, account.account_id.as_deref())?;
-
-    let selected_ide = requested_ide.clone().or(fallback_ide);
+    write_codex_auth(&account)?;
 
     let snapshot = {
-        let mut data = lock_data(state.inner())?;
-        data.active_account_id = Some(account_id);
+        let mut data = lock_data(state)?;
+        let previous_active = data.active_account_id.replace(account_id.clone());
         if let Some(ide_name) = &requested_ide {
             data.preferred_ide = Some(ide_name.clone());
         }
-        save_app_data(&data)?;
-        data.clone()
+        save_app_data(&mut data)?;
+        events::active_account_changed(state, previous_active, Some(account_id));
+        AppDataView::from(&*data)
     };
 
     let (reloaded, warning) = if let Some(ide_name) = selected_ide.as_deref() {
-        match reload_ide_windows(ide_name) {
+        let reload_result = match &target {
+            Some(target) => ide::reload(target).map_err(AppError::IdeReload),
+            None => Err(AppError::IdeReload(format!(
+                "IDE target '{ide_name}' is no longer configured"
+            ))),
+        };
+        match reload_result {
             Ok(true) => (true, None),
             Ok(false) => (
                 false,
                 Some(format!(
-                    "Account switched. No running {ide_name} process was found to reload."
+                    "Account switched. No running {} process was found to reload.",
+                    target.as_ref().map_or(ide_name, |t| t.label.as_str())
                 )),
             ),
             Err(err) => (false, Some(format!("Account switched, but IDE reload failed: {err}"))),
@@ -1271,6 +2145,17 @@ fn switch_account_for_ide(
         )
     };
 
+    let warning = match refresh_error {
+        Some(err) => {
+            let refresh_warning = format!("Stored tokens could not be refreshed: {err}");
+            Some(match warning {
+                Some(reload_warning) => format!("{reload_warning} {refresh_warning}"),
+                None => refresh_warning,
+            })
+        }
+        None => warning,
+    };
+
     Ok(SwitchAccountResponse {
         state: snapshot,
         ide: selected_ide,
@@ -1279,113 +2164,472 @@ fn switch_account_for_ide(
     })
 }
 
+#[tauri::command]
+fn switch_account_for_ide(
+    account_id: String,
+    ide: Option<String>,
+    state: State<'_, Arc<SharedState>>,
+) -> Result<SwitchAccountResponse, AppError> {
+    switch_account(state.inner(), account_id, ide)
+}
+
+/// Stores the outcome of a quota fetch made on `snapshot`, including any
+/// tokens renewed along the way, and notifies the UI.
+fn store_quota_result(
+    state: &Arc<SharedState>,
+    snapshot: &Account,
+    fetch: QuotaFetch,
+) -> Result<Account, AppError> {
+    let mut data = lock_data(state)?;
+    let is_active = data.active_account_id.as_deref() == Some(snapshot.id.as_str());
+    let account = data
+        .accounts
+        .iter_mut()
+        .find(|a| a.id == snapshot.id)
+        .ok_or_else(|| AppError::account_not_found(&snapshot.id))?;
+
+    if apply_refreshed_tokens(account, snapshot) && is_active {
+        write_codex_auth(account)?;
+    }
+    // A failure with tokens that were replaced during the fetch says nothing
+    // about the credentials now stored.
+    let stale_tokens = account.tokens.access_token != snapshot.tokens.access_token;
+    if fetch.result.is_ok() || !stale_tokens {
+        record_quota_outcome(account, &fetch);
+    }
+
+    match fetch.result {
+        Ok(quota) => {
+            account.quota = Some(quota);
+            account.last_error = None;
+            history::record(account);
+        }
+        Err(err) if !stale_tokens => {
+            account.last_error = Some(err.message);
+        }
+        Err(_) => {}
+    }
+
+    let updated = account.clone();
+    save_app_data(&mut data)?;
+    drop(data);
+
+    events::quota_updated(state, &updated);
+    Ok(updated)
+}
+
 #[tauri::command]
 fn refresh_account_quota(
     account_id: String,
     state: State<'_, Arc<SharedState>>,
-) -> Result<Account, String> {
-    let (base_url, account_snapshot, proxy) = {
+) -> Result<AccountView, AppError> {
+    sync_tokens_from_codex_auth(state.inner())?;
+
+    let (base_url, mut account_snapshot, proxy) = {
         let data = lock_data(state.inner())?;
         let account = data
             .accounts
             .iter()
             .find(|a| a.id == account_id)
             .cloned()
-            .ok_or_else(|| "Account not found".to_string())?;
+            .ok_or_else(|| AppError::account_not_found(&account_id))?;
 
         (data.limits_base_url.clone(), account, active_proxy(&data))
     };
 
-    let quota_result = fetch_quota(
-        &base_url,
-        &account_snapshot.tokens,
-        account_snapshot.account_id.as_deref(),
-        proxy.as_ref(),
-    );
+    let fetch = fetch_quota_with_refresh(&base_url, &mut account_snapshot, proxy.as_ref());
+    let failure = fetch.result.as_ref().err().cloned();
+    let updated = store_quota_result(state.inner(), &account_snapshot, fetch)?;
 
-    let mut data = lock_data(state.inner())?;
-    let account = data
-        .accounts
-        .iter_mut()
-        .find(|a| a.id == account_id)
-        .ok_or_else(|| "Account disappeared during update".to_string())?;
+    rotation::rotate_and_notify(state.inner());
+    match failure {
+        Some(err) => Err(AppError::quota(err)),
+        None => Ok(AccountView::from(&updated)),
+    }
+}
 
-    match quota_result {
-        Ok(quota) => {
-            account.quota = Some(quota);
-            account.last_error = None;
-        }
-        Err(err) => {
-            account.last_error = Some(err);
-        }
+#[tauri::command]
+fn refresh_account_tokens(
+    account_id: String,
+    state: State<'_, Arc<SharedState>>,
+) -> Result<AccountView, AppError> {
+    sync_tokens_from_codex_auth(state.inner())?;
+
+    let (mut account, proxy) = {
+        let data = lock_data(state.inner())?;
+        let account = data
+            .accounts
+            .iter()
+            .find(|a| a.id == account_id)
+            .cloned()
+            .ok_or_else(|| AppError::account_not_found(&account_id))?;
+        (account, active_proxy(&data))
+    };
+
+    if let Err(err) = refresh_tokens(&mut account, proxy.as_ref()) {
+        record_refresh_failure(state.inner(), &account_id, &err)?;
+        return Err(AppError::token_refresh(err));
     }
 
-    let result = account.clone();
-    save_app_data(&data)?;
-    Ok(result)
+    persist_refreshed_account(state.inner(), &account).map(|updated| AccountView::from(&updated))
 }
 
-#[tauri::command]
-fn refresh_all_quotas(state: State<'_, Arc<SharedState>>) -> Result<AppData, String> {
+/// Fetches quota for every stored account, renewing tokens where needed, and
+/// persists the results. Rotation is left to the caller.
+fn refresh_quotas_for_all(state: &Arc<SharedState>) -> Result<QuotaRefreshReport, AppError> {
+    refresh_quotas_where(state, |_| true)
+}
+
+/// Like [`refresh_quotas_for_all`], limited to the accounts `include` accepts.
+///
+/// Fetches run on a small worker pool and each account is saved and announced
+/// as soon as it finishes. After [`QUOTA_REFRESH_DEADLINE`] the call returns
+/// with whatever has completed: queued accounts are dropped and in-flight ones
+/// keep running in the background so renewed tokens are never lost.
+fn refresh_quotas_where(
+    state: &Arc<SharedState>,
+    include: impl Fn(&Account) -> bool,
+) -> Result<QuotaRefreshReport, AppError> {
+    sync_tokens_from_codex_auth(state)?;
+
     let (base_url, accounts, proxy) = {
-        let data = lock_data(state.inner())?;
+        let data = lock_data(state)?;
         (
             data.limits_base_url.clone(),
-            data.accounts.clone(),
+            data.accounts
+                .iter()
+                .filter(|account| include(account))
+                .cloned()
+                .collect::<Vec<_>>(),
             active_proxy(&data),
         )
     };
 
-    let mut updates: HashMap<String, Result<QuotaInfo, String>> = HashMap::new();
-    for account in &accounts {
-        let result = fetch_quota(
-            &base_url,
-            &account.tokens,
-            account.account_id.as_deref(),
-            proxy.as_ref(),
-        );
-        updates.insert(account.id.clone(), result);
+    let mut report = QuotaRefreshReport::default();
+    let queue: VecDeque<Account> = {
+        let mut in_flight = lock_quota_in_flight(state)?;
+        accounts
+            .into_iter()
+            .filter(|account| {
+                let claimed = in_flight.insert(account.id.clone());
+                if !claimed {
+                    report.pending.push(account.id.clone());
+                }
+                claimed
+            })
+            .collect()
+    };
+
+    let mut waiting: HashSet<String> = queue.iter().map(|account| account.id.clone()).collect();
+    let workers = QUOTA_REFRESH_WORKERS.min(queue.len());
+    let queue = Arc::new(Mutex::new(queue));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = mpsc::channel::<(String, Result<Account, AppError>)>();
+
+    for _ in 0..workers {
+        let state = Arc::clone(state);
+        let queue = Arc::clone(&queue);
+        let cancelled = Arc::clone(&cancelled);
+        let tx = tx.clone();
+        let base_url = base_url.clone();
+        let proxy = proxy.clone();
+
+        std::thread::spawn(move || loop {
+            if cancelled.load(Ordering::SeqCst) {
+                break;
+            }
+            let Some(mut account) = queue.lock().ok().and_then(|mut q| q.pop_front()) else {
+                break;
+            };
+
+            let fetch = fetch_quota_with_refresh(&base_url, &mut account, proxy.as_ref());
+            let stored = store_quota_result(&state, &account, fetch);
+            if let Ok(mut in_flight) = lock_quota_in_flight(&state) {
+                in_flight.remove(&account.id);
+            }
+            // The receiver is gone once the deadline passed; the result is
+            // already saved, so there is nothing left to report.
+            let _ = tx.send((account.id, stored));
+        });
     }
+    drop(tx);
 
-    let mut data = lock_data(state.inner())?;
-    for account in &mut data.accounts {
-        if let Some(result) = updates.remove(&account.id) {
-            match result {
-                Ok(quota) => {
-                    account.quota = Some(quota);
-                    account.last_error = None;
-                }
-                Err(err) => {
-                    account.last_error = Some(err);
-                }
+    let deadline = Instant::now() + QUOTA_REFRESH_DEADLINE;
+    while !waiting.is_empty() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let Ok((account_id, stored)) = rx.recv_timeout(remaining) else {
+            break;
+        };
+        waiting.remove(&account_id);
+        match stored {
+            Ok(account) if account.health.status == AccountStatus::Healthy => {
+                report.refreshed.push(account_id)
+            }
+            Ok(_) => report.failed.push(account_id),
+            Err(err) => {
+                log::warn!("Failed to store quota for {}: {}", account_id, err);
+                report.failed.push(account_id);
             }
         }
     }
 
-    save_app_data(&data)?;
-    Ok(data.clone())
+    if !waiting.is_empty() {
+        cancelled.store(true, Ordering::SeqCst);
+        let queued: Vec<Account> = queue
+            .lock()
+            .map(|mut q| q.drain(..).collect())
+            .unwrap_or_default();
+        if let Ok(mut in_flight) = lock_quota_in_flight(state) {
+            for account in &queued {
+                in_flight.remove(&account.id);
+            }
+        }
+        log::warn!(
+            "Quota refresh hit its deadline: {} accounts still running, {} not started",
+            waiting.len() - queued.len(),
+            queued.len()
+        );
+        report.pending.extend(waiting);
+    }
+
+    Ok(report)
 }
 
 #[tauri::command]
-fn save_proxy(
-    proxy_id: Option<String>,
-    proxy_value: String,
+fn refresh_all_quotas(state: State<'_, Arc<SharedState>>) -> Result<RefreshAllResponse, AppError> {
+    let report = refresh_quotas_for_all(state.inner())?;
+    rotation::rotate_and_notify(state.inner());
+
+    let data = lock_data(state.inner())?;
+    Ok(RefreshAllResponse {
+        state: AppDataView::from(&*data),
+        refreshed_account_ids: report.refreshed,
+        failed_account_ids: report.failed,
+        pending_account_ids: report.pending,
+    })
+}
+
+#[tauri::command]
+fn set_rotation_settings(
+    settings: RotationSettings,
     state: State<'_, Arc<SharedState>>,
-) -> Result<AppData, String> {
-    let parsed = parse_proxy_input(&proxy_value)?;
+) -> Result<AppDataView, AppError> {
+    let thresholds = [settings.primary_threshold, settings.secondary_threshold];
+    if thresholds
+        .iter()
+        .any(|value| !value.is_finite() || *value < 0.0 || *value > 100.0)
+    {
+        return Err(AppError::InvalidInput(
+            "Rotation thresholds must be between 0 and 100".to_string(),
+        ));
+    }
+
+    {
+        let mut data = lock_data(state.inner())?;
+        data.rotation = settings;
+        save_app_data(&mut data)?;
+    }
+
+    rotation::rotate_and_notify(state.inner());
+
+    let data = lock_data(state.inner())?;
+    Ok(AppDataView::from(&*data))
+}
+
+#[tauri::command]
+fn get_quota_history(
+    account_id: Option<String>,
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Result<Vec<history::QuotaSeries>, AppError> {
+    vault::ensure_unlocked().map_err(AppError::vault)?;
+    history::query(account_id.as_deref(), from, to).map_err(AppError::Storage)
+}
+
+#[tauri::command]
+fn get_usage_summary(
+    from: Option<i64>,
+    to: Option<i64>,
+) -> Result<Vec<history::UsageSummary>, AppError> {
+    vault::ensure_unlocked().map_err(AppError::vault)?;
+    history::usage_summary(from, to).map_err(AppError::Storage)
+}
+
+#[tauri::command]
+fn get_quota_forecasts(
+    state: State<'_, Arc<SharedState>>,
+) -> Result<Vec<forecast::AccountForecast>, AppError> {
+    let accounts = {
+        let data = lock_data(state.inner())?;
+        data.accounts.clone()
+    };
+    accounts
+        .iter()
+        .map(|account| forecast::forecast_account(account).map_err(AppError::Storage))
+        .collect()
+}
+
+#[tauri::command]
+fn get_quota_scheduler_status(
+    state: State<'_, Arc<SharedState>>,
+) -> Result<scheduler::SchedulerStatus, AppError> {
+    scheduler::status(state.inner())
+}
+
+#[tauri::command]
+fn set_quota_refresh_settings(
+    settings: QuotaRefreshSettings,
+    state: State<'_, Arc<SharedState>>,
+) -> Result<AppDataView, AppError> {
+    if !(1..=24 * 60).contains(&settings.interval_minutes) {
+        return Err(AppError::InvalidInput(
+            "Refresh interval must be between 1 and 1440 minutes".to_string(),
+        ));
+    }
+    if settings.jitter_seconds > settings.interval_minutes * 30 {
+        return Err(AppError::InvalidInput(
+            "Jitter cannot exceed half of the refresh interval".to_string(),
+        ));
+    }
+
+    let view = {
+        let mut data = lock_data(state.inner())?;
+        data.quota_refresh = settings;
+        save_app_data(&mut data)?;
+        AppDataView::from(&*data)
+    };
+
+    scheduler::wake(state.inner());
+    Ok(view)
+}
+
+#[tauri::command]
+fn get_relay_status(state: State<'_, Arc<SharedState>>) -> Result<relay::RelayStatus, AppError> {
+    relay::status(state.inner())
+}
+
+#[tauri::command]
+fn set_relay_settings(
+    settings: RelaySettings,
+    state: State<'_, Arc<SharedState>>,
+) -> Result<relay::RelayStatus, AppError> {
+    if settings.port == 0 {
+        return Err(AppError::InvalidInput(
+            "Relay port must be non-zero".to_string(),
+        ));
+    }
+    let upstream = Url::parse(settings.upstream_url.trim())
+        .map_err(|e| AppError::InvalidInput(format!("Invalid upstream URL: {e}")))?;
+    if !matches!(upstream.scheme(), "http" | "https") {
+        return Err(AppError::InvalidInput(
+            "Upstream URL must use http or https".to_string(),
+        ));
+    }
+
+    {
+        let mut data = lock_data(state.inner())?;
+        if data
+            .oauth_callback
+            .ports()
+            .any(|port| port == settings.port)
+        {
+            return Err(AppError::InvalidInput(
+                "Relay port must differ from the OAuth callback ports".to_string(),
+            ));
+        }
+        data.relay = RelaySettings {
+            upstream_url: settings.upstream_url.trim().to_string(),
+            ..settings
+        };
+        save_app_data(&mut data)?;
+    }
+
+    if let Err(err) = relay::apply_settings(state.inner()) {
+        let mut data = lock_data(state.inner())?;
+        data.relay.enabled = false;
+        save_app_data(&mut data)?;
+        return Err(err);
+    }
+
+    relay::status(state.inner())
+}
+
+/// Stores the OAuth callback origin and ports. A listener that is already
+/// running keeps its port until it next starts.
+#[tauri::command]
+fn set_oauth_callback_settings(
+    settings: OAuthCallbackSettings,
+    state: State<'_, Arc<SharedState>>,
+) -> Result<AppDataView, AppError> {
+    let host = settings.host.trim().to_ascii_lowercase();
+    let valid_host = !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+    if !valid_host {
+        return Err(AppError::InvalidInput(
+            "Callback host must be a host name or IPv4 address".to_string(),
+        ));
+    }
+    let loopback = (host.as_str(), settings.port)
+        .to_socket_addrs()
+        .map(|mut addrs| addrs.any(|addr| addr.is_ipv4() && addr.ip().is_loopback()))
+        .unwrap_or(false);
+    if !loopback {
+        return Err(AppError::InvalidInput(format!(
+            "Callback host {host} does not resolve to this machine's loopback address"
+        )));
+    }
+    if settings.port == 0 || settings.fallback_ports.contains(&0) {
+        return Err(AppError::InvalidInput(
+            "Callback ports must be non-zero".to_string(),
+        ));
+    }
+
+    let mut fallback_ports = Vec::new();
+    for port in settings.fallback_ports {
+        if port != settings.port && !fallback_ports.contains(&port) {
+            fallback_ports.push(port);
+        }
+    }
+    let settings = OAuthCallbackSettings {
+        host,
+        port: settings.port,
+        fallback_ports,
+    };
+
+    let mut data = lock_data(state.inner())?;
+    if settings.ports().any(|port| port == data.relay.port) {
+        return Err(AppError::InvalidInput(
+            "Callback ports must differ from the relay port".to_string(),
+        ));
+    }
+    data.oauth_callback = settings;
+    save_app_data(&mut data)?;
+    Ok(AppDataView::from(&*data))
+}
+
+fn store_proxy(
+    state: &Arc<SharedState>,
+    proxy_id: Option<String>,
+    proxy_value: &str,
+) -> Result<AppDataView, AppError> {
+    let parsed = parse_proxy_input(proxy_value)?;
     let raw = format!(
         "{}:{}@{}:{}",
         parsed.login, parsed.password, parsed.host, parsed.port
     );
 
-    let mut data = lock_data(state.inner())?;
+    let mut data = lock_data(state)?;
 
     if let Some(proxy_id) = proxy_id {
         let proxy = data
             .proxies
             .iter_mut()
             .find(|p| p.id == proxy_id)
-            .ok_or_else(|| "Proxy not found".to_string())?;
+            .ok_or_else(|| AppError::ProxyNotFound {
+                proxy_id: proxy_id.clone(),
+            })?;
 
         proxy.login = parsed.login;
         proxy.password = parsed.password;
@@ -1407,12 +2651,24 @@ fn save_proxy(
         data.proxies.push(entry);
     }
 
-    save_app_data(&data)?;
-    Ok(data.clone())
+    save_app_data(&mut data)?;
+    Ok(AppDataView::from(&*data))
 }
 
 #[tauri::command]
-fn delete_proxy(proxy_id: String, state: State<'_, Arc<SharedState>>) -> Result<AppData, String> {
+fn save_proxy(
+    proxy_id: Option<String>,
+    proxy_value: String,
+    state: State<'_, Arc<SharedState>>,
+) -> Result<AppDataView, AppError> {
+    store_proxy(state.inner(), proxy_id, &proxy_value)
+}
+
+#[tauri::command]
+fn delete_proxy(
+    proxy_id: String,
+    state: State<'_, Arc<SharedState>>,
+) -> Result<AppDataView, AppError> {
     let mut data = lock_data(state.inner())?;
     data.proxies.retain(|proxy| proxy.id != proxy_id);
 
@@ -1420,53 +2676,60 @@ fn delete_proxy(proxy_id: String, state: State<'_, Arc<SharedState>>) -> Result<
         data.active_proxy_id = None;
     }
 
-    save_app_data(&data)?;
-    Ok(data.clone())
+    save_app_data(&mut data)?;
+    Ok(AppDataView::from(&*data))
 }
 
-#[tauri::command]
-fn set_active_proxy(
+fn select_active_proxy(
+    state: &Arc<SharedState>,
     proxy_id: Option<String>,
-    state: State<'_, Arc<SharedState>>,
-) -> Result<AppData, String> {
-    let mut data = lock_data(state.inner())?;
+) -> Result<AppDataView, AppError> {
+    let mut data = lock_data(state)?;
 
     if let Some(proxy_id) = proxy_id {
         if !data.proxies.iter().any(|proxy| proxy.id == proxy_id) {
-            return Err("Proxy not found".to_string());
+            return Err(AppError::ProxyNotFound { proxy_id });
         }
         data.active_proxy_id = Some(proxy_id);
     } else {
         data.active_proxy_id = None;
     }
 
-    save_app_data(&data)?;
-    Ok(data.clone())
+    save_app_data(&mut data)?;
+    Ok(AppDataView::from(&*data))
 }
 
 #[tauri::command]
-fn test_proxy(
-    proxy_id: String,
+fn set_active_proxy(
+    proxy_id: Option<String>,
     state: State<'_, Arc<SharedState>>,
-) -> Result<ProxyTestResult, String> {
+) -> Result<AppDataView, AppError> {
+    select_active_proxy(state.inner(), proxy_id)
+}
+
+fn run_proxy_test(state: &Arc<SharedState>, proxy_id: String) -> Result<ProxyTestResult, AppError> {
     let proxy = {
-        let data = lock_data(state.inner())?;
+        let data = lock_data(state)?;
         data.proxies
             .iter()
             .find(|proxy| proxy.id == proxy_id)
             .cloned()
-            .ok_or_else(|| "Proxy not found".to_string())?
+            .ok_or_else(|| AppError::ProxyNotFound {
+                proxy_id: proxy_id.clone(),
+            })?
     };
 
     let checked_at = now_ts();
     let ping = test_proxy_latency(&proxy);
 
-    let mut data = lock_data(state.inner())?;
+    let mut data = lock_data(state)?;
     let proxy = data
         .proxies
         .iter_mut()
         .find(|proxy| proxy.id == proxy_id)
-        .ok_or_else(|| "Proxy disappeared during update".to_string())?;
+        .ok_or_else(|| AppError::ProxyNotFound {
+            proxy_id: proxy_id.to_string(),
+        })?;
 
     let result = match ping {
         Ok(latency) => {
@@ -1497,43 +2760,341 @@ fn test_proxy(
         }
     };
 
-    save_app_data(&data)?;
+    save_app_data(&mut data)?;
+    drop(data);
+
+    events::emit(state, events::PROXY_TESTED_EVENT, result.clone());
     Ok(result)
 }
 
+#[tauri::command]
+fn test_proxy(
+    proxy_id: String,
+    state: State<'_, Arc<SharedState>>,
+) -> Result<ProxyTestResult, AppError> {
+    run_proxy_test(state.inner(), proxy_id)
+}
+
+#[tauri::command]
+fn get_vault_status() -> Result<vault::VaultStatus, AppError> {
+    vault::status().map_err(AppError::vault)
+}
+
+#[tauri::command]
+fn enable_vault(
+    passphrase: String,
+    state: State<'_, Arc<SharedState>>,
+) -> Result<vault::VaultStatus, AppError> {
+    let mut data = lock_data(state.inner())?;
+    vault::enable(&passphrase, data.vault_auto_lock_minutes).map_err(AppError::vault)?;
+    if let Err(err) = save_app_data(&mut data) {
+        vault::cancel_enable();
+        return Err(err);
+    }
+    if let Err(err) = storage::seal_plaintext_copies() {
+        log::warn!("Failed to seal plaintext backups: {}", err);
+    }
+    vault::status().map_err(AppError::vault)
+}
+
+#[tauri::command]
+fn unlock_vault(
+    passphrase: String,
+    state: State<'_, Arc<SharedState>>,
+) -> Result<AppDataView, AppError> {
+    let text = vault::unlock(&passphrase).map_err(AppError::vault)?;
+    let unlocked = parse_app_data(&text)?;
+    storage::mark_synced(&unlocked);
+    vault::set_idle_timeout(unlocked.vault_auto_lock_minutes);
+    // Catches copies written in the clear by a build without this sweep.
+    if let Err(err) = storage::seal_plaintext_copies() {
+        log::warn!("Failed to seal plaintext backups: {}", err);
+    }
+
+    {
+        let mut data = lock_data(state.inner())?;
+        *data = unlocked;
+    }
+
+    // Relay and refresh settings were unknown while the state was sealed.
+    if let Err(err) = relay::apply_settings(state.inner()) {
+        log::warn!("Failed to start relay: {}", err);
+    }
+    scheduler::wake(state.inner());
+
+    let data = lock_data(state.inner())?;
+    Ok(AppDataView::from(&*data))
+}
+
+#[tauri::command]
+fn lock_vault(state: State<'_, Arc<SharedState>>) -> Result<vault::VaultStatus, AppError> {
+    vault::lock().map_err(AppError::vault)?;
+    vault::wipe_shared_state(state.inner());
+    vault::status().map_err(AppError::vault)
+}
+
+#[tauri::command]
+fn disable_vault(
+    passphrase: String,
+    state: State<'_, Arc<SharedState>>,
+) -> Result<vault::VaultStatus, AppError> {
+    let mut data = lock_data(state.inner())?;
+    vault::disable(&passphrase).map_err(AppError::vault)?;
+    save_app_data(&mut data)?;
+    vault::status().map_err(AppError::vault)
+}
+
+#[tauri::command]
+fn set_vault_auto_lock(
+    minutes: u32,
+    state: State<'_, Arc<SharedState>>,
+) -> Result<AppDataView, AppError> {
+    let mut data = lock_data(state.inner())?;
+    data.vault_auto_lock_minutes = minutes;
+    vault::set_idle_timeout(minutes);
+    save_app_data(&mut data)?;
+    Ok(AppDataView::from(&*data))
+}
+
+#[tauri::command]
+fn get_state_recovery() -> Option<storage::RecoveryNotice> {
+    storage::recovery_notice()
+}
+
+#[tauri::command]
+fn dismiss_state_recovery() {
+    storage::dismiss_recovery_notice();
+}
+
+#[tauri::command]
+fn list_state_backups() -> Result<Vec<storage::StateBackup>, AppError> {
+    vault::ensure_unlocked().map_err(AppError::vault)?;
+    storage::list_backups()
+}
+
+/// Replaces the current state with a backup. The file being replaced is
+/// backed up first, so a restore can itself be undone.
+#[tauri::command]
+fn restore_state_backup(
+    name: String,
+    state: State<'_, Arc<SharedState>>,
+) -> Result<AppDataView, AppError> {
+    let restored = storage::load_backup(&name)?;
+    storage::backup_state(true)?;
+
+    let view = {
+        let mut data = lock_data(state.inner())?;
+        *data = restored;
+        save_app_data(&mut data)?;
+        vault::set_idle_timeout(data.vault_auto_lock_minutes);
+        AppDataView::from(&*data)
+    };
+    storage::dismiss_recovery_notice();
+    log::info!("Restored state from backup {}", name);
+
+    if let Err(err) = relay::apply_settings(state.inner()) {
+        log::warn!("Failed to start relay: {}", err);
+    }
+    scheduler::wake(state.inner());
+    Ok(view)
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let initial_data = load_app_data().unwrap_or_else(|err| {
-        log::warn!("Failed to load persisted state, using defaults: {}", err);
-        AppData::default()
-    });
+    let launch_args: Vec<String> = std::env::args().skip(1).collect();
+    let single_instance = match instance::acquire(&launch_args) {
+        instance::Instance::Forwarded => return,
+        instance::Instance::Primary(listener) => listener,
+    };
+
+    vault::detect_sealed_state();
+    let initial_data = match storage::load_with_recovery() {
+        Ok(data) => data,
+        // Saves refuse to replace that file, so the defaults only keep the
+        // app running long enough to show the error.
+        Err(err @ AppError::UnsupportedSchema { .. }) => {
+            log::error!("{}", err);
+            storage::hold_unsupported(err);
+            AppData::default()
+        }
+        Err(err) => {
+            if !matches!(err, AppError::VaultLocked) {
+                log::warn!("Failed to load persisted state, using defaults: {}", err);
+            }
+            AppData::default()
+        }
+    };
 
     let shared_state = Arc::new(SharedState::new(initial_data));
 
+    let commands: fn(tauri::ipc::Invoke) -> bool = tauri::generate_handler![
+        get_app_state,
+        get_storage_path,
+        start_oauth_flow,
+        get_oauth_flow_status,
+        complete_oauth_with_callback,
+        cancel_oauth_flow,
+        remove_account,
+        set_active_account,
+        set_preferred_ide,
+        list_ide_targets,
+        save_custom_ide,
+        delete_custom_ide,
+        switch_account_for_ide,
+        refresh_account_quota,
+        refresh_account_tokens,
+        refresh_all_quotas,
+        set_rotation_settings,
+        get_quota_scheduler_status,
+        set_quota_refresh_settings,
+        get_relay_status,
+        set_relay_settings,
+        set_oauth_callback_settings,
+        get_quota_history,
+        get_usage_summary,
+        get_quota_forecasts,
+        save_proxy,
+        delete_proxy,
+        set_active_proxy,
+        test_proxy,
+        get_vault_status,
+        enable_vault,
+        unlock_vault,
+        lock_vault,
+        disable_vault,
+        set_vault_auto_lock,
+        get_state_recovery,
+        dismiss_state_recovery,
+        list_state_backups,
+        restore_state_backup
+    ];
+
     tauri::Builder::default()
-        .manage(shared_state)
+        .manage(Arc::clone(&shared_state))
+        .setup(move |app| {
+            // Attached first so background threads started below can emit.
+            events::attach(&shared_state, app.handle().clone());
+            vault::start_idle_lock_timer(Arc::clone(&shared_state));
+            if let Err(err) = relay::apply_settings(&shared_state) {
+                log::warn!("Failed to start relay: {}", err);
+            }
+            std::thread::spawn(|| {
+                if let Err(err) = history::compact() {
+                    log::warn!("Failed to compact quota history: {}", err);
+                }
+            });
+            if let Some((listener, token)) = single_instance {
+                instance::serve(
+                    listener,
+                    token,
+                    app.handle().clone(),
+                    Arc::clone(&shared_state),
+                );
+            }
+            if !launch_args.is_empty() {
+                let state = Arc::clone(&shared_state);
+                std::thread::spawn(move || instance::handle_args(&state, &launch_args));
+            }
+            scheduler::start(Arc::clone(&shared_state));
+            state_watcher::start_state_watcher(Arc::clone(&shared_state));
+            auth_watcher::start_auth_watcher(shared_state);
+            Ok(())
+        })
         .plugin(
             tauri_plugin_log::Builder::default()
                 .level(log::LevelFilter::Info)
                 .build(),
         )
-        .invoke_handler(tauri::generate_handler![
-            get_app_state,
-            get_storage_path,
-            start_oauth_flow,
-            get_oauth_flow_status,
-            complete_oauth_with_callback,
-            remove_account,
-            set_active_account,
-            set_preferred_ide,
-            switch_account_for_ide,
-            refresh_account_quota,
-            refresh_all_quotas,
-            save_proxy,
-            delete_proxy,
-            set_active_proxy,
-            test_proxy
-        ])
+        .invoke_handler(move |invoke| {
+            vault::touch_activity();
+            commands(invoke)
+        })
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(refresh_token: &str, last_refresh_at: Option<i64>) -> Account {
+        Account {
+            id: "account-1".to_string(),
+            email: Some("user@example.com".to_string()),
+            account_id: Some("acct-1".to_string()),
+            tokens: Tokens {
+                id_token: "id".to_string(),
+                access_token: format!("access-{refresh_token}"),
+                refresh_token: refresh_token.to_string(),
+            },
+            quota: None,
+            created_at: 1_700_000_000,
+            last_login_at: 1_700_000_000,
+            last_error: None,
+            last_refresh_at,
+            auth_failures: 0,
+            health: AccountHealth::default(),
+        }
+    }
+
+    #[test]
+    fn applies_newer_refresh() {
+        let mut stored = account("refresh-1", Some(100));
+        stored.auth_failures = 2;
+        let refreshed = account("refresh-2", Some(200));
+
+        assert!(apply_refreshed_tokens(&mut stored, &refreshed));
+        assert_eq!(stored.tokens.refresh_token, "refresh-2");
+        assert_eq!(stored.last_refresh_at, Some(200));
+        assert_eq!(stored.auth_failures, 0);
+    }
+
+    #[test]
+    fn keeps_stored_tokens_newer_than_snapshot() {
+        let mut stored = account("refresh-3", Some(300));
+        stored.auth_failures = 2;
+        let snapshot = account("refresh-2", Some(200));
+
+        assert!(!apply_refreshed_tokens(&mut stored, &snapshot));
+        assert_eq!(stored.tokens.refresh_token, "refresh-3");
+        assert_eq!(stored.last_refresh_at, Some(300));
+        assert_eq!(stored.auth_failures, 2);
+
+        let unchanged = stored.clone();
+        assert!(!apply_refreshed_tokens(&mut stored, &unchanged));
+    }
+
+    #[test]
+    fn applies_rotation_in_the_same_second() {
+        let mut stored = account("refresh-1", Some(100));
+        let rotated = account("refresh-2", Some(100));
+
+        assert!(apply_refreshed_tokens(&mut stored, &rotated));
+        assert_eq!(stored.tokens.refresh_token, "refresh-2");
+    }
+
+    #[test]
+    fn signing_in_again_resumes_a_paused_account() {
+        let mut paused = account("refresh-1", Some(100));
+        paused.auth_failures = AUTH_BREAKER_THRESHOLD;
+        paused.health.set(AccountStatus::NeedsReauth, Some(401));
+        let mut data = AppData {
+            accounts: vec![paused],
+            ..AppData::default()
+        };
+
+        let tokens = account("refresh-2", None).tokens;
+        let account = upsert_account(
+            &mut data,
+            tokens,
+            Some("USER@example.com".to_string()),
+            Some("acct-1".to_string()),
+        );
+
+        assert_eq!(data.accounts.len(), 1);
+        assert_eq!(account.id, "account-1");
+        assert_eq!(data.accounts[0].tokens.refresh_token, "refresh-2");
+        assert_eq!(data.accounts[0].auth_failures, 0);
+        assert_eq!(data.accounts[0].health.status, AccountStatus::Stale);
+    }
+}
@@ -0,0 +1,217 @@
+//! Upgrades older `state.json` shapes before they are deserialized.
+//!
+//! Every layout that shipped gets a schema version. Files written before the
+//! version field existed are identified by their keys: 0.1 had no
+//! `preferredIde`, 0.2 always wrote it. Each step in [`MIGRATIONS`] rewrites
+//! the raw JSON from one version to the next, so a field rename only needs a
+//! new step here and `AppData` can keep a single, current shape.
+
+use crate::error::AppError;
+use serde_json::{Map, Value};
+
+/// Schema written by this build.
+pub(crate) const CURRENT_SCHEMA_VERSION: u32 = 3;
+
+const SCHEMA_VERSION_KEY: &str = "schemaVersion";
+
+type Migration = fn(&mut Map<String, Value>);
+
+/// `MIGRATIONS[n]` upgrades version `n + 1` to `n + 2`.
+const MIGRATIONS: [Migration; 2] = [v1_to_v2, v2_to_v3];
+
+/// 0.1 -> 0.2: the IDE preference was added.
+fn v1_to_v2(state: &mut Map<String, Value>) {
+    state.entry("preferredIde").or_insert(Value::Null);
+}
+
+/// 0.2 -> 3: every field added since 0.2 has a serde default, so the
+/// version key is the only change.
+fn v2_to_v3(_state: &mut Map<String, Value>) {}
+
+fn detect_version(state: &Map<String, Value>) -> Result<u32, AppError> {
+    match state.get(SCHEMA_VERSION_KEY) {
+        Some(value) => value
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .filter(|version| *version >= 1)
+            .ok_or_else(|| AppError::Parse(format!("Invalid schema version: {value}"))),
+        None if state.contains_key("preferredIde") => Ok(2),
+        None => Ok(1),
+    }
+}
+
+/// Brings a parsed state file up to [`CURRENT_SCHEMA_VERSION`]. Files from a
+/// newer build are rejected with [`AppError::UnsupportedSchema`] rather than
+/// read with fields silently dropped; they are not corrupt, so callers must
+/// not replace them.
+pub(crate) fn migrate(mut value: Value) -> Result<Value, AppError> {
+    let state = value
+        .as_object_mut()
+        .ok_or_else(|| AppError::Parse("State file is not a JSON object".to_string()))?;
+    let version = detect_version(state)?;
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(AppError::UnsupportedSchema { version });
+    }
+
+    for step in &MIGRATIONS[(version - 1) as usize..] {
+        step(state);
+    }
+    if version < CURRENT_SCHEMA_VERSION {
+        log::info!(
+            "Migrated state file from schema {} to {}",
+            version,
+            CURRENT_SCHEMA_VERSION
+        );
+    }
+    state.insert(
+        SCHEMA_VERSION_KEY.to_string(),
+        Value::from(CURRENT_SCHEMA_VERSION),
+    );
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::AccountStatus;
+    use crate::{parse_app_data, AppData};
+    use serde_json::json;
+
+    const STATE_0_1: &str = r#"{
+        "accounts": [
+            {
+                "id": "8c1f6d2e-1111-4b7a-9a57-0d6f3c9e2a10",
+                "email": "first@example.com",
+                "accountId": "acct-1",
+                "tokens": { "idToken": "id-1", "accessToken": "access-1", "refreshToken": "refresh-1" },
+                "quota": {
+                    "planType": "plus",
+                    "primary": { "usedPercent": 42.0, "limitWindowSeconds": 18000, "resetAt": 1735690000, "fetchedAt": 1735680000 },
+                    "secondary": { "usedPercent": 7.5, "limitWindowSeconds": 604800, "resetAt": 1736200000, "fetchedAt": 1735680000 },
+                    "fetchedAt": 1735680000
+                },
+                "createdAt": 1735600000,
+                "lastLoginAt": 1735600000,
+                "lastError": null
+            }
+        ],
+        "activeAccountId": "8c1f6d2e-1111-4b7a-9a57-0d6f3c9e2a10",
+        "proxies": [
+            {
+                "id": "proxy-1",
+                "login": "user",
+                "password": "secret",
+                "host": "10.0.0.2",
+                "port": 8080,
+                "raw": "user:secret@10.0.0.2:8080",
+                "lastLatencyMs": 120,
+                "lastStatus": "ok",
+                "lastCheckedAt": 1735600100
+            }
+        ],
+        "activeProxyId": "proxy-1",
+        "limitsBaseUrl": "https://chatgpt.com/backend-api"
+    }"#;
+
+    const STATE_0_2: &str = r#"{
+        "accounts": [
+            {
+                "id": "2b0e9f4a-2222-4c1d-8e33-5a7b9c0d1e2f",
+                "email": "second@example.com",
+                "accountId": null,
+                "tokens": { "idToken": "id-2", "accessToken": "access-2", "refreshToken": "refresh-2" },
+                "quota": null,
+                "createdAt": 1738000000,
+                "lastLoginAt": 1738100000,
+                "lastError": "Quota request failed (401)"
+            }
+        ],
+        "activeAccountId": null,
+        "proxies": [],
+        "activeProxyId": null,
+        "limitsBaseUrl": "https://chatgpt.com/backend-api",
+        "preferredIde": "cursor"
+    }"#;
+
+    fn migrated(text: &str) -> Value {
+        migrate(serde_json::from_str(text).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn detects_unversioned_layouts() {
+        let v01: Value = serde_json::from_str(STATE_0_1).unwrap();
+        let v02: Value = serde_json::from_str(STATE_0_2).unwrap();
+        assert_eq!(detect_version(v01.as_object().unwrap()).unwrap(), 1);
+        assert_eq!(detect_version(v02.as_object().unwrap()).unwrap(), 2);
+    }
+
+    #[test]
+    fn migrates_0_1_layout() {
+        let value = migrated(STATE_0_1);
+        assert_eq!(value["schemaVersion"], json!(CURRENT_SCHEMA_VERSION));
+        assert_eq!(value["preferredIde"], Value::Null);
+
+        let data = parse_app_data(STATE_0_1).unwrap();
+        assert_eq!(data.schema_version, CURRENT_SCHEMA_VERSION);
+        assert_eq!(data.preferred_ide, None);
+        assert_eq!(data.accounts.len(), 1);
+        let account = &data.accounts[0];
+        assert_eq!(account.email.as_deref(), Some("first@example.com"));
+        assert_eq!(account.tokens.refresh_token, "refresh-1");
+        let quota = account.quota.as_ref().unwrap();
+        assert_eq!(quota.primary.used_percent, Some(42.0));
+        assert_eq!(quota.secondary.limit_window_seconds, Some(604800));
+        assert_eq!(account.health.status, AccountStatus::Stale);
+        assert_eq!(data.proxies[0].port, 8080);
+        assert_eq!(data.active_proxy_id.as_deref(), Some("proxy-1"));
+    }
+
+    #[test]
+    fn migrates_0_2_layout() {
+        let value = migrated(STATE_0_2);
+        assert_eq!(value["schemaVersion"], json!(CURRENT_SCHEMA_VERSION));
+        assert_eq!(value["preferredIde"], json!("cursor"));
+
+        let data = parse_app_data(STATE_0_2).unwrap();
+        let defaults = AppData::default();
+        assert_eq!(data.preferred_ide.as_deref(), Some("cursor"));
+        assert_eq!(data.accounts[0].last_refresh_at, None);
+        assert_eq!(data.accounts[0].auth_failures, 0);
+        assert_eq!(
+            data.accounts[0].last_error.as_deref(),
+            Some("Quota request failed (401)")
+        );
+        assert!(!data.rotation.enabled);
+        assert!(data.rotation_log.is_empty());
+        assert_eq!(
+            data.vault_auto_lock_minutes,
+            defaults.vault_auto_lock_minutes
+        );
+        assert_eq!(data.relay.port, defaults.relay.port);
+        assert_eq!(
+            data.quota_refresh.interval_minutes,
+            defaults.quota_refresh.interval_minutes
+        );
+    }
+
+    #[test]
+    fn current_layout_round_trips() {
+        let data = parse_app_data(STATE_0_2).unwrap();
+        let text = serde_json::to_string(&data).unwrap();
+        let value: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(value["schemaVersion"], json!(CURRENT_SCHEMA_VERSION));
+        assert_eq!(migrate(value.clone()).unwrap(), value);
+    }
+
+    #[test]
+    fn rejects_newer_and_invalid_versions() {
+        let newer = json!({ "schemaVersion": CURRENT_SCHEMA_VERSION + 1, "accounts": [] });
+        assert!(matches!(
+            migrate(newer),
+            Err(AppError::UnsupportedSchema { version }) if version == CURRENT_SCHEMA_VERSION + 1
+        ));
+        let invalid = json!({ "schemaVersion": "two", "accounts": [] });
+        assert!(matches!(migrate(invalid), Err(AppError::Parse(_))));
+        assert!(matches!(migrate(json!([])), Err(AppError::Parse(_))));
+    }
+}
@@ -0,0 +1,546 @@
+//! Optional loopback reverse proxy for Codex traffic. Codex is pointed at it
+//! as its base URL; every request is forwarded upstream with the credentials
+//! of the best stored account, and a 429 moves on to the next account without
+//! touching auth.json or reloading the IDE.
+
+use crate::error::AppError;
+use crate::{
+    active_proxy, ensure_fresh_tokens, events, http, lock_data, now_ts, rotation, Account,
+    SharedState,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+pub(crate) const RELAY_FAILOVER_EVENT: &str = "relay-failover";
+const MAX_HEADER_LINES: usize = 200;
+/// Larger request bodies are answered with 413 instead of being buffered.
+const MAX_BODY_BYTES: usize = 32 * 1024 * 1024;
+const DEFAULT_COOLDOWN_SECS: i64 = 60;
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Request headers that are either hop-by-hop or replaced by the relay.
+const STRIPPED_REQUEST_HEADERS: &[&str] = &[
+    "authorization",
+    "chatgpt-account-id",
+    "connection",
+    "content-length",
+    "host",
+    "keep-alive",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Response headers dropped because the relay streams the body and closes the
+/// connection instead.
+const STRIPPED_RESPONSE_HEADERS: &[&str] = &[
+    "connection",
+    "content-length",
+    "keep-alive",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+struct RelayHandle {
+    port: u16,
+    stop: Arc<AtomicBool>,
+    /// The accept loop; it owns the listener, so the port is free once it
+    /// has been joined.
+    thread: JoinHandle<()>,
+}
+
+impl RelayHandle {
+    fn shut_down(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if self.thread.join().is_err() {
+            log::warn!("Relay accept loop on port {} panicked", self.port);
+        }
+    }
+}
+
+/// Runtime state of the relay, owned by `SharedState`.
+#[derive(Default)]
+pub(crate) struct RelayRuntime {
+    handle: Option<RelayHandle>,
+    /// Accounts that recently answered 429, mapped to when they may be tried
+    /// again.
+    cooldowns: HashMap<String, i64>,
+    last_account_id: Option<String>,
+    last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RelayStatus {
+    running: bool,
+    base_url: Option<String>,
+    last_account_id: Option<String>,
+    last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RelayFailover {
+    skipped_account_ids: Vec<String>,
+    account_id: String,
+}
+
+struct RelayRequest {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+fn lock_runtime(state: &Arc<SharedState>) -> Result<MutexGuard<'_, RelayRuntime>, AppError> {
+    state
+        .relay
+        .lock()
+        .map_err(|_| AppError::Internal("State lock poisoned (relay)".to_string()))
+}
+
+pub(crate) fn status(state: &Arc<SharedState>) -> Result<RelayStatus, AppError> {
+    let runtime = lock_runtime(state)?;
+    Ok(RelayStatus {
+        running: runtime.handle.is_some(),
+        base_url: runtime
+            .handle
+            .as_ref()
+            .map(|handle| format!("http://127.0.0.1:{}", handle.port)),
+        last_account_id: runtime.last_account_id.clone(),
+        last_error: runtime.last_error.clone(),
+    })
+}
+
+/// Starts, restarts or stops the relay to match `AppData::relay`. Bind
+/// failures are returned so the settings screen can show them.
+pub(crate) fn apply_settings(state: &Arc<SharedState>) -> Result<(), AppError> {
+    let settings = {
+        let data = lock_data(state)?;
+        data.relay.clone()
+    };
+
+    let mut runtime = lock_runtime(state)?;
+    // The accept loop never takes the runtime lock, so joining it here is
+    // safe and keeps the rebind below from racing the old listener.
+    if let Some(handle) = runtime.handle.take() {
+        handle.shut_down();
+    }
+    runtime.last_error = None;
+
+    if !settings.enabled {
+        return Ok(());
+    }
+
+    let addr = format!("127.0.0.1:{}", settings.port);
+    let listener = TcpListener::bind(&addr)
+        .map_err(|e| AppError::Relay(format!("Relay failed to bind on {addr}: {e}")))?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| AppError::Relay(format!("Failed to configure relay listener: {e}")))?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let port = settings.port;
+    let shared = Arc::clone(state);
+    let upstream = settings.upstream_url.trim_end_matches('/').to_string();
+    let loop_stop = Arc::clone(&stop);
+    let thread = std::thread::spawn(move || {
+        log::info!("Relay listening on {} -> {}", addr, upstream);
+        while !loop_stop.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let shared = Arc::clone(&shared);
+                    let upstream = upstream.clone();
+                    std::thread::spawn(move || {
+                        if let Err(err) = handle_connection(stream, &shared, &upstream, port) {
+                            log::warn!("Relay request failed: {}", err);
+                            if let Ok(mut runtime) = lock_runtime(&shared) {
+                                runtime.last_error = Some(err);
+                            }
+                        }
+                    });
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(ACCEPT_POLL_INTERVAL);
+                }
+                Err(err) => log::warn!("Relay incoming connection failed: {}", err),
+            }
+        }
+        log::info!("Relay on {} stopped", addr);
+    });
+    runtime.handle = Some(RelayHandle { port, stop, thread });
+
+    Ok(())
+}
+
+/// Refuses requests that did not come from a local client talking to the
+/// relay directly. Browsers always send `Origin` on cross-origin requests,
+/// and a rebound DNS name still shows up in `Host`.
+fn check_local_client(request: &RelayRequest, port: u16) -> Result<(), String> {
+    let header = |name: &str| {
+        request
+            .headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    };
+    if header("origin").is_some() {
+        return Err("Requests from web pages are not relayed".to_string());
+    }
+    let host = header("host").unwrap_or_default().to_ascii_lowercase();
+    if host != format!("127.0.0.1:{port}") && host != format!("localhost:{port}") {
+        return Err(format!("Unexpected Host header: {host}"));
+    }
+    Ok(())
+}
+
+fn read_head(reader: &mut impl BufRead) -> Result<RelayRequest, String> {
+    let mut line = String::new();
+    reader
+        .read_line(&mut line)
+        .map_err(|e| format!("Failed to read request line: {e}"))?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or("/").to_string();
+    if method.is_empty() || !path.starts_with('/') {
+        return Err("Malformed request line".to_string());
+    }
+
+    let mut headers = Vec::new();
+    loop {
+        if headers.len() > MAX_HEADER_LINES {
+            return Err("Too many request headers".to_string());
+        }
+        line.clear();
+        reader
+            .read_line(&mut line)
+            .map_err(|e| format!("Failed to read request headers: {e}"))?;
+        let trimmed = line.trim_end();
+        if trimmed.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = trimmed.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    Ok(RelayRequest {
+        method,
+        path,
+        headers,
+        body: Vec::new(),
+    })
+}
+
+enum BodyError {
+    TooLarge,
+    Malformed(String),
+}
+
+/// Reads the body announced by the headers of `request`, refusing anything
+/// over [`MAX_BODY_BYTES`] before allocating for it.
+fn read_body(reader: &mut impl BufRead, request: &mut RelayRequest) -> Result<(), BodyError> {
+    let header = |name: &str| {
+        request
+            .headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+    };
+    let chunked =
+        header("transfer-encoding").is_some_and(|value| value.eq_ignore_ascii_case("chunked"));
+    let content_length = header("content-length");
+    let body = &mut request.body;
+
+    let mut line = String::new();
+    if chunked {
+        loop {
+            line.clear();
+            reader
+                .read_line(&mut line)
+                .map_err(|e| BodyError::Malformed(format!("Failed to read chunk size: {e}")))?;
+            let size_text = line.trim().split(';').next().unwrap_or_default();
+            let size = usize::from_str_radix(size_text, 16)
+                .map_err(|_| BodyError::Malformed("Malformed chunk size".to_string()))?;
+            if size == 0 {
+                // Skip optional trailers up to the terminating blank line.
+                loop {
+                    line.clear();
+                    let read = reader.read_line(&mut line).map_err(|e| {
+                        BodyError::Malformed(format!("Failed to read chunk trailer: {e}"))
+                    })?;
+                    if read == 0 || line.trim().is_empty() {
+                        break;
+                    }
+                }
+                break;
+            }
+            if size > MAX_BODY_BYTES - body.len() {
+                return Err(BodyError::TooLarge);
+            }
+            let start = body.len();
+            body.resize(start + size, 0);
+            reader
+                .read_exact(&mut body[start..])
+                .map_err(|e| BodyError::Malformed(format!("Failed to read chunk: {e}")))?;
+            line.clear();
+            reader.read_line(&mut line).map_err(|e| {
+                BodyError::Malformed(format!("Failed to read chunk terminator: {e}"))
+            })?;
+        }
+    } else if let Some(length) = content_length {
+        let length: usize = length
+            .parse()
+            .map_err(|_| BodyError::Malformed("Malformed Content-Length".to_string()))?;
+        if length > MAX_BODY_BYTES {
+            return Err(BodyError::TooLarge);
+        }
+        body.resize(length, 0);
+        reader
+            .read_exact(body)
+            .map_err(|e| BodyError::Malformed(format!("Failed to read request body: {e}")))?;
+    }
+    Ok(())
+}
+
+fn write_error(
+    stream: &mut TcpStream,
+    status: u16,
+    reason: &str,
+    message: &str,
+) -> Result<(), String> {
+    let body =
+        serde_json::json!({ "error": { "message": message, "type": "relay_error" } }).to_string();
+    let response = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream
+        .write_all(response.as_bytes())
+        .map_err(|e| format!("Failed to write relay response: {e}"))
+}
+
+fn write_upstream_response(
+    stream: &mut TcpStream,
+    mut response: reqwest::blocking::Response,
+) -> Result<(), String> {
+    let status = response.status();
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\n",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or("")
+    );
+    for (name, value) in response.headers() {
+        if STRIPPED_RESPONSE_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        if let Ok(value) = value.to_str() {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+    }
+    head.push_str("Connection: close\r\n\r\n");
+
+    stream
+        .write_all(head.as_bytes())
+        .map_err(|e| format!("Failed to write relay response: {e}"))?;
+    std::io::copy(&mut response, stream)
+        .map_err(|e| format!("Failed to stream upstream response: {e}"))?;
+    Ok(())
+}
+
+/// How long to leave an account alone after a 429: Retry-After when present,
+/// otherwise the nearest known quota reset.
+fn cooldown_until(response: &reqwest::blocking::Response, account: &Account) -> i64 {
+    let now = now_ts();
+    if let Some(delay) = http::parse_retry_after(response) {
+        return now + (delay.as_secs() as i64).max(1);
+    }
+
+    account
+        .quota
+        .as_ref()
+        .and_then(|quota| {
+            [quota.primary.reset_at, quota.secondary.reset_at]
+                .into_iter()
+                .flatten()
+                .filter(|reset_at| *reset_at > now)
+                .min()
+        })
+        .unwrap_or(now + DEFAULT_COOLDOWN_SECS)
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    state: &Arc<SharedState>,
+    upstream: &str,
+    port: u16,
+) -> Result<(), String> {
+    stream
+        .set_nonblocking(false)
+        .map_err(|e| format!("Failed to configure relay stream: {e}"))?;
+
+    let mut reader = BufReader::new(
+        stream
+            .try_clone()
+            .map_err(|e| format!("Failed to clone relay stream: {e}"))?,
+    );
+    let mut request = match read_head(&mut reader) {
+        Ok(request) => request,
+        Err(err) => return write_error(&mut stream, 400, "Bad Request", &err),
+    };
+    // Checked before the body is read so web pages cannot make the relay
+    // buffer anything.
+    if let Err(err) = check_local_client(&request, port) {
+        return write_error(&mut stream, 403, "Forbidden", &err);
+    }
+    match read_body(&mut reader, &mut request) {
+        Ok(()) => {}
+        Err(BodyError::TooLarge) => {
+            let message = format!("Request body exceeds {MAX_BODY_BYTES} bytes");
+            return write_error(&mut stream, 413, "Payload Too Large", &message);
+        }
+        Err(BodyError::Malformed(err)) => {
+            return write_error(&mut stream, 400, "Bad Request", &err)
+        }
+    }
+    let method = match reqwest::Method::from_bytes(request.method.as_bytes()) {
+        Ok(method) => method,
+        Err(_) => return write_error(&mut stream, 405, "Method Not Allowed", "Unsupported method"),
+    };
+
+    let (candidates, proxy) = {
+        let data = match lock_data(state) {
+            Ok(data) => data,
+            Err(err) => {
+                return write_error(&mut stream, 503, "Service Unavailable", &err.to_string())
+            }
+        };
+        (rotation::relay_candidates(&data), active_proxy(&data))
+    };
+
+    let now = now_ts();
+    let candidates = {
+        let mut runtime = lock_runtime(state)?;
+        runtime.cooldowns.retain(|_, until| *until > now);
+        let ready: Vec<String> = candidates
+            .iter()
+            .filter(|id| !runtime.cooldowns.contains_key(*id))
+            .cloned()
+            .collect();
+        // When every account is cooling down, still try them rather than
+        // failing outright; upstream knows better than our estimate.
+        if ready.is_empty() {
+            candidates
+        } else {
+            ready
+        }
+    };
+
+    if candidates.is_empty() {
+        return write_error(
+            &mut stream,
+            503,
+            "Service Unavailable",
+            "No usable account is stored",
+        );
+    }
+
+    // Responses stream for as long as the model keeps generating, so no
+    // request timeout is set; the shared client only bounds the connect.
+    let client = match http::shared_client(proxy.as_ref()) {
+        Ok(client) => client,
+        Err(err) => return write_error(&mut stream, 502, "Bad Gateway", &err),
+    };
+    let url = format!("{upstream}{}", request.path);
+
+    let mut skipped = Vec::new();
+    let mut last_limited = None;
+    for (index, account_id) in candidates.iter().enumerate() {
+        let account = match ensure_fresh_tokens(state, account_id) {
+            Ok((account, _)) => account,
+            Err(err) => {
+                log::warn!("Relay skipped account {}: {}", account_id, err);
+                continue;
+            }
+        };
+
+        let mut upstream_request = client
+            .request(method.clone(), &url)
+            .header(
+                "Authorization",
+                format!("Bearer {}", account.tokens.access_token),
+            )
+            .body(request.body.clone());
+        for (name, value) in &request.headers {
+            if !STRIPPED_REQUEST_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+                upstream_request = upstream_request.header(name.as_str(), value.as_str());
+            }
+        }
+        if let Some(chatgpt_account_id) = account.account_id.as_deref() {
+            if !chatgpt_account_id.trim().is_empty() {
+                upstream_request =
+                    upstream_request.header("ChatGPT-Account-Id", chatgpt_account_id);
+            }
+        }
+
+        let response = match upstream_request.send() {
+            Ok(response) => response,
+            Err(err) => {
+                let message = format!("Upstream request failed: {err}");
+                return write_error(&mut stream, 502, "Bad Gateway", &message);
+            }
+        };
+
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let until = cooldown_until(&response, &account);
+            lock_runtime(state)?
+                .cooldowns
+                .insert(account_id.clone(), until);
+            log::info!(
+                "Relay: account {} is rate limited until {}, trying the next one",
+                account_id,
+                until
+            );
+            skipped.push(account_id.clone());
+            if index + 1 < candidates.len() {
+                continue;
+            }
+            last_limited = Some(response);
+            break;
+        }
+
+        {
+            let mut runtime = lock_runtime(state)?;
+            runtime.last_account_id = Some(account_id.clone());
+            runtime.last_error = None;
+        }
+        if !skipped.is_empty() {
+            let event = RelayFailover {
+                skipped_account_ids: skipped,
+                account_id: account_id.clone(),
+            };
+            events::emit(state, RELAY_FAILOVER_EVENT, event);
+        }
+        return write_upstream_response(&mut stream, response);
+    }
+
+    match last_limited {
+        Some(response) => write_upstream_response(&mut stream, response),
+        None => write_error(
+            &mut stream,
+            503,
+            "Service Unavailable",
+            "No stored account could be prepared for the request",
+        ),
+    }
+}
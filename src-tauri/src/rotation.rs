@@ -0,0 +1,334 @@
+use crate::health::AccountStatus;
+use crate::{
+    events, lock_data, now_ts, save_app_data, switch_account, Account, AppData, QuotaWindow,
+    RotationEvent, RotationPolicy, RotationSettings, SharedState,
+};
+use std::sync::Arc;
+
+pub(crate) const ACCOUNT_ROTATED_EVENT: &str = "account-rotated";
+const ROTATION_LOG_LIMIT: usize = 100;
+
+fn remaining_percent(window: &QuotaWindow) -> Option<f64> {
+    window
+        .used_percent
+        .map(|used| (100.0 - used).clamp(0.0, 100.0))
+}
+
+/// Explains why the account has dropped below the configured thresholds, or
+/// `None` while it still has enough quota left.
+fn exhaustion_reason(account: &Account, settings: &RotationSettings) -> Option<String> {
+    let quota = account.quota.as_ref()?;
+
+    if let Some(left) = remaining_percent(&quota.primary) {
+        if left < settings.primary_threshold {
+            return Some(format!(
+                "5-hour quota at {left:.0}% remaining (threshold {:.0}%)",
+                settings.primary_threshold
+            ));
+        }
+    }
+
+    if let Some(left) = remaining_percent(&quota.secondary) {
+        if left < settings.secondary_threshold {
+            return Some(format!(
+                "weekly quota at {left:.0}% remaining (threshold {:.0}%)",
+                settings.secondary_threshold
+            ));
+        }
+    }
+
+    None
+}
+
+/// Explains why the account cannot be used at all right now, regardless of
+/// its quota.
+fn unavailable_reason(account: &Account) -> Option<String> {
+    let health = account.health.current(now_ts());
+    let code = health
+        .http_status
+        .map(|status| format!(" (HTTP {status})"))
+        .unwrap_or_default();
+    match health.status {
+        AccountStatus::NeedsReauth => Some(format!("account needs to sign in again{code}")),
+        AccountStatus::Deactivated => Some(format!("account is deactivated{code}")),
+        AccountStatus::RateLimited => Some(format!("account is rate limited{code}")),
+        _ => None,
+    }
+}
+
+fn is_eligible(account: &Account, settings: &RotationSettings) -> bool {
+    account.health.is_usable(now_ts())
+        && account.quota.is_some()
+        && exhaustion_reason(account, settings).is_none()
+}
+
+/// Smallest remaining share across both windows; a window the plan does not
+/// report counts as untouched.
+fn headroom(account: &Account) -> f64 {
+    let Some(quota) = account.quota.as_ref() else {
+        return 0.0;
+    };
+    let primary = remaining_percent(&quota.primary).unwrap_or(100.0);
+    let secondary = remaining_percent(&quota.secondary).unwrap_or(100.0);
+    primary.min(secondary)
+}
+
+fn next_reset(account: &Account) -> Option<i64> {
+    let quota = account.quota.as_ref()?;
+    quota.primary.reset_at.or(quota.secondary.reset_at)
+}
+
+fn pick_target<'a>(data: &'a AppData, active_id: &str) -> Option<&'a Account> {
+    let settings = &data.rotation;
+    let eligible = |account: &&Account| account.id != active_id && is_eligible(account, settings);
+
+    match settings.policy {
+        RotationPolicy::MostRemaining => data
+            .accounts
+            .iter()
+            .filter(eligible)
+            .max_by(|a, b| headroom(a).total_cmp(&headroom(b))),
+        RotationPolicy::SoonestReset => data
+            .accounts
+            .iter()
+            .filter(eligible)
+            .min_by_key(|account| next_reset(account).unwrap_or(i64::MAX)),
+        RotationPolicy::RoundRobin => {
+            let start = data
+                .accounts
+                .iter()
+                .position(|account| account.id == active_id)
+                .map_or(0, |index| index + 1);
+            data.accounts
+                .iter()
+                .cycle()
+                .skip(start)
+                .take(data.accounts.len())
+                .find(eligible)
+        }
+    }
+}
+
+/// Accounts to try for a relayed request, best first: the active account while
+/// it is above the thresholds, then other eligible accounts by headroom, then
+/// the remaining accounts in usable health.
+pub(crate) fn relay_candidates(data: &AppData) -> Vec<String> {
+    let settings = &data.rotation;
+    let active_id = data.active_account_id.as_deref();
+    let now = now_ts();
+
+    let mut eligible: Vec<&Account> = data
+        .accounts
+        .iter()
+        .filter(|account| is_eligible(account, settings))
+        .collect();
+    eligible.sort_by(|a, b| {
+        let a_active = Some(a.id.as_str()) == active_id;
+        let b_active = Some(b.id.as_str()) == active_id;
+        b_active
+            .cmp(&a_active)
+            .then_with(|| headroom(b).total_cmp(&headroom(a)))
+    });
+
+    let mut rest: Vec<&Account> = data
+        .accounts
+        .iter()
+        .filter(|account| account.health.is_usable(now) && !is_eligible(account, settings))
+        .collect();
+    rest.sort_by(|a, b| headroom(b).total_cmp(&headroom(a)));
+
+    eligible
+        .into_iter()
+        .chain(rest)
+        .map(|account| account.id.clone())
+        .collect()
+}
+
+/// Checks the active account against the rotation thresholds and, when it runs
+/// low or becomes unusable (signed out, deactivated, rate limited), switches to
+/// the account chosen by the configured policy.
+pub(crate) fn maybe_rotate(state: &Arc<SharedState>) -> Result<Option<RotationEvent>, String> {
+    let (from_id, to_id, reason, policy) = {
+        let data = lock_data(state)?;
+        if !data.rotation.enabled {
+            return Ok(None);
+        }
+
+        let Some(active_id) = data.active_account_id.clone() else {
+            return Ok(None);
+        };
+        let Some(active) = data.accounts.iter().find(|a| a.id == active_id) else {
+            return Ok(None);
+        };
+        let reason =
+            unavailable_reason(active).or_else(|| exhaustion_reason(active, &data.rotation));
+        let Some(reason) = reason else {
+            return Ok(None);
+        };
+        let Some(target) = pick_target(&data, &active_id) else {
+            log::info!(
+                "Active account should be rotated ({}), but no other account is eligible",
+                reason
+            );
+            return Ok(None);
+        };
+
+        (active_id, target.id.clone(), reason, data.rotation.policy)
+    };
+
+    let response = switch_account(state, to_id.clone(), None)?;
+
+    let event = RotationEvent {
+        at: now_ts(),
+        from_account_id: Some(from_id),
+        to_account_id: to_id,
+        policy,
+        reason,
+        ide: response.ide,
+        reloaded: response.reloaded,
+    };
+
+    let mut data = lock_data(state)?;
+    data.rotation_log.push(event.clone());
+    if data.rotation_log.len() > ROTATION_LOG_LIMIT {
+        let excess = data.rotation_log.len() - ROTATION_LOG_LIMIT;
+        data.rotation_log.drain(..excess);
+    }
+    save_app_data(&mut data)?;
+
+    Ok(Some(event))
+}
+
+/// Runs [`maybe_rotate`] and tells the UI about any switch it made.
+pub(crate) fn rotate_and_notify(state: &Arc<SharedState>) {
+    match maybe_rotate(state) {
+        Ok(Some(event)) => {
+            log::info!(
+                "Rotated active account to {} ({})",
+                event.to_account_id,
+                event.reason
+            );
+            events::emit(state, ACCOUNT_ROTATED_EVENT, event);
+        }
+        Ok(None) => {}
+        Err(err) => log::warn!("Automatic account rotation failed: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::AccountHealth;
+    use crate::{QuotaInfo, Tokens};
+
+    fn account(id: &str, used_percent: f64, reset_at: i64) -> Account {
+        Account {
+            id: id.to_string(),
+            email: None,
+            account_id: None,
+            tokens: Tokens::default(),
+            quota: Some(QuotaInfo {
+                primary: QuotaWindow {
+                    used_percent: Some(used_percent),
+                    reset_at: Some(reset_at),
+                    ..QuotaWindow::default()
+                },
+                ..QuotaInfo::default()
+            }),
+            created_at: 0,
+            last_login_at: 0,
+            last_error: None,
+            last_refresh_at: None,
+            auth_failures: 0,
+            health: AccountHealth::default(),
+        }
+    }
+
+    fn data(policy: RotationPolicy, accounts: Vec<Account>) -> AppData {
+        AppData {
+            active_account_id: accounts.first().map(|account| account.id.clone()),
+            accounts,
+            rotation: RotationSettings {
+                policy,
+                ..RotationSettings::default()
+            },
+            ..AppData::default()
+        }
+    }
+
+    fn picked(data: &AppData, active_id: &str) -> Option<String> {
+        pick_target(data, active_id).map(|account| account.id.clone())
+    }
+
+    #[test]
+    fn most_remaining_picks_largest_headroom() {
+        let data = data(
+            RotationPolicy::MostRemaining,
+            vec![
+                account("a", 95.0, 100),
+                account("b", 50.0, 100),
+                account("c", 20.0, 100),
+            ],
+        );
+        assert_eq!(picked(&data, "a").as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn soonest_reset_picks_earliest_reset() {
+        let data = data(
+            RotationPolicy::SoonestReset,
+            vec![
+                account("a", 95.0, 100),
+                account("b", 20.0, 300),
+                account("c", 50.0, 200),
+            ],
+        );
+        assert_eq!(picked(&data, "a").as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn round_robin_wraps_around() {
+        let data = data(
+            RotationPolicy::RoundRobin,
+            vec![
+                account("a", 50.0, 100),
+                account("b", 50.0, 100),
+                account("c", 95.0, 100),
+            ],
+        );
+        assert_eq!(picked(&data, "a").as_deref(), Some("b"));
+        // "c" is below the threshold, so the search wraps to the start.
+        assert_eq!(picked(&data, "b").as_deref(), Some("a"));
+        assert_eq!(picked(&data, "c").as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn skips_accounts_that_cannot_be_used() {
+        let mut signed_out = account("b", 0.0, 100);
+        signed_out.health.set(AccountStatus::NeedsReauth, Some(401));
+        let data = data(
+            RotationPolicy::MostRemaining,
+            vec![account("a", 95.0, 100), signed_out],
+        );
+        assert_eq!(picked(&data, "a"), None);
+    }
+
+    #[test]
+    fn relay_candidates_put_active_first_then_headroom() {
+        let mut deactivated = account("e", 0.0, 100);
+        deactivated
+            .health
+            .set(AccountStatus::Deactivated, Some(403));
+        let data = data(
+            RotationPolicy::MostRemaining,
+            vec![
+                account("a", 60.0, 100),
+                account("b", 95.0, 100),
+                account("c", 10.0, 100),
+                account("d", 30.0, 100),
+                deactivated,
+            ],
+        );
+        assert_eq!(relay_candidates(&data), vec!["a", "c", "d", "b"]);
+    }
+}
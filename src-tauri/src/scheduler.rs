@@ -0,0 +1,324 @@
+//! Background quota refresh. A single thread owned by `SharedState` refreshes
+//! every account on the configured interval (with jitter) and once more just
+//! after a window's `reset_at`, so quotas stay current while the window is
+//! hidden. Accounts that keep failing back off instead of being polled, and
+//! accounts waiting for a new login are left alone.
+
+use crate::error::AppError;
+use crate::health::AccountStatus;
+use crate::view::AppDataView;
+use crate::{
+    auth_paused, events, lock_data, lock_quota_in_flight, now_ts, refresh_quotas_where, rotation,
+    vault, Account, QuotaRefreshSettings, SharedState,
+};
+use rand::Rng;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+pub(crate) const QUOTAS_REFRESHED_EVENT: &str = "quotas-refreshed";
+
+/// First refresh after launch, so the table is current shortly after startup.
+const STARTUP_DELAY_SECS: i64 = 15;
+/// Give the backend a moment to roll the window over before asking again.
+const RESET_GRACE_SECS: i64 = 20;
+/// How often a disabled scheduler or a locked vault is looked at again.
+const IDLE_RECHECK_SECS: i64 = 60;
+const MIN_INTERVAL_SECS: i64 = 30;
+const BACKOFF_BASE_SECS: i64 = 2 * 60;
+const BACKOFF_MAX_SECS: i64 = 60 * 60;
+
+#[derive(Debug, Clone, Copy)]
+struct Backoff {
+    failures: u32,
+    retry_at: i64,
+}
+
+#[derive(Default)]
+struct SchedulerInner {
+    started: bool,
+    /// Set by [`wake`]; the thread re-plans instead of refreshing.
+    replan: bool,
+    next_full_at: Option<i64>,
+    last_run_at: Option<i64>,
+    backoff: HashMap<String, Backoff>,
+}
+
+#[derive(Default)]
+pub(crate) struct Scheduler {
+    inner: Mutex<SchedulerInner>,
+    signal: Condvar,
+}
+
+impl Scheduler {
+    fn lock(&self) -> MutexGuard<'_, SchedulerInner> {
+        // The inner state is plain bookkeeping; a panic mid-update cannot
+        // leave it in a shape that is unsafe to keep using.
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AccountBackoff {
+    pub account_id: String,
+    pub failures: u32,
+    pub retry_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SchedulerStatus {
+    pub enabled: bool,
+    pub interval_minutes: u32,
+    pub last_run_at: Option<i64>,
+    pub next_run_at: Option<i64>,
+    pub backoff: Vec<AccountBackoff>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct QuotasRefreshed {
+    refreshed_account_ids: Vec<String>,
+    failed_account_ids: Vec<String>,
+    /// Still running when the refresh deadline passed.
+    pending_account_ids: Vec<String>,
+    skipped_account_ids: Vec<String>,
+    next_run_at: Option<i64>,
+    state: AppDataView,
+}
+
+fn jittered_interval(settings: &QuotaRefreshSettings) -> i64 {
+    let base = i64::from(settings.interval_minutes.max(1)) * 60;
+    let jitter = i64::from(settings.jitter_seconds);
+    let offset = if jitter > 0 {
+        rand::thread_rng().gen_range(-jitter..=jitter)
+    } else {
+        0
+    };
+    (base + offset).max(MIN_INTERVAL_SECS)
+}
+
+fn backoff_delay(failures: u32) -> i64 {
+    let exponent = failures.saturating_sub(1).min(5);
+    (BACKOFF_BASE_SECS << exponent).min(BACKOFF_MAX_SECS)
+}
+
+/// Earliest window reset the stored quota has not caught up with yet.
+fn pending_reset(account: &Account) -> Option<i64> {
+    let quota = account.quota.as_ref()?;
+    [&quota.primary, &quota.secondary]
+        .iter()
+        .filter_map(|window| window.reset_at)
+        .filter(|reset_at| *reset_at > quota.fetched_at)
+        .min()
+}
+
+/// Accounts only a new login can fix; polling them repeats the same failure.
+fn awaits_sign_in(account: &Account) -> bool {
+    auth_paused(account)
+        || matches!(
+            account.health.status,
+            AccountStatus::NeedsReauth | AccountStatus::Deactivated
+        )
+}
+
+/// Makes the thread pick up changed settings or a freshly unlocked vault. The
+/// interval restarts from now.
+pub(crate) fn wake(state: &Arc<SharedState>) {
+    let mut inner = state.scheduler.lock();
+    inner.replan = true;
+    inner.next_full_at = None;
+    state.scheduler.signal.notify_all();
+}
+
+pub(crate) fn status(state: &Arc<SharedState>) -> Result<SchedulerStatus, AppError> {
+    let settings = lock_data(state)?.quota_refresh.clone();
+    let inner = state.scheduler.lock();
+
+    let mut backoff: Vec<AccountBackoff> = inner
+        .backoff
+        .iter()
+        .map(|(account_id, entry)| AccountBackoff {
+            account_id: account_id.clone(),
+            failures: entry.failures,
+            retry_at: entry.retry_at,
+        })
+        .collect();
+    backoff.sort_by_key(|entry| entry.retry_at);
+
+    Ok(SchedulerStatus {
+        enabled: settings.enabled,
+        interval_minutes: settings.interval_minutes,
+        last_run_at: inner.last_run_at,
+        next_run_at: inner.next_full_at.filter(|_| settings.enabled),
+        backoff,
+    })
+}
+
+/// Returns the unix time the thread should next wake up at.
+fn plan(state: &Arc<SharedState>) -> i64 {
+    let now = now_ts();
+    let Ok(data) = lock_data(state) else {
+        return now + IDLE_RECHECK_SECS;
+    };
+    let mut inner = state.scheduler.lock();
+    if !data.quota_refresh.enabled {
+        inner.next_full_at = None;
+        return now + IDLE_RECHECK_SECS;
+    }
+
+    let next_full = match inner.next_full_at {
+        Some(at) => at,
+        None => {
+            let at = match inner.last_run_at {
+                Some(_) => now + jittered_interval(&data.quota_refresh),
+                None => now + STARTUP_DELAY_SECS,
+            };
+            inner.next_full_at = Some(at);
+            at
+        }
+    };
+
+    // Accounts still being fetched would otherwise wake the thread right away.
+    let in_flight = lock_quota_in_flight(state)
+        .map(|set| set.clone())
+        .unwrap_or_default();
+    data.accounts
+        .iter()
+        .filter(|account| !in_flight.contains(&account.id) && !awaits_sign_in(account))
+        .filter_map(|account| {
+            let reset_due = pending_reset(account)? + RESET_GRACE_SECS;
+            let retry_at = inner.backoff.get(&account.id).map_or(0, |b| b.retry_at);
+            Some(reset_due.max(retry_at))
+        })
+        .fold(next_full, i64::min)
+}
+
+/// Blocks until `at`. Returns `false` when woken early to re-plan.
+fn wait_until(state: &Arc<SharedState>, at: i64) -> bool {
+    let mut inner = state.scheduler.lock();
+    loop {
+        if inner.replan {
+            inner.replan = false;
+            return false;
+        }
+        let now = now_ts();
+        if now >= at {
+            return true;
+        }
+        let timeout = Duration::from_secs((at - now) as u64);
+        inner = match state.scheduler.signal.wait_timeout(inner, timeout) {
+            Ok((guard, _)) => guard,
+            Err(poisoned) => poisoned.into_inner().0,
+        };
+    }
+}
+
+fn run_due(state: &Arc<SharedState>) -> Result<(), String> {
+    let now = now_ts();
+    let (due, skipped) = {
+        let data = lock_data(state)?;
+        if !data.quota_refresh.enabled {
+            return Ok(());
+        }
+        let mut inner = state.scheduler.lock();
+        let full = inner.next_full_at.map_or(true, |at| now >= at);
+        if full {
+            inner.next_full_at = Some(now + jittered_interval(&data.quota_refresh));
+        }
+        inner
+            .backoff
+            .retain(|id, _| data.accounts.iter().any(|account| &account.id == id));
+
+        let mut due = Vec::new();
+        let mut skipped = Vec::new();
+        for account in &data.accounts {
+            let reset_due = pending_reset(account).is_some_and(|at| at + RESET_GRACE_SECS <= now);
+            if !full && !reset_due {
+                continue;
+            }
+            let backing_off = inner
+                .backoff
+                .get(&account.id)
+                .is_some_and(|entry| entry.retry_at > now);
+            if backing_off || awaits_sign_in(account) {
+                skipped.push(account.id.clone());
+            } else {
+                due.push(account.id.clone());
+            }
+        }
+        (due, skipped)
+    };
+
+    if due.is_empty() {
+        return Ok(());
+    }
+
+    let report = match refresh_quotas_where(state, |account| due.contains(&account.id)) {
+        Ok(report) => report,
+        Err(err) => {
+            // Back the whole batch off, otherwise a pending reset would retry
+            // in a tight loop.
+            record_outcomes(state, now, &[], &due);
+            return Err(err.into());
+        }
+    };
+    record_outcomes(state, now, &report.refreshed, &report.failed);
+
+    rotation::rotate_and_notify(state);
+
+    let payload = QuotasRefreshed {
+        refreshed_account_ids: report.refreshed,
+        failed_account_ids: report.failed,
+        pending_account_ids: report.pending,
+        skipped_account_ids: skipped,
+        next_run_at: state.scheduler.lock().next_full_at,
+        state: AppDataView::from(&*lock_data(state)?),
+    };
+    events::emit(state, QUOTAS_REFRESHED_EVENT, payload);
+    Ok(())
+}
+
+/// Clears backoff for accounts that refreshed and extends it for failures.
+fn record_outcomes(state: &Arc<SharedState>, now: i64, refreshed: &[String], failed: &[String]) {
+    let mut inner = state.scheduler.lock();
+    inner.last_run_at = Some(now);
+
+    for account_id in refreshed {
+        inner.backoff.remove(account_id);
+    }
+    for account_id in failed {
+        let entry = inner.backoff.entry(account_id.clone()).or_insert(Backoff {
+            failures: 0,
+            retry_at: now,
+        });
+        entry.failures += 1;
+        entry.retry_at = now + backoff_delay(entry.failures);
+    }
+}
+
+pub(crate) fn start(state: Arc<SharedState>) {
+    {
+        let mut inner = state.scheduler.lock();
+        if inner.started {
+            return;
+        }
+        inner.started = true;
+    }
+
+    std::thread::spawn(move || loop {
+        let at = plan(&state);
+        if !wait_until(&state, at) {
+            continue;
+        }
+        if let Err(err) = run_due(&state) {
+            if err != vault::VAULT_LOCKED_ERROR {
+                log::warn!("Scheduled quota refresh failed: {}", err);
+            }
+        }
+    });
+}
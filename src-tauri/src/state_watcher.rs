@@ -0,0 +1,72 @@
+//! Picks up changes other processes make to `state.json`. The file is polled
+//! rather than watched through OS notifications, which behave differently
+//! across platforms and editors; a change shows up within [`POLL_INTERVAL`].
+
+use crate::error::AppError;
+use crate::{app_storage_file, events, lock_data, relay, scheduler, storage, vault, SharedState};
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+pub(crate) const STATE_CHANGED_EVENT: &str = "state-changed";
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+type FileFingerprint = Option<(SystemTime, u64)>;
+
+fn state_fingerprint() -> FileFingerprint {
+    let path = app_storage_file().ok()?;
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+/// Merges a save made by another process into the shared state. Returns
+/// whether anything changed.
+fn reload_state(state: &Arc<SharedState>) -> Result<bool, AppError> {
+    let (auto_lock_minutes, relay_changed) = {
+        let mut data = lock_data(state)?;
+        let relay_before = data.relay.clone();
+        if !storage::reload(&mut data)? {
+            return Ok(false);
+        }
+        (data.vault_auto_lock_minutes, data.relay != relay_before)
+    };
+
+    vault::set_idle_timeout(auto_lock_minutes);
+    // A restart rebinds the relay port, so only do it when the relay settings
+    // themselves changed.
+    if relay_changed {
+        if let Err(err) = relay::apply_settings(state) {
+            log::warn!("Failed to start relay: {}", err);
+        }
+    }
+    scheduler::wake(state);
+    Ok(true)
+}
+
+/// Polls state.json for saves made by the CLI, another instance or a text
+/// editor and merges them in, notifying the UI through `state-changed`. Our
+/// own saves leave the file equal to what this process last wrote, so they
+/// are skipped without a merge.
+pub(crate) fn start_state_watcher(state: Arc<SharedState>) {
+    std::thread::spawn(move || {
+        let mut last_seen = state_fingerprint();
+
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+
+            let current = state_fingerprint();
+            if current == last_seen {
+                continue;
+            }
+            last_seen = current;
+            match reload_state(&state) {
+                Ok(true) => {
+                    log::info!("state.json changed externally, reloaded");
+                    events::emit(&state, STATE_CHANGED_EVENT, ());
+                }
+                Ok(false) | Err(AppError::VaultLocked) => {}
+                Err(err) => log::warn!("Failed to reload state.json: {}", err),
+            }
+        }
+    });
+}
@@ -0,0 +1,708 @@
+//! Crash-safe persistence for `state.json`.
+//!
+//! Saves go to a temp file that is fsynced and renamed over the original, so a
+//! crash leaves either the old or the new file, never a truncated one. Before
+//! a save replaces the file, the previous version is copied into `backups/` at
+//! most every [`BACKUP_MIN_INTERVAL_SECS`], keeping the newest [`BACKUP_KEEP`].
+//!
+//! A state file that cannot be parsed is moved aside rather than overwritten,
+//! and the newest backup that still reads is put in its place.
+//!
+//! The GUI, the `cam` CLI and other tools may all write the file. Writers take
+//! `state.json.lock` (created exclusively, removed when done) around each
+//! read-check-write, and every save bumps `AppData.revision`. A save that
+//! finds the file changed since it was loaded merges its changes into what is
+//! on disk instead of overwriting it; see [`merge`]. Editors and scripts
+//! rarely bump the revision, so the content is compared as well.
+
+use crate::error::AppError;
+use crate::AppData;
+use crate::{
+    app_storage_dir, app_storage_file, load_app_data, migrate, now_ts, parse_app_data, vault,
+};
+use chrono::{NaiveDateTime, Utc};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const BACKUP_DIR: &str = "backups";
+const BACKUP_PREFIX: &str = "state-";
+const BACKUP_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const BACKUP_KEEP: usize = 10;
+const BACKUP_MIN_INTERVAL_SECS: i64 = 15 * 60;
+const QUARANTINE_PREFIX: &str = "state.corrupt-";
+const LOCK_FILE: &str = "state.json.lock";
+/// How long a save waits for another writer before giving up.
+const LOCK_WAIT: Duration = Duration::from_secs(5);
+const LOCK_RETRY: Duration = Duration::from_millis(25);
+/// A lock file older than this was left behind by a writer that died.
+const LOCK_STALE_AFTER: Duration = Duration::from_secs(30);
+/// Entries of arrays whose objects carry this key are merged one by one.
+const MERGE_ID_KEY: &str = "id";
+
+/// Newest backup time, cached so saves do not list the directory each time.
+static LAST_BACKUP_AT: Mutex<Option<i64>> = Mutex::new(None);
+static RECOVERY: Mutex<Option<RecoveryNotice>> = Mutex::new(None);
+/// The state as last read from or written to disk by this process: the common
+/// ancestor when merging with changes made elsewhere.
+static SYNCED: Mutex<Option<Value>> = Mutex::new(None);
+/// Set at startup when the state file was written by a newer build. The app
+/// then runs read-only on empty defaults and reports this instead.
+static UNSUPPORTED: Mutex<Option<AppError>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct StateBackup {
+    pub name: String,
+    pub created_at: i64,
+    pub size: u64,
+    /// Sealed backups can only be checked once the vault is unlocked.
+    pub sealed: bool,
+    /// Number of accounts, when the backup is plaintext and parses.
+    pub accounts: Option<usize>,
+}
+
+/// What happened when the state file failed to load at startup.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RecoveryNotice {
+    pub at: i64,
+    pub error: String,
+    /// Where the unreadable file was moved.
+    pub quarantined_as: Option<String>,
+    /// The backup now in use, or `None` when no usable backup was found.
+    pub restored_from: Option<String>,
+}
+
+/// Replaces `path` with `contents` so readers only ever see a complete file.
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format!("Invalid file path: {}", path.display()))?;
+    let mut tmp_name = file_name.to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+
+    let mut file =
+        File::create(&tmp).map_err(|e| format!("Failed to create {}: {e}", tmp.display()))?;
+    file.write_all(contents)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| format!("Failed to write {}: {e}", tmp.display()))?;
+    drop(file);
+
+    fs::rename(&tmp, path).map_err(|e| format!("Failed to replace {}: {e}", path.display()))?;
+
+    // Persist the rename itself; Windows has no directory handles to sync.
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(())
+}
+
+fn backup_dir() -> Result<PathBuf, AppError> {
+    let dir = app_storage_dir()?.join(BACKUP_DIR);
+    fs::create_dir_all(&dir)
+        .map_err(|e| AppError::Storage(format!("Failed to create backup directory: {e}")))?;
+    Ok(dir)
+}
+
+fn backup_time(name: &str) -> Option<i64> {
+    let stamp = name.strip_prefix(BACKUP_PREFIX)?.strip_suffix(".json")?;
+    NaiveDateTime::parse_from_str(stamp, BACKUP_TIME_FORMAT)
+        .ok()
+        .map(|time| time.and_utc().timestamp())
+}
+
+/// Backup file names with their timestamps, newest first.
+fn backup_files() -> Result<Vec<(String, i64)>, AppError> {
+    let entries = fs::read_dir(backup_dir()?)
+        .map_err(|e| AppError::Storage(format!("Failed to list backups: {e}")))?;
+    let mut files: Vec<(String, i64)> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let at = backup_time(&name)?;
+            Some((name, at))
+        })
+        .collect();
+    files.sort_by_key(|(_, at)| std::cmp::Reverse(*at));
+    Ok(files)
+}
+
+/// Copies the current state file into `backups/` and prunes old copies.
+/// Unless `force` is set, nothing happens while the newest backup is recent.
+pub(crate) fn backup_state(force: bool) -> Result<(), AppError> {
+    let path = app_storage_file()?;
+    if !path.exists() {
+        return Ok(());
+    }
+
+    let now = now_ts();
+    let mut last = LAST_BACKUP_AT
+        .lock()
+        .map_err(|_| AppError::Internal("Backup lock poisoned".to_string()))?;
+    if last.is_none() {
+        *last = Some(backup_files()?.first().map_or(0, |(_, at)| *at));
+    }
+    if !force && last.is_some_and(|at| now - at < BACKUP_MIN_INTERVAL_SECS) {
+        return Ok(());
+    }
+
+    let stamp = Utc::now().format(BACKUP_TIME_FORMAT);
+    let target = backup_dir()?.join(format!("{BACKUP_PREFIX}{stamp}.json"));
+    fs::copy(&path, &target)
+        .map_err(|e| AppError::Storage(format!("Failed to back up state file: {e}")))?;
+    *last = Some(now);
+    drop(last);
+
+    for (name, _) in backup_files()?.into_iter().skip(BACKUP_KEEP) {
+        if let Err(err) = fs::remove_file(backup_dir()?.join(&name)) {
+            log::warn!("Failed to prune backup {}: {}", name, err);
+        }
+    }
+    Ok(())
+}
+
+fn read_backup(name: &str) -> Result<String, AppError> {
+    if backup_time(name).is_none() || name.contains(['/', '\\']) {
+        return Err(AppError::InvalidInput(format!("Unknown backup: {name}")));
+    }
+    fs::read_to_string(backup_dir()?.join(name))
+        .map_err(|e| AppError::Storage(format!("Failed to read backup {name}: {e}")))
+}
+
+pub(crate) fn list_backups() -> Result<Vec<StateBackup>, AppError> {
+    let dir = backup_dir()?;
+    let mut backups = Vec::new();
+    for (name, created_at) in backup_files()? {
+        let Ok(text) = fs::read_to_string(dir.join(&name)) else {
+            continue;
+        };
+        let sealed = vault::is_sealed(&text);
+        backups.push(StateBackup {
+            size: text.len() as u64,
+            accounts: (!sealed)
+                .then(|| parse_app_data(&text).ok())
+                .flatten()
+                .map(|data| data.accounts.len()),
+            name,
+            created_at,
+            sealed,
+        });
+    }
+    Ok(backups)
+}
+
+/// Decodes a backup with the current vault session.
+pub(crate) fn load_backup(name: &str) -> Result<AppData, AppError> {
+    let text = read_backup(name)?;
+    let text = vault::decode_state_text(text).map_err(AppError::vault)?;
+    parse_app_data(&text)
+}
+
+/// Moves the unreadable state file aside so the next save cannot destroy it.
+fn quarantine(path: &Path) -> Result<PathBuf, AppError> {
+    let stamp = Utc::now().format(BACKUP_TIME_FORMAT);
+    let target = path.with_file_name(format!("{QUARANTINE_PREFIX}{stamp}.json"));
+    fs::rename(path, &target)
+        .map_err(|e| AppError::Storage(format!("Failed to quarantine state file: {e}")))?;
+    Ok(target)
+}
+
+/// Seals the plaintext backups and quarantined state files left from before
+/// the vault was enabled, so tokens are not kept in the clear next to the
+/// sealed state. A copy that cannot be sealed is deleted instead. Does nothing
+/// while the vault is disabled or locked.
+pub(crate) fn seal_plaintext_copies() -> Result<(), AppError> {
+    if !matches!(vault::status(), Ok(status) if status.enabled && !status.locked) {
+        return Ok(());
+    }
+
+    let mut paths: Vec<PathBuf> = backup_files()?
+        .into_iter()
+        .map(|(name, _)| backup_dir().map(|dir| dir.join(name)))
+        .collect::<Result<_, _>>()?;
+    let storage_dir = app_storage_dir()?;
+    let entries = fs::read_dir(&storage_dir)
+        .map_err(|e| AppError::Storage(format!("Failed to list data directory: {e}")))?;
+    paths.extend(entries.filter_map(Result::ok).filter_map(|entry| {
+        let name = entry.file_name().into_string().ok()?;
+        name.starts_with(QUARANTINE_PREFIX)
+            .then(|| storage_dir.join(name))
+    }));
+
+    for path in paths {
+        let Ok(text) = fs::read_to_string(&path) else {
+            continue;
+        };
+        if vault::is_sealed(&text) {
+            continue;
+        }
+        let sealed = vault::encode_state_text(text)
+            .and_then(|sealed| write_atomic(&path, sealed.as_bytes()));
+        if let Err(err) = sealed {
+            log::warn!("Failed to seal {}, deleting it: {}", path.display(), err);
+            if let Err(err) = fs::remove_file(&path) {
+                log::warn!("Failed to delete {}: {}", path.display(), err);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The newest backup that is either sealed or parses as plaintext.
+fn newest_usable_backup() -> Result<Option<(String, String)>, AppError> {
+    for (name, _) in backup_files()? {
+        let Ok(text) = read_backup(&name) else {
+            continue;
+        };
+        if vault::is_sealed(&text) || parse_app_data(&text).is_ok() {
+            return Ok(Some((name, text)));
+        }
+    }
+    Ok(None)
+}
+
+fn recover(error: AppError) -> Result<AppData, AppError> {
+    let path = app_storage_file()?;
+    let quarantined = quarantine(&path)?;
+    log::warn!(
+        "State file could not be loaded ({}); moved it to {}",
+        error,
+        quarantined.display()
+    );
+
+    let restored = newest_usable_backup()?;
+    if let Some((name, text)) = &restored {
+        write_atomic(&path, text.as_bytes()).map_err(AppError::Storage)?;
+        log::warn!("Restored state from backup {}", name);
+    }
+
+    if let Ok(mut notice) = RECOVERY.lock() {
+        *notice = Some(RecoveryNotice {
+            at: now_ts(),
+            error: error.to_string(),
+            quarantined_as: quarantined
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+            restored_from: restored.as_ref().map(|(name, _)| name.clone()),
+        });
+    }
+
+    if restored.is_none() {
+        return Ok(AppData::default());
+    }
+    // A sealed backup leaves the vault locked until the user unlocks it.
+    vault::detect_sealed_state();
+    load_app_data()
+}
+
+/// Loads the state file, falling back to the newest usable backup when it
+/// does not parse. A file from a newer build is not corrupt, so it is
+/// reported as [`AppError::UnsupportedSchema`] and left in place.
+pub(crate) fn load_with_recovery() -> Result<AppData, AppError> {
+    let data = match load_app_data() {
+        Err(err @ AppError::Parse(_)) => recover(err),
+        other => other,
+    }?;
+    mark_synced(&data);
+    Ok(data)
+}
+
+pub(crate) fn recovery_notice() -> Option<RecoveryNotice> {
+    RECOVERY.lock().ok().and_then(|notice| notice.clone())
+}
+
+pub(crate) fn dismiss_recovery_notice() {
+    if let Ok(mut notice) = RECOVERY.lock() {
+        *notice = None;
+    }
+}
+
+pub(crate) fn hold_unsupported(error: AppError) {
+    if let Ok(mut unsupported) = UNSUPPORTED.lock() {
+        *unsupported = Some(error);
+    }
+}
+
+/// Fails with the startup error while the state file belongs to a newer
+/// build, so the UI shows that instead of an empty account list.
+pub(crate) fn ensure_supported() -> Result<(), AppError> {
+    match UNSUPPORTED.lock().ok().and_then(|error| error.clone()) {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// Holds `state.json.lock` until dropped.
+struct StateLock {
+    path: PathBuf,
+}
+
+impl Drop for StateLock {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            log::warn!("Failed to release state lock: {}", err);
+        }
+    }
+}
+
+fn lock_is_stale(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age > LOCK_STALE_AFTER)
+}
+
+fn acquire_lock() -> Result<StateLock, AppError> {
+    let path = app_storage_dir()?.join(LOCK_FILE);
+    let started = Instant::now();
+    loop {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                let _ = write!(file, "{}", std::process::id());
+                return Ok(StateLock { path });
+            }
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                if lock_is_stale(&path) {
+                    log::warn!("Removing stale state lock {}", path.display());
+                    let _ = fs::remove_file(&path);
+                    continue;
+                }
+                if started.elapsed() >= LOCK_WAIT {
+                    return Err(AppError::Storage(
+                        "State file is locked by another process".to_string(),
+                    ));
+                }
+                std::thread::sleep(LOCK_RETRY);
+            }
+            Err(err) => {
+                return Err(AppError::Storage(format!(
+                    "Failed to lock state file: {err}"
+                )))
+            }
+        }
+    }
+}
+
+fn to_value(data: &AppData) -> Result<Value, AppError> {
+    serde_json::to_value(data)
+        .map_err(|e| AppError::Internal(format!("Failed to serialize state: {e}")))
+}
+
+fn from_value(value: Value) -> Result<AppData, AppError> {
+    serde_json::from_value(value)
+        .map_err(|e| AppError::Internal(format!("Failed to merge state: {e}")))
+}
+
+fn revision_of(value: &Value) -> u64 {
+    value.get("revision").and_then(Value::as_u64).unwrap_or(0)
+}
+
+/// The state currently on disk, migrated to the current schema.
+fn read_disk() -> Result<Option<Value>, AppError> {
+    let path = app_storage_file()?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let text = fs::read_to_string(&path)
+        .map_err(|e| AppError::Storage(format!("Failed to read state file: {e}")))?;
+    let text = vault::decode_state_text(text).map_err(AppError::vault)?;
+    let value: Value = serde_json::from_str(&text)
+        .map_err(|e| AppError::Parse(format!("Failed to parse state file: {e}")))?;
+    migrate::migrate(value).map(Some)
+}
+
+pub(crate) fn mark_synced(data: &AppData) {
+    // Taken through text so it compares equal to the same state read back
+    // from disk.
+    let value = serde_json::to_string(data)
+        .ok()
+        .and_then(|text| serde_json::from_str::<Value>(&text).ok());
+    match (value, SYNCED.lock()) {
+        (Some(value), Ok(mut synced)) => *synced = Some(value),
+        _ => log::warn!("Failed to record the saved state"),
+    }
+}
+
+/// Drops the merge base. It is a full decrypted copy of the state, so it goes
+/// with everything else when the vault locks.
+pub(crate) fn forget_synced() {
+    if let Ok(mut synced) = SYNCED.lock() {
+        *synced = None;
+    }
+}
+
+fn synced_base() -> Option<Value> {
+    SYNCED.lock().ok().and_then(|synced| synced.clone())
+}
+
+/// Whether `disk` differs from what this process last read or wrote.
+fn changed_on_disk(disk: &Value, revision: u64, base: Option<&Value>) -> bool {
+    revision_of(disk) != revision || base.is_some_and(|base| base != disk)
+}
+
+/// Writes `data` unless someone else saved since it was loaded, in which case
+/// both sets of changes are merged first and `data` is updated to the result.
+pub(crate) fn save(data: &mut AppData) -> Result<(), AppError> {
+    let _lock = acquire_lock()?;
+    let disk = match read_disk() {
+        Ok(disk) => disk,
+        // Overwriting it would roll back whatever the newer build stored.
+        Err(err @ AppError::UnsupportedSchema { .. }) => return Err(err),
+        // Nothing to merge with; the previous file is still kept as a backup.
+        Err(err) => {
+            log::warn!("Saving over a state file that could not be read: {}", err);
+            None
+        }
+    };
+
+    let disk_revision = disk.as_ref().map_or(0, revision_of);
+    let base = synced_base();
+    if let Some(theirs) = disk.filter(|disk| changed_on_disk(disk, data.revision, base.as_ref())) {
+        log::info!(
+            "State file changed on disk (revision {} -> {}), merging",
+            data.revision,
+            disk_revision
+        );
+        *data = from_value(merge(base.as_ref(), &to_value(data)?, &theirs))?;
+    }
+    data.revision = disk_revision + 1;
+
+    let text = serde_json::to_string_pretty(data)
+        .map_err(|e| AppError::Internal(format!("Failed to serialize state: {e}")))?;
+    let text = vault::encode_state_text(text).map_err(AppError::vault)?;
+    if let Err(err) = backup_state(false) {
+        log::warn!("Failed to back up state file: {}", err);
+    }
+    write_atomic(&app_storage_file()?, text.as_bytes()).map_err(AppError::Storage)?;
+    mark_synced(data);
+    Ok(())
+}
+
+/// Picks up a save made by another process. Returns whether `data` changed.
+pub(crate) fn reload(data: &mut AppData) -> Result<bool, AppError> {
+    let _lock = acquire_lock()?;
+    let Some(theirs) = read_disk()? else {
+        return Ok(false);
+    };
+    let disk_revision = revision_of(&theirs);
+    let base = synced_base();
+    if !changed_on_disk(&theirs, data.revision, base.as_ref()) {
+        return Ok(false);
+    }
+
+    let mut merged = from_value(merge(base.as_ref(), &to_value(data)?, &theirs))?;
+    merged.revision = disk_revision;
+    *data = merged;
+    if let Ok(mut synced) = SYNCED.lock() {
+        *synced = Some(theirs);
+    }
+    Ok(true)
+}
+
+/// Three-way merge of two edits of `base`. Objects merge key by key and
+/// arrays of objects with an `id` merge entry by entry, so two writers that
+/// touched different accounts or settings both keep their change. When both
+/// changed the same value, ours wins. Without a base nothing counts as
+/// deleted.
+fn merge(base: Option<&Value>, ours: &Value, theirs: &Value) -> Value {
+    if ours == theirs || base == Some(theirs) {
+        return ours.clone();
+    }
+    if base == Some(ours) {
+        return theirs.clone();
+    }
+    match (ours, theirs) {
+        (Value::Object(o), Value::Object(t)) => {
+            let base = base.and_then(Value::as_object).map(object_entries);
+            let merged = merge_entries(base, object_entries(o), object_entries(t));
+            Value::Object(merged.into_iter().collect())
+        }
+        (Value::Array(o), Value::Array(t)) => {
+            let base = base.and_then(Value::as_array).map(|b| keyed_entries(b));
+            match (keyed_entries(o), keyed_entries(t)) {
+                (Some(o), Some(t)) => Value::Array(
+                    merge_entries(base.flatten(), o, t)
+                        .into_iter()
+                        .map(|(_, value)| value)
+                        .collect(),
+                ),
+                _ => ours.clone(),
+            }
+        }
+        _ => ours.clone(),
+    }
+}
+
+type Entries<'a> = Vec<(String, &'a Value)>;
+
+fn object_entries(map: &Map<String, Value>) -> Entries<'_> {
+    map.iter()
+        .map(|(key, value)| (key.clone(), value))
+        .collect()
+}
+
+/// Array entries keyed by their `id`, or `None` if any entry has none.
+fn keyed_entries(items: &[Value]) -> Option<Entries<'_>> {
+    items
+        .iter()
+        .map(|item| {
+            let id = item.get(MERGE_ID_KEY)?.as_str()?;
+            Some((id.to_string(), item))
+        })
+        .collect()
+}
+
+fn find_entry<'a>(entries: &Entries<'a>, key: &str) -> Option<&'a Value> {
+    entries
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, value)| *value)
+}
+
+fn merge_entries(base: Option<Entries>, ours: Entries, theirs: Entries) -> Vec<(String, Value)> {
+    let base_value = |key: &str| base.as_ref().and_then(|base| find_entry(base, key));
+
+    let mut merged = Vec::new();
+    for (key, value) in &ours {
+        match find_entry(&theirs, key) {
+            Some(their) => merged.push((key.clone(), merge(base_value(key), value, their))),
+            // Deleted by them; keep it only if we changed it since.
+            None if base_value(key) == Some(*value) => {}
+            None => merged.push((key.clone(), (*value).clone())),
+        }
+    }
+    for (key, value) in &theirs {
+        if find_entry(&ours, key).is_some() {
+            continue;
+        }
+        // Deleted by us, unless they changed it since.
+        if base_value(key) != Some(*value) {
+            merged.push((key.clone(), (*value).clone()));
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_app_data, SharedState};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[test]
+    fn merge_keeps_edits_to_different_values() {
+        let base = json!({ "preferredIde": null, "relay": { "enabled": false, "port": 1455 } });
+        let ours = json!({ "preferredIde": "cursor", "relay": { "enabled": false, "port": 1455 } });
+        let theirs = json!({ "preferredIde": null, "relay": { "enabled": true, "port": 1455 } });
+
+        assert_eq!(
+            merge(Some(&base), &ours, &theirs),
+            json!({ "preferredIde": "cursor", "relay": { "enabled": true, "port": 1455 } })
+        );
+    }
+
+    #[test]
+    fn merge_prefers_ours_when_both_changed_a_value() {
+        let base = json!({ "relay": { "port": 1455 } });
+        let ours = json!({ "relay": { "port": 2000 } });
+        let theirs = json!({ "relay": { "port": 3000 } });
+
+        assert_eq!(
+            merge(Some(&base), &ours, &theirs),
+            json!({ "relay": { "port": 2000 } })
+        );
+    }
+
+    #[test]
+    fn merge_combines_accounts_by_id() {
+        let base = json!({ "accounts": [
+            { "id": "a", "lastError": null },
+            { "id": "b", "lastError": null },
+            { "id": "c", "lastError": null },
+        ] });
+        // We touched "a"; they touched "b", removed "c" and added "d".
+        let ours = json!({ "accounts": [
+            { "id": "a", "lastError": "ours" },
+            { "id": "b", "lastError": null },
+            { "id": "c", "lastError": null },
+        ] });
+        let theirs = json!({ "accounts": [
+            { "id": "a", "lastError": null },
+            { "id": "b", "lastError": "theirs" },
+            { "id": "d", "lastError": null },
+        ] });
+
+        assert_eq!(
+            merge(Some(&base), &ours, &theirs),
+            json!({ "accounts": [
+                { "id": "a", "lastError": "ours" },
+                { "id": "b", "lastError": "theirs" },
+                { "id": "d", "lastError": null },
+            ] })
+        );
+    }
+
+    #[test]
+    fn merge_keeps_entries_changed_after_the_other_side_deleted_them() {
+        let base = json!({ "accounts": [{ "id": "a", "lastError": null }] });
+        let ours = json!({ "accounts": [{ "id": "a", "lastError": "ours" }] });
+        let theirs = json!({ "accounts": [] });
+
+        assert_eq!(merge(Some(&base), &ours, &theirs), ours);
+        assert_eq!(merge(Some(&base), &theirs, &ours), ours);
+    }
+
+    #[test]
+    fn merge_without_base_deletes_nothing() {
+        let ours = json!({ "accounts": [{ "id": "a" }], "preferredIde": "cursor" });
+        let theirs = json!({ "accounts": [{ "id": "b" }], "preferredIde": "vscode" });
+
+        assert_eq!(
+            merge(None, &ours, &theirs),
+            json!({ "accounts": [{ "id": "a" }, { "id": "b" }], "preferredIde": "cursor" })
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn lock_forgets_the_merge_base() {
+        let dir = std::env::temp_dir().join(format!("cam-storage-{}", uuid::Uuid::new_v4()));
+        std::env::set_var("XDG_DATA_HOME", &dir);
+
+        vault::enable("correct horse battery", 0).unwrap();
+        let mut data = AppData {
+            preferred_ide: Some("cursor".to_string()),
+            ..AppData::default()
+        };
+        save(&mut data).unwrap();
+        assert!(synced_base().is_some());
+
+        vault::lock().unwrap();
+        vault::wipe_shared_state(&Arc::new(SharedState::new(AppData::default())));
+        assert!(synced_base().is_none());
+
+        let mut unlocked =
+            parse_app_data(&vault::unlock("correct horse battery").unwrap()).unwrap();
+        mark_synced(&unlocked);
+        unlocked.preferred_ide = Some("vscode".to_string());
+        save(&mut unlocked).unwrap();
+
+        let text = fs::read_to_string(app_storage_file().unwrap()).unwrap();
+        let saved = parse_app_data(&vault::decode_state_text(text).unwrap()).unwrap();
+        assert_eq!(saved.preferred_ide.as_deref(), Some("vscode"));
+        assert_eq!(saved.revision, 2);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
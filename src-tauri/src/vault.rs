@@ -0,0 +1,454 @@
+use crate::{app_storage_file, events, http, storage, AppData, SharedState};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+pub(crate) const VAULT_LOCKED_EVENT: &str = "vault-locked";
+pub(crate) const VAULT_LOCKED_ERROR: &str = "Vault is locked";
+const VAULT_FORMAT_VERSION: u32 = 1;
+const VAULT_AAD: &[u8] = b"codex-account-manager/vault/v1";
+const MIN_PASSPHRASE_LEN: usize = 8;
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KdfParams {
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        // Argon2id with 64 MiB of memory and three passes.
+        Self {
+            m_cost: 64 * 1024,
+            t_cost: 3,
+            p_cost: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SealedState {
+    version: u32,
+    kdf: String,
+    params: KdfParams,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VaultFile {
+    vault: SealedState,
+}
+
+struct VaultKey {
+    key: Zeroizing<[u8; 32]>,
+    salt: Vec<u8>,
+    params: KdfParams,
+}
+
+enum VaultSession {
+    Disabled,
+    Locked,
+    Unlocked {
+        key: VaultKey,
+        last_activity: Instant,
+        idle_timeout: Option<Duration>,
+    },
+}
+
+static SESSION: Mutex<VaultSession> = Mutex::new(VaultSession::Disabled);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct VaultStatus {
+    pub(crate) enabled: bool,
+    pub(crate) locked: bool,
+}
+
+fn lock_session() -> Result<std::sync::MutexGuard<'static, VaultSession>, String> {
+    SESSION
+        .lock()
+        .map_err(|_| "State lock poisoned (vault)".to_string())
+}
+
+fn idle_timeout(minutes: u32) -> Option<Duration> {
+    (minutes > 0).then(|| Duration::from_secs(u64::from(minutes) * 60))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: KdfParams) -> Result<VaultKey, String> {
+    let argon_params = Params::new(params.m_cost, params.t_cost, params.p_cost, Some(32))
+        .map_err(|e| format!("Invalid vault KDF parameters: {e}"))?;
+    let argon = Argon2::new(Algorithm::Argon2id, Version::V0x13, argon_params);
+
+    let mut key = Zeroizing::new([0u8; 32]);
+    argon
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|e| format!("Failed to derive vault key: {e}"))?;
+
+    Ok(VaultKey {
+        key,
+        salt: salt.to_vec(),
+        params,
+    })
+}
+
+fn seal(key: &VaultKey, plaintext: &[u8]) -> Result<String, String> {
+    let mut nonce = [0u8; 24];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key.key.as_ref()));
+    let ciphertext = cipher
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad: VAULT_AAD,
+            },
+        )
+        .map_err(|_| "Failed to encrypt state".to_string())?;
+
+    let file = VaultFile {
+        vault: SealedState {
+            version: VAULT_FORMAT_VERSION,
+            kdf: "argon2id".to_string(),
+            params: key.params,
+            salt: STANDARD.encode(&key.salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        },
+    };
+
+    serde_json::to_string_pretty(&file).map_err(|e| format!("Failed to serialize vault: {e}"))
+}
+
+fn open(key: &VaultKey, sealed: &SealedState) -> Result<String, String> {
+    let nonce = STANDARD
+        .decode(&sealed.nonce)
+        .map_err(|e| format!("Invalid vault nonce: {e}"))?;
+    let ciphertext = STANDARD
+        .decode(&sealed.ciphertext)
+        .map_err(|e| format!("Invalid vault payload: {e}"))?;
+    if nonce.len() != 24 {
+        return Err("Invalid vault nonce length".to_string());
+    }
+
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key.key.as_ref()));
+    let plaintext = Zeroizing::new(
+        cipher
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: VAULT_AAD,
+                },
+            )
+            .map_err(|_| "Invalid passphrase or corrupted vault".to_string())?,
+    );
+
+    String::from_utf8(plaintext.to_vec())
+        .map_err(|_| "Vault payload is not valid UTF-8".to_string())
+}
+
+fn parse_sealed(text: &str) -> Option<SealedState> {
+    serde_json::from_str::<VaultFile>(text)
+        .ok()
+        .map(|file| file.vault)
+}
+
+/// Whether `text` is a sealed state file rather than plaintext JSON.
+pub(crate) fn is_sealed(text: &str) -> bool {
+    parse_sealed(text).is_some()
+}
+
+fn read_sealed_file() -> Result<SealedState, String> {
+    let path = app_storage_file()?;
+    let text = fs::read_to_string(&path).map_err(|e| format!("Failed to read state file: {e}"))?;
+    let sealed = parse_sealed(&text).ok_or_else(|| "State file is not a vault".to_string())?;
+    if sealed.version != VAULT_FORMAT_VERSION || sealed.kdf != "argon2id" {
+        return Err(format!(
+            "Unsupported vault format (version {}, kdf {})",
+            sealed.version, sealed.kdf
+        ));
+    }
+    Ok(sealed)
+}
+
+fn key_for_sealed(passphrase: &str, sealed: &SealedState) -> Result<VaultKey, String> {
+    let salt = STANDARD
+        .decode(&sealed.salt)
+        .map_err(|e| format!("Invalid vault salt: {e}"))?;
+    derive_key(passphrase, &salt, sealed.params)
+}
+
+/// Marks the session locked when the state file on disk is a sealed vault.
+/// Called once at startup, before anything tries to load or save state.
+pub(crate) fn detect_sealed_state() {
+    let Ok(path) = app_storage_file() else {
+        return;
+    };
+    let Ok(text) = fs::read_to_string(path) else {
+        return;
+    };
+    if parse_sealed(&text).is_some() {
+        if let Ok(mut session) = lock_session() {
+            *session = VaultSession::Locked;
+        }
+    }
+}
+
+/// Turns the raw state file into AppData JSON, decrypting it when sealed.
+pub(crate) fn decode_state_text(text: String) -> Result<String, String> {
+    let Some(sealed) = parse_sealed(&text) else {
+        return Ok(text);
+    };
+
+    let session = lock_session()?;
+    match &*session {
+        VaultSession::Unlocked { key, .. } => open(key, &sealed),
+        _ => Err(VAULT_LOCKED_ERROR.to_string()),
+    }
+}
+
+/// Turns AppData JSON into what should be written to disk, sealing it when
+/// the vault is enabled. Refuses to write while locked so the placeholder
+/// state never overwrites the sealed file.
+pub(crate) fn encode_state_text(text: String) -> Result<String, String> {
+    let session = lock_session()?;
+    match &*session {
+        VaultSession::Disabled => Ok(text),
+        VaultSession::Locked => Err(VAULT_LOCKED_ERROR.to_string()),
+        VaultSession::Unlocked { key, .. } => {
+            let text = Zeroizing::new(text);
+            seal(key, text.as_bytes())
+        }
+    }
+}
+
+pub(crate) fn ensure_unlocked() -> Result<(), String> {
+    match &*lock_session()? {
+        VaultSession::Locked => Err(VAULT_LOCKED_ERROR.to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// Records user activity for the idle auto-lock timer.
+pub(crate) fn touch_activity() {
+    if let Ok(mut session) = lock_session() {
+        if let VaultSession::Unlocked { last_activity, .. } = &mut *session {
+            *last_activity = Instant::now();
+        }
+    }
+}
+
+pub(crate) fn set_idle_timeout(minutes: u32) {
+    if let Ok(mut session) = lock_session() {
+        if let VaultSession::Unlocked {
+            idle_timeout: timeout,
+            ..
+        } = &mut *session
+        {
+            *timeout = idle_timeout(minutes);
+        }
+    }
+}
+
+pub(crate) fn status() -> Result<VaultStatus, String> {
+    let session = lock_session()?;
+    Ok(match &*session {
+        VaultSession::Disabled => VaultStatus {
+            enabled: false,
+            locked: false,
+        },
+        VaultSession::Locked => VaultStatus {
+            enabled: true,
+            locked: true,
+        },
+        VaultSession::Unlocked { .. } => VaultStatus {
+            enabled: true,
+            locked: false,
+        },
+    })
+}
+
+fn validate_passphrase(passphrase: &str) -> Result<(), String> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(format!(
+            "Passphrase must be at least {MIN_PASSPHRASE_LEN} characters"
+        ));
+    }
+    Ok(())
+}
+
+/// Switches the session to vault mode with a fresh salt. The caller saves
+/// the current state afterwards, which upgrades the plaintext file in place.
+pub(crate) fn enable(passphrase: &str, auto_lock_minutes: u32) -> Result<(), String> {
+    validate_passphrase(passphrase)?;
+
+    let mut session = lock_session()?;
+    if !matches!(&*session, VaultSession::Disabled) {
+        return Err("Vault is already enabled".to_string());
+    }
+
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let key = derive_key(passphrase, &salt, KdfParams::default())?;
+
+    *session = VaultSession::Unlocked {
+        key,
+        last_activity: Instant::now(),
+        idle_timeout: idle_timeout(auto_lock_minutes),
+    };
+    Ok(())
+}
+
+/// Rolls back [`enable`] when the first sealed write fails.
+pub(crate) fn cancel_enable() {
+    if let Ok(mut session) = lock_session() {
+        *session = VaultSession::Disabled;
+    }
+}
+
+/// Verifies the passphrase against the sealed file and returns the decrypted
+/// state JSON. The session stays unlocked on success.
+pub(crate) fn unlock(passphrase: &str) -> Result<String, String> {
+    let sealed = read_sealed_file()?;
+    let key = key_for_sealed(passphrase, &sealed)?;
+    let text = open(&key, &sealed)?;
+
+    let mut session = lock_session()?;
+    *session = VaultSession::Unlocked {
+        key,
+        last_activity: Instant::now(),
+        idle_timeout: None,
+    };
+    Ok(text)
+}
+
+/// Drops the key. The caller is responsible for wiping in-memory secrets.
+pub(crate) fn lock() -> Result<(), String> {
+    let mut session = lock_session()?;
+    if matches!(&*session, VaultSession::Disabled) {
+        return Err("Vault is not enabled".to_string());
+    }
+    *session = VaultSession::Locked;
+    Ok(())
+}
+
+/// Leaves vault mode after re-checking the passphrase. The caller saves the
+/// current state afterwards, which writes it back as plaintext.
+pub(crate) fn disable(passphrase: &str) -> Result<(), String> {
+    let sealed = read_sealed_file()?;
+    let key = key_for_sealed(passphrase, &sealed)?;
+    open(&key, &sealed)?;
+
+    let mut session = lock_session()?;
+    *session = VaultSession::Disabled;
+    Ok(())
+}
+
+/// Replaces everything secret held in memory with empty placeholders.
+pub(crate) fn wipe_shared_state(state: &Arc<SharedState>) {
+    if let Ok(mut data) = state.data.lock() {
+        *data = AppData::default();
+    }
+    if let Ok(mut flows) = state.flows.lock() {
+        flows.clear();
+    }
+    storage::forget_synced();
+    http::clear();
+}
+
+/// Locks the vault after the configured period without any command from the
+/// UI, wiping decrypted state and notifying the frontend.
+pub(crate) fn start_idle_lock_timer(state: Arc<SharedState>) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(IDLE_CHECK_INTERVAL);
+
+        let expired = match lock_session() {
+            Ok(session) => match &*session {
+                VaultSession::Unlocked {
+                    last_activity,
+                    idle_timeout: Some(timeout),
+                    ..
+                } => last_activity.elapsed() >= *timeout,
+                _ => false,
+            },
+            Err(_) => false,
+        };
+
+        if expired && lock().is_ok() {
+            wipe_shared_state(&state);
+            log::info!("Vault auto-locked after inactivity");
+            events::emit(&state, VAULT_LOCKED_EVENT, ());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Far cheaper than the default so the tests stay fast.
+    const TEST_PARAMS: KdfParams = KdfParams {
+        m_cost: 1024,
+        t_cost: 1,
+        p_cost: 1,
+    };
+    const PASSPHRASE: &str = "correct horse battery";
+    const STATE: &str = r#"{"accounts":[],"activeAccountId":null}"#;
+
+    fn sealed_state() -> SealedState {
+        let key = derive_key(PASSPHRASE, b"0123456789abcdef", TEST_PARAMS).unwrap();
+        let text = seal(&key, STATE.as_bytes()).unwrap();
+        assert!(is_sealed(&text));
+        assert!(!text.contains("accounts"));
+        parse_sealed(&text).unwrap()
+    }
+
+    #[test]
+    fn seal_and_open_round_trip() {
+        let sealed = sealed_state();
+        let key = key_for_sealed(PASSPHRASE, &sealed).unwrap();
+        assert_eq!(open(&key, &sealed).unwrap(), STATE);
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let sealed = sealed_state();
+        let key = key_for_sealed("battery horse correct", &sealed).unwrap();
+        assert_eq!(
+            open(&key, &sealed).unwrap_err(),
+            "Invalid passphrase or corrupted vault"
+        );
+    }
+
+    #[test]
+    fn tampered_payload_is_rejected() {
+        let mut sealed = sealed_state();
+        let mut ciphertext = STANDARD.decode(&sealed.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        sealed.ciphertext = STANDARD.encode(ciphertext);
+
+        let key = key_for_sealed(PASSPHRASE, &sealed).unwrap();
+        assert!(open(&key, &sealed).is_err());
+    }
+
+    #[test]
+    fn short_passphrases_are_refused() {
+        assert!(validate_passphrase("short").is_err());
+        assert!(validate_passphrase(PASSPHRASE).is_ok());
+    }
+}
@@ -0,0 +1,162 @@
+//! Redacted shapes returned to the webview. Tokens and proxy passwords stay in
+//! `SharedState`; the frontend only learns whether they exist, when they
+//! expire and a short fingerprint to tell them apart.
+
+use crate::health::AccountHealth;
+use crate::ide::IdeTarget;
+use crate::{
+    now_ts, token_expires_at, Account, AppData, OAuthCallbackSettings, ProxyEntry, QuotaInfo,
+    QuotaRefreshSettings, RelaySettings, RotationEvent, RotationSettings, Tokens,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenView {
+    pub present: bool,
+    pub expires_at: Option<i64>,
+    pub fingerprint: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokensView {
+    pub id_token: TokenView,
+    pub access_token: TokenView,
+    pub refresh_token: TokenView,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountView {
+    pub id: String,
+    pub email: Option<String>,
+    pub account_id: Option<String>,
+    pub tokens: TokensView,
+    pub quota: Option<QuotaInfo>,
+    pub created_at: i64,
+    pub last_login_at: i64,
+    pub last_error: Option<String>,
+    pub last_refresh_at: Option<i64>,
+    pub health: AccountHealth,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyView {
+    pub id: String,
+    pub login: String,
+    pub has_password: bool,
+    pub host: String,
+    pub port: u16,
+    pub last_latency_ms: Option<u64>,
+    pub last_status: Option<String>,
+    pub last_checked_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppDataView {
+    pub accounts: Vec<AccountView>,
+    pub active_account_id: Option<String>,
+    pub proxies: Vec<ProxyView>,
+    pub active_proxy_id: Option<String>,
+    pub limits_base_url: String,
+    pub preferred_ide: Option<String>,
+    pub rotation: RotationSettings,
+    pub rotation_log: Vec<RotationEvent>,
+    pub vault_auto_lock_minutes: u32,
+    pub custom_ides: Vec<IdeTarget>,
+    pub relay: RelaySettings,
+    pub quota_refresh: QuotaRefreshSettings,
+    pub oauth_callback: OAuthCallbackSettings,
+}
+
+fn fingerprint(secret: &str) -> String {
+    let digest = Sha256::digest(secret.as_bytes());
+    digest[..6]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+impl TokenView {
+    fn from_secret(secret: &str, jwt: bool) -> Self {
+        if secret.trim().is_empty() {
+            return Self {
+                present: false,
+                expires_at: None,
+                fingerprint: None,
+            };
+        }
+
+        Self {
+            present: true,
+            expires_at: if jwt { token_expires_at(secret) } else { None },
+            fingerprint: Some(fingerprint(secret)),
+        }
+    }
+}
+
+impl From<&Tokens> for TokensView {
+    fn from(tokens: &Tokens) -> Self {
+        Self {
+            id_token: TokenView::from_secret(&tokens.id_token, true),
+            access_token: TokenView::from_secret(&tokens.access_token, true),
+            refresh_token: TokenView::from_secret(&tokens.refresh_token, false),
+        }
+    }
+}
+
+impl From<&Account> for AccountView {
+    fn from(account: &Account) -> Self {
+        Self {
+            id: account.id.clone(),
+            email: account.email.clone(),
+            account_id: account.account_id.clone(),
+            tokens: TokensView::from(&account.tokens),
+            quota: account.quota.clone(),
+            created_at: account.created_at,
+            last_login_at: account.last_login_at,
+            last_error: account.last_error.clone(),
+            last_refresh_at: account.last_refresh_at,
+            health: account.health.current(now_ts()),
+        }
+    }
+}
+
+impl From<&ProxyEntry> for ProxyView {
+    fn from(proxy: &ProxyEntry) -> Self {
+        Self {
+            id: proxy.id.clone(),
+            login: proxy.login.clone(),
+            has_password: !proxy.password.is_empty(),
+            host: proxy.host.clone(),
+            port: proxy.port,
+            last_latency_ms: proxy.last_latency_ms,
+            last_status: proxy.last_status.clone(),
+            last_checked_at: proxy.last_checked_at,
+        }
+    }
+}
+
+impl From<&AppData> for AppDataView {
+    fn from(data: &AppData) -> Self {
+        Self {
+            accounts: data.accounts.iter().map(AccountView::from).collect(),
+            active_account_id: data.active_account_id.clone(),
+            proxies: data.proxies.iter().map(ProxyView::from).collect(),
+            active_proxy_id: data.active_proxy_id.clone(),
+            limits_base_url: data.limits_base_url.clone(),
+            preferred_ide: data.preferred_ide.clone(),
+            rotation: data.rotation.clone(),
+            rotation_log: data.rotation_log.clone(),
+            vault_auto_lock_minutes: data.vault_auto_lock_minutes,
+            custom_ides: data.custom_ides.clone(),
+            relay: data.relay.clone(),
+            quota_refresh: data.quota_refresh.clone(),
+            oauth_callback: data.oauth_callback.clone(),
+        }
+    }
+}
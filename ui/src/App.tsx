@@ -1,12 +1,15 @@
 ﻿import { useCallback, useEffect, useState, type MouseEvent } from 'react'
-import { Minus, MoonStar, Network, Sun, Users, X } from 'lucide-react'
+import { Minus, MoonStar, Network, Shield, Sun, Users, X } from 'lucide-react'
 import { getCurrentWindow } from '@tauri-apps/api/window'
 import { getVersion } from '@tauri-apps/api/app'
 import './App.css'
-import { api } from './api'
-import type { AppData } from './types'
+import { api, errorCode, errorMessage } from './api'
+import { subscribe } from './events'
+import type { Account, AppData, VaultStatus } from './types'
 import { AccountsTab } from './components/AccountsTab'
 import { ProxyTab } from './components/ProxyTab'
+import { RecoveryBanner } from './components/RecoveryBanner'
+import { VaultModal, VaultUnlockScreen } from './components/VaultModal'
 
 type TabKey = 'accounts' | 'proxy'
 type ThemeMode = 'light' | 'dark'
@@ -29,6 +32,11 @@ function isInteractiveTarget(target: EventTarget | null): boolean {
   )
 }
 
+function upsertAccount(accounts: Account[], account: Account): Account[] {
+  if (!accounts.some((entry) => entry.id === account.id)) return [...accounts, account]
+  return accounts.map((entry) => (entry.id === account.id ? account : entry))
+}
+
 function App() {
   const [activeTab, setActiveTab] = useState<TabKey>('accounts')
   const [data, setData] = useState<AppData | null>(null)
@@ -36,15 +44,23 @@ function App() {
   const [error, setError] = useState<string | null>(null)
   const [theme, setTheme] = useState<ThemeMode>(readInitialTheme)
   const [version, setVersion] = useState<string>('0.2.0')
+  const [vault, setVault] = useState<VaultStatus>({ enabled: false, locked: false })
+  const [vaultOpen, setVaultOpen] = useState(false)
 
   const load = useCallback(async () => {
     try {
       setLoading(true)
       setError(null)
+      const vaultStatus = await api.getVaultStatus()
+      setVault(vaultStatus)
+      if (vaultStatus.locked) {
+        setData(null)
+        return
+      }
       const state = await api.getState()
       setData(state)
     } catch (err) {
-      setError(String(err))
+      setError(errorMessage(err))
     } finally {
       setLoading(false)
     }
@@ -54,6 +70,45 @@ function App() {
     void load()
   }, [load])
 
+  useEffect(() => {
+    const refreshState = () => {
+      void api
+        .getState()
+        .then(setData)
+        .catch((err) => {
+          if (errorCode(err) === 'vault_locked') {
+            setData(null)
+            setVault({ enabled: true, locked: true })
+            return
+          }
+          setError(errorMessage(err))
+        })
+    }
+    const unsubscribers = [
+      subscribe('codex-auth-changed', refreshState),
+      subscribe('state-changed', refreshState),
+      subscribe('account-rotated', refreshState),
+      subscribe('quotas-refreshed', (payload) => setData(payload.state)),
+      subscribe('account-updated', (account) => {
+        setData((prev) => (prev ? { ...prev, accounts: upsertAccount(prev.accounts, account) } : prev))
+      }),
+      subscribe('active-account-changed', ({ activeAccountId }) => {
+        setData((prev) => (prev ? { ...prev, activeAccountId } : prev))
+      }),
+      subscribe('vault-locked', () => {
+        setVaultOpen(false)
+        setData(null)
+        setVault({ enabled: true, locked: true })
+      })
+    ]
+
+    return () => {
+      for (const unsubscribe of unsubscribers) {
+        unsubscribe()
+      }
+    }
+  }, [])
+
   useEffect(() => {
     document.documentElement.setAttribute('data-theme', theme)
     window.localStorage.setItem(THEME_STORAGE_KEY, theme)
@@ -119,6 +174,16 @@ function App() {
             </div>
 
             <div className="flex items-center gap-2" data-no-drag>
+              <button
+                className="window-btn"
+                onClick={() => setVaultOpen(true)}
+                disabled={vault.locked}
+                title={vault.enabled ? 'State vault: encrypted' : 'State vault: plaintext'}
+                aria-label="State vault"
+              >
+                <Shield size={14} className={vault.enabled ? 'text-ag-primary' : undefined} />
+              </button>
+
               <button
                 className="theme-toggle"
                 onClick={toggleTheme}
@@ -153,14 +218,35 @@ function App() {
               </div>
             )}
 
-            {!loading && error && (
+            {!loading && vault.locked && (
+              <VaultUnlockScreen
+                onUnlocked={(next) => {
+                  setData(next)
+                  setVault({ enabled: true, locked: false })
+                }}
+              />
+            )}
+
+            {!loading && !vault.locked && error && (
               <div className="h-full rounded-2xl border border-red-200 bg-red-50 shadow-ag p-6 text-red-700">
                 Error: {error}
               </div>
             )}
 
-            {!loading && !error && data && (
+            {!loading && !vault.locked && !error && data && (
               <>
+                <VaultModal
+                  open={vaultOpen}
+                  status={vault}
+                  data={data}
+                  setData={setData}
+                  setStatus={(next) => {
+                    setVault(next)
+                    if (next.locked) setData(null)
+                  }}
+                  onClose={() => setVaultOpen(false)}
+                />
+                <RecoveryBanner setData={setData} />
                 {activeTab === 'accounts' && <AccountsTab data={data} setData={setData} reload={load} />}
                 {activeTab === 'proxy' && <ProxyTab data={data} setData={setData} />}
               </>
@@ -1,12 +1,28 @@
 import { invoke } from '@tauri-apps/api/core'
 import type {
   Account,
+  AccountForecast,
   AppData,
+  AppError,
+  AppErrorCode,
+  OAuthCallbackSettings,
   OAuthFlowResponse,
   OAuthStartResponse,
   ProxyTestResult,
+  QuotaRefreshSettings,
+  QuotaSeries,
+  RecoveryNotice,
+  RefreshAllResponse,
   SwitchAccountResponse,
-  IdeTarget
+  UsageSummary,
+  IdeTarget,
+  IdeTargetInfo,
+  RelaySettings,
+  RelayStatus,
+  RotationSettings,
+  SchedulerStatus,
+  StateBackup,
+  VaultStatus
 } from './types'
 
 export const api = {
@@ -18,6 +34,7 @@ export const api = {
     invoke<OAuthFlowResponse>('get_oauth_flow_status', { flowId }),
   completeOAuthWithCallback: (flowId: string, callbackUrl: string) =>
     invoke<OAuthFlowResponse>('complete_oauth_with_callback', { flowId, callbackUrl }),
+  cancelOAuthFlow: (flowId: string) => invoke<void>('cancel_oauth_flow', { flowId }),
 
   removeAccount: (accountId: string) =>
     invoke<AppData>('remove_account', { accountId }),
@@ -25,6 +42,9 @@ export const api = {
     invoke<AppData>('set_active_account', { accountId }),
   setPreferredIde: (ide?: IdeTarget) =>
     invoke<AppData>('set_preferred_ide', { ide: ide ?? null }),
+  listIdeTargets: () => invoke<IdeTargetInfo[]>('list_ide_targets'),
+  saveCustomIde: (target: IdeTargetInfo) => invoke<AppData>('save_custom_ide', { target }),
+  deleteCustomIde: (ideId: string) => invoke<AppData>('delete_custom_ide', { ideId }),
   switchAccountForIde: (accountId: string, ide?: IdeTarget) =>
     invoke<SwitchAccountResponse>('switch_account_for_ide', {
       accountId,
@@ -33,7 +53,28 @@ export const api = {
 
   refreshAccountQuota: (accountId: string) =>
     invoke<Account>('refresh_account_quota', { accountId }),
-  refreshAllQuotas: () => invoke<AppData>('refresh_all_quotas'),
+  refreshAccountTokens: (accountId: string) =>
+    invoke<Account>('refresh_account_tokens', { accountId }),
+  refreshAllQuotas: () => invoke<RefreshAllResponse>('refresh_all_quotas'),
+  setRotationSettings: (settings: RotationSettings) =>
+    invoke<AppData>('set_rotation_settings', { settings }),
+  getQuotaHistory: (accountId?: string, from?: number, to?: number) =>
+    invoke<QuotaSeries[]>('get_quota_history', {
+      accountId: accountId ?? null,
+      from: from ?? null,
+      to: to ?? null
+    }),
+  getUsageSummary: (from?: number, to?: number) =>
+    invoke<UsageSummary[]>('get_usage_summary', { from: from ?? null, to: to ?? null }),
+  getQuotaForecasts: () => invoke<AccountForecast[]>('get_quota_forecasts'),
+  getQuotaSchedulerStatus: () => invoke<SchedulerStatus>('get_quota_scheduler_status'),
+  setQuotaRefreshSettings: (settings: QuotaRefreshSettings) =>
+    invoke<AppData>('set_quota_refresh_settings', { settings }),
+  getRelayStatus: () => invoke<RelayStatus>('get_relay_status'),
+  setRelaySettings: (settings: RelaySettings) =>
+    invoke<RelayStatus>('set_relay_settings', { settings }),
+  setOAuthCallbackSettings: (settings: OAuthCallbackSettings) =>
+    invoke<AppData>('set_oauth_callback_settings', { settings }),
 
   saveProxy: (proxyValue: string, proxyId?: string) =>
     invoke<AppData>('save_proxy', {
@@ -45,5 +86,29 @@ export const api = {
   setActiveProxy: (proxyId?: string) =>
     invoke<AppData>('set_active_proxy', { proxyId: proxyId ?? null }),
   testProxy: (proxyId: string) =>
-    invoke<ProxyTestResult>('test_proxy', { proxyId })
-}
\ No newline at end of file
+    invoke<ProxyTestResult>('test_proxy', { proxyId }),
+
+  getVaultStatus: () => invoke<VaultStatus>('get_vault_status'),
+  enableVault: (passphrase: string) => invoke<VaultStatus>('enable_vault', { passphrase }),
+  unlockVault: (passphrase: string) => invoke<AppData>('unlock_vault', { passphrase }),
+  lockVault: () => invoke<VaultStatus>('lock_vault'),
+  disableVault: (passphrase: string) => invoke<VaultStatus>('disable_vault', { passphrase }),
+  setVaultAutoLock: (minutes: number) => invoke<AppData>('set_vault_auto_lock', { minutes }),
+
+  getStateRecovery: () => invoke<RecoveryNotice | null>('get_state_recovery'),
+  dismissStateRecovery: () => invoke<void>('dismiss_state_recovery'),
+  listStateBackups: () => invoke<StateBackup[]>('list_state_backups'),
+  restoreStateBackup: (name: string) => invoke<AppData>('restore_state_backup', { name })
+}
+
+export function isAppError(err: unknown): err is AppError {
+  return typeof err === 'object' && err !== null && 'code' in err && 'message' in err
+}
+
+export function errorCode(err: unknown): AppErrorCode | null {
+  return isAppError(err) ? err.code : null
+}
+
+export function errorMessage(err: unknown): string {
+  return isAppError(err) ? err.message : String(err)
+}
@@ -1,4 +1,4 @@
-﻿import { useCallback, useEffect, useMemo, useState } from 'react'
+﻿import { useEffect, useMemo, useState } from 'react'
 import {
   ArrowRightLeft,
   CircleAlert,
@@ -6,12 +6,29 @@ import {
   Info,
   Loader2,
   RefreshCw,
+  Settings2,
   Trash2,
   UserPlus
 } from 'lucide-react'
-import { api } from '../api'
+import { api, errorCode, errorMessage } from '../api'
+import { subscribe } from '../events'
 import { formatRemainingPercent, remainingPercent, formatTimeUntil } from '../format'
-import type { Account, AppData, IdeTarget } from '../types'
+import type {
+  Account,
+  AccountForecast,
+  AccountHealth,
+  AccountStatus,
+  AppData,
+  IdeTarget,
+  IdeTargetInfo,
+  QuotaRefreshSettings,
+  RotationPolicy,
+  RotationSettings,
+  SchedulerStatus,
+  UsageSummary,
+  WindowForecast
+} from '../types'
+import { IdeTargetsModal } from './IdeTargetsModal'
 import { OAuthModal } from './OAuthModal'
 
 type AccountsTabProps = {
@@ -20,17 +37,12 @@ type AccountsTabProps = {
   reload: () => Promise<void>
 }
 
-const IDE_OPTIONS: Array<{ value: IdeTarget; label: string }> = [
-  { value: 'vscode', label: 'VS Code' },
-  { value: 'cursor', label: 'Cursor' },
-  { value: 'windsurf', label: 'Windsurf' },
-  { value: 'trae', label: 'Trae' },
-  { value: 'vscodium', label: 'VSCodium' },
-  { value: 'zed', label: 'Zed' }
+const ROTATION_POLICY_OPTIONS: Array<{ value: RotationPolicy; label: string }> = [
+  { value: 'most_remaining', label: 'Most remaining' },
+  { value: 'soonest_reset', label: 'Soonest reset' },
+  { value: 'round_robin', label: 'Round robin' }
 ]
 
-const AUTO_REFRESH_MS = 5 * 60 * 1000
-
 function quotaClass(remaining: number): string {
   if (remaining <= 10) return 'quota-fill-danger'
   if (remaining <= 30) return 'quota-fill-warn'
@@ -40,10 +52,12 @@ function quotaClass(remaining: number): string {
 function QuotaCell({
   value,
   resetAt,
+  forecast,
   title
 }: {
   value: number | null | undefined
   resetAt: number | null | undefined
+  forecast: WindowForecast | null | undefined
   title: string
 }) {
   const remaining = remainingPercent(value)
@@ -59,11 +73,61 @@ function QuotaCell({
         <div className={`quota-fill ${quotaClass(barPercent)}`} style={{ width: `${barPercent}%` }} />
       </div>
       <div className="text-xs text-ag-muted mt-1">reset {formatTimeUntil(resetAt)}</div>
+      {forecast?.willExhaustBeforeReset && forecast.exhaustsAt && (
+        <div
+          className="text-xs text-amber-600 mt-0.5"
+          title={`~${forecast.burnRatePerHour?.toFixed(1) ?? '?'}%/h from ${forecast.samples} samples`}
+        >
+          runs out {formatTimeUntil(forecast.exhaustsAt)}
+        </div>
+      )}
     </div>
   )
 }
 
+const HEALTH_LABELS: Record<AccountStatus, string> = {
+  healthy: 'healthy',
+  stale: 'not checked recently',
+  token_expired: 'token expired',
+  needs_reauth: 'sign in again',
+  rate_limited: 'rate limited',
+  deactivated: 'deactivated',
+  network_error: 'network error'
+}
+
+function HealthBadge({ health }: { health: AccountHealth }) {
+  const ok = health.status === 'healthy'
+  const muted = health.status === 'stale' || health.status === 'network_error'
+  const color = ok ? 'text-emerald-600' : muted ? 'text-ag-muted' : 'text-red-600'
+  const details = [
+    health.httpStatus != null ? `HTTP ${health.httpStatus}` : null,
+    health.since ? `since ${new Date(health.since * 1000).toLocaleString()}` : null
+  ]
+    .filter(Boolean)
+    .join(', ')
+
+  return (
+    <span className={`inline-flex items-center gap-1 text-xs ${color}`} title={details || undefined}>
+      {ok ? <CircleCheck size={14} /> : <CircleAlert size={14} />} {HEALTH_LABELS[health.status]}
+      {health.status === 'rate_limited' && health.retryAt && <>, retry {formatTimeUntil(health.retryAt)}</>}
+    </span>
+  )
+}
+
+const WEEK_SECONDS = 7 * 24 * 60 * 60
+
 function AccountInfoModal({ account, onClose }: { account: Account | null; onClose: () => void }) {
+  const [weekUsage, setWeekUsage] = useState<UsageSummary | null>(null)
+
+  useEffect(() => {
+    setWeekUsage(null)
+    if (!account) return
+    api
+      .getUsageSummary(Math.floor(Date.now() / 1000) - WEEK_SECONDS)
+      .then((summaries) => setWeekUsage(summaries.find((entry) => entry.accountId === account.id) ?? null))
+      .catch(() => setWeekUsage(null))
+  }, [account])
+
   if (!account) return null
 
   return (
@@ -72,7 +136,7 @@ function AccountInfoModal({ account, onClose }: { account: Account | null; onClo
         <div className="px-5 py-4 border-b border-ag-border flex items-center justify-between">
           <div>
             <div className="text-lg font-semibold text-ag-text">Account Info</div>
-            <div className="text-xs text-ag-muted mt-1">Full account payload currently stored in app data</div>
+            <div className="text-xs text-ag-muted mt-1">Stored account data; tokens are shown as fingerprints only</div>
           </div>
           <button
             className="h-9 px-3 rounded-lg border border-ag-border text-sm font-semibold text-ag-text hover:bg-ag-surface"
@@ -81,7 +145,13 @@ function AccountInfoModal({ account, onClose }: { account: Account | null; onClo
             Close
           </button>
         </div>
-        <div className="p-4 bg-ag-surface">
+        <div className="p-4 bg-ag-surface space-y-3">
+          {weekUsage && (
+            <div className="text-sm text-ag-text">
+              Last 7 days: {weekUsage.primaryUsed.toFixed(0)}% of 5-hour windows,{' '}
+              {weekUsage.secondaryUsed.toFixed(0)}% of weekly quota ({weekUsage.samples} samples)
+            </div>
+          )}
           <pre className="allow-select m-0 max-h-[65vh] overflow-auto rounded-xl border border-ag-border bg-ag-card p-4 text-xs leading-5 text-ag-text">
             {JSON.stringify(account, null, 2)}
           </pre>
@@ -96,9 +166,11 @@ export function AccountsTab({ data, setData, reload }: AccountsTabProps) {
   const [infoAccount, setInfoAccount] = useState<Account | null>(null)
   const [busyKey, setBusyKey] = useState<string | null>(null)
   const [refreshingAll, setRefreshingAll] = useState(false)
-  const [autoRefreshing, setAutoRefreshing] = useState(false)
-  const [lastAutoRefresh, setLastAutoRefresh] = useState<number | null>(null)
+  const [scheduler, setScheduler] = useState<SchedulerStatus | null>(null)
   const [ideTarget, setIdeTarget] = useState<IdeTarget | null>(data.preferredIde)
+  const [ideTargets, setIdeTargets] = useState<IdeTargetInfo[]>([])
+  const [ideTargetsOpen, setIdeTargetsOpen] = useState(false)
+  const [forecasts, setForecasts] = useState<Record<string, AccountForecast>>({})
   const [error, setError] = useState<string | null>(null)
 
   const accounts = useMemo(
@@ -106,6 +178,28 @@ export function AccountsTab({ data, setData, reload }: AccountsTabProps) {
     [data.accounts]
   )
 
+  useEffect(() => {
+    api
+      .listIdeTargets()
+      .then(setIdeTargets)
+      .catch((err) => setError(errorMessage(err)))
+  }, [data.customIdes])
+
+  useEffect(() => {
+    api
+      .getQuotaForecasts()
+      .then((list) => setForecasts(Object.fromEntries(list.map((item) => [item.accountId, item]))))
+      .catch(() => setForecasts({}))
+  }, [data.accounts])
+
+  useEffect(() => {
+    const loadScheduler = () => {
+      void api.getQuotaSchedulerStatus().then(setScheduler).catch(() => setScheduler(null))
+    }
+    loadScheduler()
+    return subscribe('quotas-refreshed', loadScheduler)
+  }, [data.quotaRefresh])
+
   useEffect(() => {
     if (!ideTarget && data.preferredIde) {
       setIdeTarget(data.preferredIde)
@@ -120,10 +214,42 @@ export function AccountsTab({ data, setData, reload }: AccountsTabProps) {
       const updated = await api.setPreferredIde(next)
       setData(updated)
     } catch (err) {
-      setError(String(err))
+      setError(errorMessage(err))
     }
   }
 
+  const updateRotation = async (patch: Partial<RotationSettings>) => {
+    try {
+      setError(null)
+      const updated = await api.setRotationSettings({ ...data.rotation, ...patch })
+      setData(updated)
+    } catch (err) {
+      setError(errorMessage(err))
+    }
+  }
+
+  const updateQuotaRefresh = async (patch: Partial<QuotaRefreshSettings>) => {
+    try {
+      setError(null)
+      const updated = await api.setQuotaRefreshSettings({ ...data.quotaRefresh, ...patch })
+      setData(updated)
+    } catch (err) {
+      setError(errorMessage(err))
+    }
+  }
+
+  const changeRefreshInterval = (raw: string) => {
+    const value = Number(raw)
+    if (raw.length === 0 || !Number.isInteger(value) || value < 1) return
+    void updateQuotaRefresh({ intervalMinutes: value })
+  }
+
+  const changeThreshold = (key: 'primaryThreshold' | 'secondaryThreshold', raw: string) => {
+    const value = Number(raw)
+    if (raw.length === 0 || !Number.isFinite(value)) return
+    void updateRotation({ [key]: value })
+  }
+
   const remove = async (accountId: string) => {
     try {
       setBusyKey(`delete:${accountId}`)
@@ -131,7 +257,7 @@ export function AccountsTab({ data, setData, reload }: AccountsTabProps) {
       const next = await api.removeAccount(accountId)
       setData(next)
     } catch (err) {
-      setError(String(err))
+      setError(errorMessage(err))
     } finally {
       setBusyKey(null)
     }
@@ -154,7 +280,7 @@ export function AccountsTab({ data, setData, reload }: AccountsTabProps) {
         setError(response.warning)
       }
     } catch (err) {
-      setError(String(err))
+      setError(errorMessage(err))
     } finally {
       setBusyKey(null)
     }
@@ -171,51 +297,42 @@ export function AccountsTab({ data, setData, reload }: AccountsTabProps) {
       }
       setData(next)
     } catch (err) {
-      setError(String(err))
+      // A failed fetch is already stored on the row, which shows the account
+      // health and message.
+      const code = errorCode(err)
+      if (code !== 'quota_http' && code !== 'network' && code !== 'token_refresh') {
+        setError(errorMessage(err))
+      }
     } finally {
       setBusyKey(null)
     }
   }
 
-  const refreshAll = async (silent = false) => {
+  const refreshAll = async () => {
     try {
-      if (silent) {
-        setAutoRefreshing(true)
-      } else {
-        setRefreshingAll(true)
-      }
+      setRefreshingAll(true)
       setError(null)
-      const next = await api.refreshAllQuotas()
-      setData(next)
-      if (silent) {
-        setLastAutoRefresh(Date.now())
+      const response = await api.refreshAllQuotas()
+      setData(response.state)
+      if (response.pendingAccountIds.length > 0) {
+        setError(
+          `${response.pendingAccountIds.length} account(s) are still refreshing and will update when they finish.`
+        )
       }
     } catch (err) {
-      setError(String(err))
+      setError(errorMessage(err))
     } finally {
       setRefreshingAll(false)
-      setAutoRefreshing(false)
     }
   }
 
-  const runAutoRefresh = useCallback(async () => {
-    if (data.accounts.length === 0) return
-    await refreshAll(true)
-  }, [data.accounts.length])
-
-  useEffect(() => {
-    const timer = setInterval(() => {
-      void runAutoRefresh()
-    }, AUTO_REFRESH_MS)
-
-    return () => clearInterval(timer)
-  }, [runAutoRefresh])
-
   return (
     <div className="page-fade h-full flex flex-col gap-4">
       <OAuthModal
         open={oauthOpen}
         onClose={() => setOauthOpen(false)}
+        callback={data.oauthCallback}
+        setData={setData}
         onCompleted={async () => {
           await reload()
         }}
@@ -223,6 +340,13 @@ export function AccountsTab({ data, setData, reload }: AccountsTabProps) {
 
       <AccountInfoModal account={infoAccount} onClose={() => setInfoAccount(null)} />
 
+      <IdeTargetsModal
+        open={ideTargetsOpen}
+        targets={ideTargets}
+        setData={setData}
+        onClose={() => setIdeTargetsOpen(false)}
+      />
+
       <div className="rounded-2xl border border-ag-border bg-ag-card shadow-ag p-4 flex items-center gap-3 flex-wrap">
         <button
           className="h-10 px-4 rounded-xl bg-ag-primary text-white text-sm font-semibold hover:bg-blue-700 inline-flex items-center gap-2"
@@ -233,7 +357,7 @@ export function AccountsTab({ data, setData, reload }: AccountsTabProps) {
 
         <button
           className="h-10 px-4 rounded-xl border border-ag-border text-sm font-semibold text-ag-text hover:bg-ag-surface inline-flex items-center gap-2"
-          onClick={() => void refreshAll(false)}
+          onClick={() => void refreshAll()}
           disabled={refreshingAll || data.accounts.length === 0}
         >
           {refreshingAll ? <Loader2 size={16} className="animate-spin" /> : <RefreshCw size={16} />}
@@ -248,22 +372,93 @@ export function AccountsTab({ data, setData, reload }: AccountsTabProps) {
             onChange={(event) => void changeIdeTarget(event.target.value)}
           >
             <option value="">Choose IDE...</option>
-            {IDE_OPTIONS.map((option) => (
+            {ideTargets.map((target) => (
+              <option key={target.id} value={target.id}>
+                {target.label}
+              </option>
+            ))}
+          </select>
+          <button
+            className="h-8 w-8 inline-flex items-center justify-center rounded-lg border border-ag-border text-ag-muted hover:text-ag-text hover:bg-ag-surface"
+            onClick={() => setIdeTargetsOpen(true)}
+            title="Manage IDE targets"
+          >
+            <Settings2 size={14} />
+          </button>
+        </div>
+
+        <div className="h-10 px-3 rounded-xl border border-ag-border inline-flex items-center gap-2 bg-ag-card">
+          <label className="inline-flex items-center gap-2 text-xs text-ag-muted font-semibold">
+            <input
+              type="checkbox"
+              checked={data.rotation.enabled}
+              onChange={(event) => void updateRotation({ enabled: event.target.checked })}
+            />
+            Auto-rotate
+          </label>
+          <select
+            className="h-8 rounded-lg border border-ag-border bg-ag-card px-2 text-sm text-ag-text outline-none"
+            value={data.rotation.policy}
+            onChange={(event) => void updateRotation({ policy: event.target.value as RotationPolicy })}
+          >
+            {ROTATION_POLICY_OPTIONS.map((option) => (
               <option key={option.value} value={option.value}>
                 {option.label}
               </option>
             ))}
           </select>
+          <span className="text-xs text-ag-muted">below</span>
+          <input
+            type="number"
+            min={0}
+            max={100}
+            className="h-8 w-14 rounded-lg border border-ag-border bg-ag-card px-2 text-sm text-ag-text outline-none"
+            value={data.rotation.primaryThreshold}
+            onChange={(event) => changeThreshold('primaryThreshold', event.target.value)}
+            title="Rotate when 5h quota remaining drops below this percent"
+          />
+          <span className="text-xs text-ag-muted">% 5h /</span>
+          <input
+            type="number"
+            min={0}
+            max={100}
+            className="h-8 w-14 rounded-lg border border-ag-border bg-ag-card px-2 text-sm text-ag-text outline-none"
+            value={data.rotation.secondaryThreshold}
+            onChange={(event) => changeThreshold('secondaryThreshold', event.target.value)}
+            title="Rotate when weekly quota remaining drops below this percent"
+          />
+          <span className="text-xs text-ag-muted">% weekly</span>
         </div>
 
         <div className="ml-auto text-xs text-ag-muted flex items-center gap-3">
-          <span>
-            Auto refresh 5m: <span className="font-medium">{autoRefreshing ? 'running...' : 'enabled'}</span>
-          </span>
+          <label className="inline-flex items-center gap-2 font-semibold">
+            <input
+              type="checkbox"
+              checked={data.quotaRefresh.enabled}
+              onChange={(event) => void updateQuotaRefresh({ enabled: event.target.checked })}
+            />
+            Auto refresh every
+          </label>
+          <input
+            type="number"
+            min={1}
+            max={1440}
+            className="h-8 w-14 rounded-lg border border-ag-border bg-ag-card px-2 text-sm text-ag-text outline-none"
+            value={data.quotaRefresh.intervalMinutes}
+            onChange={(event) => changeRefreshInterval(event.target.value)}
+          />
+          <span>min</span>
           <span>
             Last auto refresh:{' '}
-            <span className="font-medium">{lastAutoRefresh ? new Date(lastAutoRefresh).toLocaleTimeString() : '-'}</span>
+            <span className="font-medium">
+              {scheduler?.lastRunAt ? new Date(scheduler.lastRunAt * 1000).toLocaleTimeString() : '-'}
+            </span>
           </span>
+          {scheduler?.nextRunAt && (
+            <span>
+              Next: <span className="font-medium">{formatTimeUntil(scheduler.nextRunAt)}</span>
+            </span>
+          )}
         </div>
       </div>
 
@@ -297,6 +492,7 @@ export function AccountsTab({ data, setData, reload }: AccountsTabProps) {
                 const quota = account.quota
                 const switching = busyKey === `switch:${account.id}`
                 const quotaLoading = busyKey === `quota:${account.id}`
+                const backoff = scheduler?.backoff.find((entry) => entry.accountId === account.id)
                 const removing = busyKey === `delete:${account.id}`
 
                 return (
@@ -324,6 +520,7 @@ export function AccountsTab({ data, setData, reload }: AccountsTabProps) {
                       <QuotaCell
                         value={quota?.primary.usedPercent}
                         resetAt={quota?.primary.resetAt}
+                        forecast={forecasts[account.id]?.primary}
                         title="Primary"
                       />
                     </td>
@@ -331,18 +528,21 @@ export function AccountsTab({ data, setData, reload }: AccountsTabProps) {
                       <QuotaCell
                         value={quota?.secondary.usedPercent}
                         resetAt={quota?.secondary.resetAt}
+                        forecast={forecasts[account.id]?.secondary}
                         title="Secondary"
                       />
                     </td>
                     <td className="px-4 py-3 align-top">
-                      {account.lastError ? (
-                        <span className="inline-flex items-center gap-1 text-xs text-red-600">
-                          <CircleAlert size={14} /> {account.lastError.slice(0, 96)}
-                        </span>
-                      ) : (
-                        <span className="inline-flex items-center gap-1 text-xs text-emerald-600">
-                          <CircleCheck size={14} /> healthy
-                        </span>
+                      <HealthBadge health={account.health} />
+                      {account.lastError && account.health.status !== 'healthy' && (
+                        <div className="text-xs text-ag-muted mt-1" title={account.lastError}>
+                          {account.lastError.slice(0, 96)}
+                        </div>
+                      )}
+                      {backoff && (
+                        <div className="text-xs text-ag-muted mt-1">
+                          auto refresh paused, retry {formatTimeUntil(backoff.retryAt)}
+                        </div>
                       )}
                     </td>
                     <td className="px-4 py-3 align-top">
@@ -354,7 +554,7 @@ export function AccountsTab({ data, setData, reload }: AccountsTabProps) {
                               : 'border-ag-border text-ag-muted hover:text-ag-text hover:bg-ag-surface'
                           }`}
                           onClick={() => void switchAccount(account.id)}
-                          disabled={switching || refreshingAll || autoRefreshing}
+                          disabled={switching || refreshingAll}
                           title="Switch Codex account and reload selected IDE"
                         >
                           {switching ? <Loader2 size={14} className="animate-spin" /> : <ArrowRightLeft size={14} />}
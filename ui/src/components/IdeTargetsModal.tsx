@@ -0,0 +1,215 @@
+import { useEffect, useState } from 'react'
+import { Loader2, Plus, Trash2, X } from 'lucide-react'
+import { api, errorMessage } from '../api'
+import type { AppData, IdeReloadStrategy, IdeTargetInfo } from '../types'
+
+type IdeTargetsModalProps = {
+  open: boolean
+  targets: IdeTargetInfo[]
+  setData: (next: AppData) => void
+  onClose: () => void
+}
+
+const RELOAD_OPTIONS: Array<{ value: IdeReloadStrategy; label: string }> = [
+  { value: 'reload_window', label: 'Reload window via CLI' },
+  { value: 'restart', label: 'Restart process' }
+]
+
+const EMPTY_FORM = {
+  id: '',
+  label: '',
+  aliases: '',
+  cliCandidates: '',
+  processNames: '',
+  reload: 'reload_window' as IdeReloadStrategy
+}
+
+function splitList(raw: string): string[] {
+  return raw
+    .split(',')
+    .map((item) => item.trim())
+    .filter((item) => item.length > 0)
+}
+
+export function IdeTargetsModal({ open, targets, setData, onClose }: IdeTargetsModalProps) {
+  const [form, setForm] = useState(EMPTY_FORM)
+  const [busy, setBusy] = useState(false)
+  const [error, setError] = useState<string | null>(null)
+
+  useEffect(() => {
+    if (!open) {
+      setForm(EMPTY_FORM)
+      setBusy(false)
+      setError(null)
+    }
+  }, [open])
+
+  if (!open) return null
+
+  const run = async (action: () => Promise<void>) => {
+    try {
+      setBusy(true)
+      setError(null)
+      await action()
+    } catch (err) {
+      setError(errorMessage(err))
+    } finally {
+      setBusy(false)
+    }
+  }
+
+  const save = () =>
+    run(async () => {
+      setData(
+        await api.saveCustomIde({
+          id: form.id,
+          label: form.label,
+          aliases: splitList(form.aliases),
+          cliCandidates: splitList(form.cliCandidates),
+          processNames: splitList(form.processNames),
+          reload: form.reload,
+          builtin: false
+        })
+      )
+      setForm(EMPTY_FORM)
+    })
+
+  const edit = (target: IdeTargetInfo) => {
+    setForm({
+      id: target.id,
+      label: target.label,
+      aliases: target.aliases.join(', '),
+      cliCandidates: target.cliCandidates.join(', '),
+      processNames: target.processNames.join(', '),
+      reload: target.reload
+    })
+  }
+
+  const remove = (id: string) =>
+    run(async () => {
+      setData(await api.deleteCustomIde(id))
+    })
+
+  const inputClass =
+    'w-full h-9 rounded-lg border border-ag-border bg-ag-card px-3 text-sm text-ag-text outline-none focus:border-blue-500'
+
+  return (
+    <div className="fixed inset-0 z-50 bg-slate-900/40 backdrop-blur-sm flex items-center justify-center p-4">
+      <div className="w-full max-w-2xl rounded-2xl border border-ag-border bg-ag-card shadow-ag">
+        <div className="flex items-center justify-between px-5 py-4 border-b border-ag-border">
+          <div>
+            <h2 className="m-0 text-lg font-semibold text-ag-text">IDE Targets</h2>
+            <p className="m-0 mt-1 text-sm text-ag-muted">
+              Editors reloaded after a switch. Custom entries are stored in state.json.
+            </p>
+          </div>
+          <button
+            className="h-9 w-9 inline-flex items-center justify-center rounded-lg border border-ag-border text-ag-muted hover:text-ag-text hover:bg-ag-surface"
+            onClick={onClose}
+          >
+            <X size={16} />
+          </button>
+        </div>
+
+        <div className="p-5 space-y-4">
+          <div className="max-h-56 overflow-auto rounded-xl border border-ag-border divide-y divide-ag-border">
+            {targets.map((target) => (
+              <div key={target.id} className="px-3 py-2 flex items-center justify-between gap-3">
+                <div className="min-w-0">
+                  <div className="text-sm font-semibold text-ag-text">
+                    {target.label} <span className="text-xs font-normal text-ag-muted">{target.id}</span>
+                  </div>
+                  <div className="text-xs text-ag-muted truncate">
+                    CLI: {target.cliCandidates.join(', ') || '-'} · Processes: {target.processNames.join(', ') || '-'}
+                  </div>
+                </div>
+                {target.builtin ? (
+                  <span className="text-xs text-ag-muted">Built-in</span>
+                ) : (
+                  <div className="flex items-center gap-2">
+                    <button
+                      className="h-8 px-3 rounded-lg border border-ag-border text-xs font-semibold text-ag-text hover:bg-ag-surface"
+                      onClick={() => edit(target)}
+                      disabled={busy}
+                    >
+                      Edit
+                    </button>
+                    <button
+                      className="h-8 w-8 inline-flex items-center justify-center rounded-lg border border-red-200 text-red-600 hover:bg-red-50"
+                      onClick={() => void remove(target.id)}
+                      disabled={busy}
+                      title="Delete IDE target"
+                    >
+                      <Trash2 size={14} />
+                    </button>
+                  </div>
+                )}
+              </div>
+            ))}
+          </div>
+
+          <div className="rounded-xl border border-ag-border p-3 space-y-3">
+            <div className="text-xs font-semibold uppercase tracking-wide text-ag-muted">Custom IDE</div>
+            <div className="grid grid-cols-2 gap-3">
+              <input
+                className={inputClass}
+                placeholder="Id (e.g. kiro)"
+                value={form.id}
+                onChange={(event) => setForm({ ...form, id: event.target.value })}
+              />
+              <input
+                className={inputClass}
+                placeholder="Label"
+                value={form.label}
+                onChange={(event) => setForm({ ...form, label: event.target.value })}
+              />
+              <input
+                className={inputClass}
+                placeholder="Aliases (comma separated)"
+                value={form.aliases}
+                onChange={(event) => setForm({ ...form, aliases: event.target.value })}
+              />
+              <input
+                className={inputClass}
+                placeholder="CLI commands (comma separated)"
+                value={form.cliCandidates}
+                onChange={(event) => setForm({ ...form, cliCandidates: event.target.value })}
+              />
+              <input
+                className={inputClass}
+                placeholder="Process names (comma separated)"
+                value={form.processNames}
+                onChange={(event) => setForm({ ...form, processNames: event.target.value })}
+              />
+              <select
+                className={inputClass}
+                value={form.reload}
+                onChange={(event) => setForm({ ...form, reload: event.target.value as IdeReloadStrategy })}
+              >
+                {RELOAD_OPTIONS.map((option) => (
+                  <option key={option.value} value={option.value}>
+                    {option.label}
+                  </option>
+                ))}
+              </select>
+            </div>
+            <button
+              className="h-9 px-4 rounded-lg bg-ag-primary text-white text-sm font-semibold hover:bg-blue-700 inline-flex items-center gap-2"
+              onClick={() => void save()}
+              disabled={busy || !form.id}
+            >
+              {busy ? <Loader2 size={14} className="animate-spin" /> : <Plus size={14} />}
+              Save IDE
+            </button>
+          </div>
+
+          {error && (
+            <div className="rounded-lg border border-red-200 bg-red-50 text-red-700 px-3 py-2 text-sm">
+              {error}
+            </div>
+          )}
+        </div>
+      </div>
+    </div>
+  )
+}
@@ -1,24 +1,39 @@
 ﻿import { useEffect, useMemo, useState } from 'react'
 import { Copy, Link2, Loader2, X } from 'lucide-react'
-import { api } from '../api'
-import type { OAuthFlowResponse, OAuthStartResponse } from '../types'
+import { api, errorMessage } from '../api'
+import { subscribe } from '../events'
+import type { AppData, OAuthCallbackSettings, OAuthFlowResponse, OAuthStartResponse } from '../types'
 
 type OAuthModalProps = {
   open: boolean
   onClose: () => void
+  callback: OAuthCallbackSettings
+  setData: (next: AppData) => void
   onCompleted: () => Promise<void>
 }
 
+function parsePorts(text: string): number[] {
+  return text
+    .split(/[\s,]+/)
+    .filter(Boolean)
+    .map((part) => Number(part))
+}
+
 function statusText(status: OAuthFlowResponse['status'] | 'idle'): string {
   if (status === 'waiting_callback') return 'Waiting for callback after login'
   if (status === 'exchanging') return 'Exchanging code for tokens'
   if (status === 'completed') return 'Completed'
   if (status === 'error') return 'Error'
+  if (status === 'expired') return 'Expired'
+  if (status === 'cancelled') return 'Cancelled'
   return 'Not started'
 }
 
-export function OAuthModal({ open, onClose, onCompleted }: OAuthModalProps) {
+export function OAuthModal({ open, onClose, callback, setData, onCompleted }: OAuthModalProps) {
   const [startData, setStartData] = useState<OAuthStartResponse | null>(null)
+  const [callbackHost, setCallbackHost] = useState(callback.host)
+  const [callbackPort, setCallbackPort] = useState(String(callback.port))
+  const [fallbackPorts, setFallbackPorts] = useState(callback.fallbackPorts.join(', '))
   const [flow, setFlow] = useState<OAuthFlowResponse | null>(null)
   const [callbackInput, setCallbackInput] = useState('')
   const [busy, setBusy] = useState(false)
@@ -35,26 +50,22 @@ export function OAuthModal({ open, onClose, onCompleted }: OAuthModalProps) {
       setError(null)
       return
     }
-  }, [open])
+    setCallbackHost(callback.host)
+    setCallbackPort(String(callback.port))
+    setFallbackPorts(callback.fallbackPorts.join(', '))
+  }, [open, callback])
 
   useEffect(() => {
     if (!open || !startData?.flowId) return
-    if (status !== 'waiting_callback' && status !== 'exchanging') return
-
-    const timer = setInterval(async () => {
-      try {
-        const next = await api.getOAuthStatus(startData.flowId)
-        setFlow(next)
-        if (next.status === 'completed') {
-          await onCompleted()
-        }
-      } catch (err) {
-        setError(String(err))
-      }
-    }, 1500)
 
-    return () => clearInterval(timer)
-  }, [open, startData?.flowId, status, onCompleted])
+    return subscribe('oauth-flow-updated', (next) => {
+      if (next.flowId !== startData.flowId) return
+      setFlow(next)
+      if (next.status === 'completed') {
+        void onCompleted()
+      }
+    })
+  }, [open, startData?.flowId, onCompleted])
 
   if (!open) return null
 
@@ -67,7 +78,7 @@ export function OAuthModal({ open, onClose, onCompleted }: OAuthModalProps) {
       const firstStatus = await api.getOAuthStatus(started.flowId)
       setFlow(firstStatus)
     } catch (err) {
-      setError(String(err))
+      setError(errorMessage(err))
     } finally {
       setBusy(false)
     }
@@ -85,12 +96,39 @@ export function OAuthModal({ open, onClose, onCompleted }: OAuthModalProps) {
         await onCompleted()
       }
     } catch (err) {
-      setError(String(err))
+      setError(errorMessage(err))
+    } finally {
+      setBusy(false)
+    }
+  }
+
+  const saveCallbackSettings = async () => {
+    try {
+      setBusy(true)
+      setError(null)
+      setData(
+        await api.setOAuthCallbackSettings({
+          host: callbackHost,
+          port: Number(callbackPort),
+          fallbackPorts: parsePorts(fallbackPorts)
+        })
+      )
+    } catch (err) {
+      setError(errorMessage(err))
     } finally {
       setBusy(false)
     }
   }
 
+  // Closing mid-login cancels the flow so its verifier is dropped and the
+  // callback listener can stop.
+  const close = () => {
+    if (startData && (status === 'waiting_callback' || status === 'exchanging')) {
+      void api.cancelOAuthFlow(startData.flowId).catch(() => undefined)
+    }
+    onClose()
+  }
+
   const copyLink = async () => {
     if (!startData?.authorizationUrl) return
     await navigator.clipboard.writeText(startData.authorizationUrl)
@@ -108,7 +146,7 @@ export function OAuthModal({ open, onClose, onCompleted }: OAuthModalProps) {
           </div>
           <button
             className="h-9 w-9 inline-flex items-center justify-center rounded-lg border border-ag-border text-ag-muted hover:text-ag-text hover:bg-ag-surface"
-            onClick={onClose}
+            onClick={close}
           >
             <X size={16} />
           </button>
@@ -122,6 +160,50 @@ export function OAuthModal({ open, onClose, onCompleted }: OAuthModalProps) {
             3. After login, paste full callback URL (or query) below.
           </div>
 
+          {!startData && (
+            <div className="rounded-xl border border-ag-border p-3">
+              <div className="text-xs font-semibold uppercase tracking-wide text-ag-muted mb-2">Callback listener</div>
+              <div className="flex flex-wrap items-end gap-2">
+                <label className="text-xs text-ag-muted">
+                  Host
+                  <input
+                    className="mt-1 block h-9 w-36 rounded-lg border border-ag-border px-2 text-sm text-ag-text outline-none focus:border-blue-500"
+                    value={callbackHost}
+                    onChange={(event) => setCallbackHost(event.target.value)}
+                  />
+                </label>
+                <label className="text-xs text-ag-muted">
+                  Port
+                  <input
+                    className="mt-1 block h-9 w-24 rounded-lg border border-ag-border px-2 text-sm text-ag-text outline-none focus:border-blue-500"
+                    value={callbackPort}
+                    onChange={(event) => setCallbackPort(event.target.value)}
+                  />
+                </label>
+                <label className="text-xs text-ag-muted">
+                  Fallback ports
+                  <input
+                    className="mt-1 block h-9 w-40 rounded-lg border border-ag-border px-2 text-sm text-ag-text outline-none focus:border-blue-500"
+                    placeholder="none"
+                    value={fallbackPorts}
+                    onChange={(event) => setFallbackPorts(event.target.value)}
+                  />
+                </label>
+                <button
+                  className="h-9 px-3 rounded-lg border border-ag-border text-sm text-ag-text hover:bg-ag-surface"
+                  onClick={() => void saveCallbackSettings()}
+                  disabled={busy}
+                >
+                  Save
+                </button>
+              </div>
+              <p className="m-0 mt-2 text-xs text-ag-muted">
+                Fallback ports are tried when the port is taken, e.g. by a Codex CLI login. The provider must accept the
+                resulting redirect URI.
+              </p>
+            </div>
+          )}
+
           {!startData && (
             <button
               className="h-10 px-4 rounded-xl bg-ag-primary text-white text-sm font-semibold hover:bg-blue-700 inline-flex items-center gap-2"
@@ -144,7 +226,8 @@ export function OAuthModal({ open, onClose, onCompleted }: OAuthModalProps) {
                   value={startData.authorizationUrl}
                   readOnly
                 />
-                <div className="px-3 py-2 border-t border-ag-border bg-ag-surface flex justify-end">
+                <div className="px-3 py-2 border-t border-ag-border bg-ag-surface flex items-center justify-between gap-2">
+                  <span className="text-xs text-ag-muted truncate">Redirect URI: {startData.redirectUri}</span>
                   <button
                     className="h-8 px-3 rounded-lg border border-ag-border text-sm text-ag-text hover:bg-ag-card inline-flex items-center gap-2"
                     onClick={copyLink}
@@ -1,7 +1,8 @@
 ﻿import { useState } from 'react'
 import { CheckCircle2, Loader2, Plus, RefreshCw, Trash2, WifiOff } from 'lucide-react'
-import { api } from '../api'
+import { api, errorMessage } from '../api'
 import type { AppData, ProxyEntry } from '../types'
+import { RelayPanel } from './RelayPanel'
 
 type ProxyTabProps = {
   data: AppData
@@ -22,7 +23,7 @@ export function ProxyTab({ data, setData }: ProxyTabProps) {
       setData(next)
       setProxyInput('')
     } catch (err) {
-      setError(String(err))
+      setError(errorMessage(err))
     }
   }
 
@@ -33,7 +34,7 @@ export function ProxyTab({ data, setData }: ProxyTabProps) {
       const next = await api.setActiveProxy(proxyId)
       setData(next)
     } catch (err) {
-      setError(String(err))
+      setError(errorMessage(err))
     } finally {
       setBusyId(null)
     }
@@ -47,7 +48,7 @@ export function ProxyTab({ data, setData }: ProxyTabProps) {
       const next = await api.getState()
       setData(next)
     } catch (err) {
-      setError(String(err))
+      setError(errorMessage(err))
     } finally {
       setBusyId(null)
     }
@@ -60,7 +61,7 @@ export function ProxyTab({ data, setData }: ProxyTabProps) {
       const next = await api.deleteProxy(proxyId)
       setData(next)
     } catch (err) {
-      setError(String(err))
+      setError(errorMessage(err))
     } finally {
       setBusyId(null)
     }
@@ -97,6 +98,8 @@ export function ProxyTab({ data, setData }: ProxyTabProps) {
         </div>
       </div>
 
+      <RelayPanel data={data} setData={setData} />
+
       {error && (
         <div className="rounded-xl border border-red-200 bg-red-50 px-4 py-3 text-sm text-red-700">{error}</div>
       )}
@@ -128,7 +131,9 @@ export function ProxyTab({ data, setData }: ProxyTabProps) {
                 return (
                   <tr key={proxy.id} className={`border-b border-ag-border/70 ${active ? 'bg-ag-surface/70' : ''}`}>
                     <td className="px-4 py-3 align-top">
-                      <div className="font-semibold text-ag-text">{proxy.raw}</div>
+                      <div className="font-semibold text-ag-text">
+                        {proxy.login}:{proxy.hasPassword ? '********' : ''}@{proxy.host}:{proxy.port}
+                      </div>
                       <div className="text-xs text-ag-muted mt-1">
                         login: {proxy.login} / host: {proxy.host}:{proxy.port}
                       </div>
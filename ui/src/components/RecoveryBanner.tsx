@@ -0,0 +1,97 @@
+import { useEffect, useState } from 'react'
+import { CircleAlert, Loader2 } from 'lucide-react'
+import { api, errorMessage } from '../api'
+import type { AppData, RecoveryNotice, StateBackup } from '../types'
+
+type RecoveryBannerProps = {
+  setData: (next: AppData) => void
+}
+
+function backupLabel(backup: StateBackup): string {
+  const when = new Date(backup.createdAt * 1000).toLocaleString()
+  if (backup.sealed) return `${when} (encrypted)`
+  if (backup.accounts == null) return `${when} (unreadable)`
+  return `${when} (${backup.accounts} accounts)`
+}
+
+// Shown after startup had to replace an unreadable state.json. Lets the user
+// pick an older backup if the one restored automatically is not the right one.
+export function RecoveryBanner({ setData }: RecoveryBannerProps) {
+  const [notice, setNotice] = useState<RecoveryNotice | null>(null)
+  const [backups, setBackups] = useState<StateBackup[]>([])
+  const [selected, setSelected] = useState('')
+  const [busy, setBusy] = useState(false)
+  const [error, setError] = useState<string | null>(null)
+
+  useEffect(() => {
+    void api.getStateRecovery().then((next) => {
+      setNotice(next)
+      if (!next) return
+      void api.listStateBackups().then((list) => {
+        setBackups(list)
+        setSelected(next.restoredFrom ?? list[0]?.name ?? '')
+      })
+    })
+  }, [])
+
+  if (!notice) return null
+
+  const dismiss = () => {
+    setNotice(null)
+    void api.dismissStateRecovery()
+  }
+
+  const restore = async () => {
+    if (!selected) return
+    try {
+      setBusy(true)
+      setError(null)
+      setData(await api.restoreStateBackup(selected))
+      setNotice(null)
+    } catch (err) {
+      setError(errorMessage(err))
+    } finally {
+      setBusy(false)
+    }
+  }
+
+  return (
+    <div className="fixed bottom-4 right-4 z-40 w-full max-w-md rounded-2xl border border-amber-300 bg-ag-card shadow-ag p-4 space-y-3">
+      <div className="text-sm font-semibold text-ag-text inline-flex items-center gap-2">
+        <CircleAlert size={16} className="text-amber-600" /> State file could not be read
+      </div>
+      <div className="text-xs text-ag-muted">
+        {notice.error}. The file was moved to {notice.quarantinedAs ?? 'a quarantine copy'}
+        {notice.restoredFrom ? ` and backup ${notice.restoredFrom} was restored.` : '; no usable backup was found.'}
+      </div>
+      {backups.length > 0 && (
+        <div className="flex items-center gap-2">
+          <select
+            className="h-9 flex-1 rounded-lg border border-ag-border bg-ag-surface px-2 text-sm text-ag-text"
+            value={selected}
+            onChange={(event) => setSelected(event.target.value)}
+          >
+            {backups.map((backup) => (
+              <option key={backup.name} value={backup.name}>
+                {backupLabel(backup)}
+              </option>
+            ))}
+          </select>
+          <button
+            className="h-9 px-3 rounded-lg border border-ag-border text-sm font-semibold text-ag-text hover:bg-ag-surface inline-flex items-center gap-2"
+            onClick={() => void restore()}
+            disabled={busy || !selected}
+          >
+            {busy && <Loader2 size={14} className="animate-spin" />} Restore
+          </button>
+        </div>
+      )}
+      {error && <div className="text-xs text-red-600">{error}</div>}
+      <div className="flex justify-end">
+        <button className="text-xs font-semibold text-ag-muted hover:text-ag-text" onClick={dismiss}>
+          Dismiss
+        </button>
+      </div>
+    </div>
+  )
+}
@@ -0,0 +1,113 @@
+import { useEffect, useState } from 'react'
+import { Loader2, Radio } from 'lucide-react'
+import { api, errorMessage } from '../api'
+import { subscribe } from '../events'
+import type { AppData, RelaySettings, RelayStatus } from '../types'
+
+type RelayPanelProps = {
+  data: AppData
+  setData: (next: AppData) => void
+}
+
+export function RelayPanel({ data, setData }: RelayPanelProps) {
+  const [status, setStatus] = useState<RelayStatus | null>(null)
+  const [port, setPort] = useState(String(data.relay.port))
+  const [upstreamUrl, setUpstreamUrl] = useState(data.relay.upstreamUrl)
+  const [busy, setBusy] = useState(false)
+  const [error, setError] = useState<string | null>(null)
+
+  useEffect(() => {
+    api
+      .getRelayStatus()
+      .then(setStatus)
+      .catch((err) => setError(errorMessage(err)))
+
+    return subscribe('relay-failover', () => {
+      void api.getRelayStatus().then(setStatus)
+    })
+  }, [])
+
+  const apply = async (patch: Partial<RelaySettings>) => {
+    try {
+      setBusy(true)
+      setError(null)
+      const settings: RelaySettings = {
+        ...data.relay,
+        port: Number(port),
+        upstreamUrl,
+        ...patch
+      }
+      setStatus(await api.setRelaySettings(settings))
+      setData(await api.getState())
+    } catch (err) {
+      setError(errorMessage(err))
+      setData(await api.getState())
+    } finally {
+      setBusy(false)
+    }
+  }
+
+  const lastAccount = data.accounts.find((account) => account.id === status?.lastAccountId)
+
+  return (
+    <div className="rounded-2xl border border-ag-border bg-ag-card shadow-ag p-4 space-y-3">
+      <div className="flex items-center justify-between gap-3">
+        <div>
+          <div className="text-sm font-semibold text-ag-text inline-flex items-center gap-2">
+            <Radio size={16} /> Local relay
+          </div>
+          <div className="text-xs text-ag-muted mt-1">
+            Point Codex at the relay URL; requests use the best account and fail over on 429 without switching auth.json.
+          </div>
+        </div>
+        <label className="inline-flex items-center gap-2 text-sm text-ag-text">
+          {busy && <Loader2 size={14} className="animate-spin" />}
+          <input
+            type="checkbox"
+            checked={data.relay.enabled}
+            disabled={busy}
+            onChange={(event) => void apply({ enabled: event.target.checked })}
+          />
+          Enabled
+        </label>
+      </div>
+
+      <div className="flex items-center gap-2">
+        <input
+          type="number"
+          min={1}
+          max={65535}
+          className="w-28 h-10 rounded-xl border border-ag-border px-3 text-sm outline-none focus:border-blue-500"
+          value={port}
+          onChange={(event) => setPort(event.target.value)}
+        />
+        <input
+          className="flex-1 h-10 rounded-xl border border-ag-border px-3 text-sm outline-none focus:border-blue-500"
+          placeholder="Upstream URL"
+          value={upstreamUrl}
+          onChange={(event) => setUpstreamUrl(event.target.value)}
+        />
+        <button
+          className="h-10 px-4 rounded-xl border border-ag-border text-sm font-semibold text-ag-text hover:bg-ag-surface"
+          onClick={() => void apply({})}
+          disabled={busy}
+        >
+          Apply
+        </button>
+      </div>
+
+      {status?.running && (
+        <div className="text-xs text-ag-muted">
+          Listening on <span className="font-semibold text-ag-text">{status.baseUrl}</span>
+          {lastAccount && <> · last served by {lastAccount.email ?? lastAccount.id}</>}
+        </div>
+      )}
+
+      {(error || status?.lastError) && (
+        <div className="rounded-lg border border-red-200 bg-red-50 text-red-700 px-3 py-2 text-sm">
+          {error ?? status?.lastError}
+        </div>
+      )}
+    </div>
+  )
+}
@@ -0,0 +1,230 @@
+import { useEffect, useState } from 'react'
+import { Loader2, Lock, LockOpen, ShieldCheck, X } from 'lucide-react'
+import { api, errorMessage } from '../api'
+import type { AppData, VaultStatus } from '../types'
+
+type VaultModalProps = {
+  open: boolean
+  status: VaultStatus
+  data: AppData
+  setData: (next: AppData) => void
+  setStatus: (next: VaultStatus) => void
+  onClose: () => void
+}
+
+export function VaultModal({ open, status, data, setData, setStatus, onClose }: VaultModalProps) {
+  const [passphrase, setPassphrase] = useState('')
+  const [confirmation, setConfirmation] = useState('')
+  const [busy, setBusy] = useState(false)
+  const [error, setError] = useState<string | null>(null)
+
+  useEffect(() => {
+    if (!open) {
+      setPassphrase('')
+      setConfirmation('')
+      setBusy(false)
+      setError(null)
+    }
+  }, [open])
+
+  if (!open) return null
+
+  const run = async (action: () => Promise<void>) => {
+    try {
+      setBusy(true)
+      setError(null)
+      await action()
+      setPassphrase('')
+      setConfirmation('')
+    } catch (err) {
+      setError(errorMessage(err))
+    } finally {
+      setBusy(false)
+    }
+  }
+
+  const enable = () =>
+    run(async () => {
+      if (passphrase !== confirmation) {
+        throw new Error('Passphrases do not match')
+      }
+      setStatus(await api.enableVault(passphrase))
+    })
+
+  const disable = () =>
+    run(async () => {
+      setStatus(await api.disableVault(passphrase))
+    })
+
+  const lockNow = () =>
+    run(async () => {
+      setStatus(await api.lockVault())
+      onClose()
+    })
+
+  const changeAutoLock = (raw: string) => {
+    const minutes = Number(raw)
+    if (raw.length === 0 || !Number.isInteger(minutes) || minutes < 0) return
+    void run(async () => {
+      setData(await api.setVaultAutoLock(minutes))
+    })
+  }
+
+  return (
+    <div className="fixed inset-0 z-50 bg-slate-900/40 backdrop-blur-sm flex items-center justify-center p-4">
+      <div className="w-full max-w-xl rounded-2xl border border-ag-border bg-ag-card shadow-ag">
+        <div className="flex items-center justify-between px-5 py-4 border-b border-ag-border">
+          <div>
+            <h2 className="m-0 text-lg font-semibold text-ag-text">State Vault</h2>
+            <p className="m-0 mt-1 text-sm text-ag-muted">
+              Encrypts tokens and proxy passwords in state.json with a master passphrase.
+            </p>
+          </div>
+          <button
+            className="h-9 w-9 inline-flex items-center justify-center rounded-lg border border-ag-border text-ag-muted hover:text-ag-text hover:bg-ag-surface"
+            onClick={onClose}
+          >
+            <X size={16} />
+          </button>
+        </div>
+
+        <div className="p-5 space-y-4">
+          {!status.enabled && (
+            <div className="space-y-3">
+              <input
+                type="password"
+                className="w-full h-10 rounded-xl border border-ag-border px-3 text-sm outline-none focus:border-blue-500"
+                placeholder="Master passphrase (min 8 characters)"
+                value={passphrase}
+                onChange={(event) => setPassphrase(event.target.value)}
+              />
+              <input
+                type="password"
+                className="w-full h-10 rounded-xl border border-ag-border px-3 text-sm outline-none focus:border-blue-500"
+                placeholder="Repeat passphrase"
+                value={confirmation}
+                onChange={(event) => setConfirmation(event.target.value)}
+              />
+              <div className="text-xs text-ag-muted">
+                The passphrase cannot be recovered. Losing it means losing the stored accounts.
+              </div>
+              <button
+                className="h-10 px-4 rounded-xl bg-ag-primary text-white text-sm font-semibold hover:bg-blue-700 inline-flex items-center gap-2"
+                onClick={() => void enable()}
+                disabled={busy || !passphrase}
+              >
+                {busy ? <Loader2 size={16} className="animate-spin" /> : <ShieldCheck size={16} />}
+                Encrypt state
+              </button>
+            </div>
+          )}
+
+          {status.enabled && (
+            <div className="space-y-4">
+              <div className="flex items-center gap-3">
+                <span className="text-sm text-ag-text">Auto-lock after</span>
+                <input
+                  type="number"
+                  min={0}
+                  className="h-9 w-20 rounded-lg border border-ag-border bg-ag-card px-2 text-sm text-ag-text outline-none"
+                  value={data.vaultAutoLockMinutes}
+                  onChange={(event) => changeAutoLock(event.target.value)}
+                />
+                <span className="text-sm text-ag-muted">minutes idle (0 = never)</span>
+              </div>
+
+              <button
+                className="h-10 px-4 rounded-xl border border-ag-border text-sm font-semibold text-ag-text hover:bg-ag-surface inline-flex items-center gap-2"
+                onClick={() => void lockNow()}
+                disabled={busy}
+              >
+                <Lock size={16} /> Lock now
+              </button>
+
+              <div className="rounded-xl border border-ag-border p-3 space-y-3">
+                <div className="text-xs font-semibold uppercase tracking-wide text-ag-muted">Disable encryption</div>
+                <input
+                  type="password"
+                  className="w-full h-10 rounded-xl border border-ag-border px-3 text-sm outline-none focus:border-blue-500"
+                  placeholder="Current passphrase"
+                  value={passphrase}
+                  onChange={(event) => setPassphrase(event.target.value)}
+                />
+                <button
+                  className="h-9 px-4 rounded-lg border border-red-200 text-sm font-semibold text-red-600 hover:bg-red-50"
+                  onClick={() => void disable()}
+                  disabled={busy || !passphrase}
+                >
+                  Store as plaintext
+                </button>
+              </div>
+            </div>
+          )}
+
+          {error && (
+            <div className="rounded-lg border border-red-200 bg-red-50 text-red-700 px-3 py-2 text-sm">
+              {error}
+            </div>
+          )}
+        </div>
+      </div>
+    </div>
+  )
+}
+
+type VaultUnlockScreenProps = {
+  onUnlocked: (data: AppData) => void
+}
+
+export function VaultUnlockScreen({ onUnlocked }: VaultUnlockScreenProps) {
+  const [passphrase, setPassphrase] = useState('')
+  const [busy, setBusy] = useState(false)
+  const [error, setError] = useState<string | null>(null)
+
+  const unlock = async () => {
+    try {
+      setBusy(true)
+      setError(null)
+      const data = await api.unlockVault(passphrase)
+      setPassphrase('')
+      onUnlocked(data)
+    } catch (err) {
+      setError(errorMessage(err))
+    } finally {
+      setBusy(false)
+    }
+  }
+
+  return (
+    <div className="h-full rounded-2xl border border-ag-border bg-ag-card shadow-ag flex items-center justify-center">
+      <div className="w-full max-w-sm space-y-3 p-6" data-no-drag>
+        <div className="text-lg font-semibold text-ag-text inline-flex items-center gap-2">
+          <Lock size={18} /> State is locked
+        </div>
+        <div className="text-sm text-ag-muted">Enter the master passphrase to decrypt your accounts.</div>
+        <input
+          type="password"
+          className="w-full h-10 rounded-xl border border-ag-border px-3 text-sm outline-none focus:border-blue-500"
+          placeholder="Master passphrase"
+          value={passphrase}
+          onChange={(event) => setPassphrase(event.target.value)}
+          onKeyDown={(event) => {
+            if (event.key === 'Enter' && passphrase) void unlock()
+          }}
+          autoFocus
+        />
+        <button
+          className="h-10 px-4 rounded-xl bg-ag-primary text-white text-sm font-semibold hover:bg-blue-700 inline-flex items-center gap-2"
+          onClick={() => void unlock()}
+          disabled={busy || !passphrase}
+        >
+          {busy ? <Loader2 size={16} className="animate-spin" /> : <LockOpen size={16} />}
+          Unlock
+        </button>
+        {error && (
+          <div className="rounded-lg border border-red-200 bg-red-50 text-red-700 px-3 py-2 text-sm">{error}</div>
+        )}
+      </div>
+    </div>
+  )
+}
@@ -0,0 +1,69 @@
+import { listen } from '@tauri-apps/api/event'
+import type {
+  Account,
+  AppData,
+  OAuthFlowResponse,
+  ProxyTestResult,
+  QuotaInfo,
+  RotationEvent
+} from './types'
+
+export type QuotaUpdated = {
+  accountId: string
+  quota: QuotaInfo | null
+  error: string | null
+}
+
+export type ActiveAccountChanged = {
+  previousAccountId: string | null
+  activeAccountId: string | null
+}
+
+export type QuotasRefreshed = {
+  refreshedAccountIds: string[]
+  failedAccountIds: string[]
+  pendingAccountIds: string[]
+  skippedAccountIds: string[]
+  nextRunAt: number | null
+  state: AppData
+}
+
+export type CodexAuthChanged = {
+  kind: 'logged_in' | 'logged_out' | 'tokens_refreshed' | 'active_changed'
+  activeAccountId: string | null
+}
+
+export type RelayFailover = {
+  skippedAccountIds: string[]
+  accountId: string
+}
+
+// Every event the backend emits, keyed by name. Payload shapes mirror the
+// Rust structs serialized with camelCase fields.
+export type AppEventMap = {
+  'oauth-flow-updated': OAuthFlowResponse
+  'account-updated': Account
+  'quota-updated': QuotaUpdated
+  'proxy-tested': ProxyTestResult
+  'active-account-changed': ActiveAccountChanged
+  'quotas-refreshed': QuotasRefreshed
+  'account-rotated': RotationEvent
+  'codex-auth-changed': CodexAuthChanged
+  'relay-failover': RelayFailover
+  'vault-locked': null
+  'state-changed': null
+}
+
+export type AppEventName = keyof AppEventMap
+
+// Subscribes to a backend event. The returned function unsubscribes and is
+// safe to call before the listener has finished registering.
+export function subscribe<K extends AppEventName>(
+  event: K,
+  handler: (payload: AppEventMap[K]) => void
+): () => void {
+  const unlisten = listen<AppEventMap[K]>(event, (message) => handler(message.payload))
+  return () => {
+    void unlisten.then((stop) => stop())
+  }
+}
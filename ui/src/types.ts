@@ -1,7 +1,13 @@
+export type TokenInfo = {
+  present: boolean
+  expiresAt: number | null
+  fingerprint: string | null
+}
+
 export type Tokens = {
-  idToken: string
-  accessToken: string
-  refreshToken: string
+  idToken: TokenInfo
+  accessToken: TokenInfo
+  refreshToken: TokenInfo
 }
 
 export type QuotaWindow = {
@@ -18,6 +24,23 @@ export type QuotaInfo = {
   fetchedAt: number
 }
 
+export type AccountStatus =
+  | 'healthy'
+  | 'stale'
+  | 'token_expired'
+  | 'needs_reauth'
+  | 'rate_limited'
+  | 'deactivated'
+  | 'network_error'
+
+export type AccountHealth = {
+  status: AccountStatus
+  since: number | null
+  checkedAt: number | null
+  httpStatus: number | null
+  retryAt: number | null
+}
+
 export type Account = {
   id: string
   email: string | null
@@ -27,21 +50,136 @@ export type Account = {
   createdAt: number
   lastLoginAt: number
   lastError: string | null
+  lastRefreshAt: number | null
+  health: AccountHealth
 }
 
 export type ProxyEntry = {
   id: string
   login: string
-  password: string
+  hasPassword: boolean
   host: string
   port: number
-  raw: string
   lastLatencyMs: number | null
   lastStatus: string | null
   lastCheckedAt: number | null
 }
 
-export type IdeTarget = 'vscode' | 'cursor' | 'windsurf' | 'trae' | 'vscodium' | 'zed'
+export type IdeTarget = string
+
+export type IdeReloadStrategy = 'reload_window' | 'restart'
+
+export type IdeTargetInfo = {
+  id: IdeTarget
+  label: string
+  aliases: string[]
+  cliCandidates: string[]
+  processNames: string[]
+  reload: IdeReloadStrategy
+  builtin: boolean
+}
+
+export type RotationPolicy = 'most_remaining' | 'soonest_reset' | 'round_robin'
+
+export type RotationSettings = {
+  enabled: boolean
+  policy: RotationPolicy
+  primaryThreshold: number
+  secondaryThreshold: number
+}
+
+export type QuotaSample = {
+  accountId: string
+  email: string | null
+  planType: string | null
+  primary: QuotaWindow
+  secondary: QuotaWindow
+  fetchedAt: number
+}
+
+export type QuotaSeries = {
+  accountId: string
+  email: string | null
+  samples: QuotaSample[]
+}
+
+export type UsageSummary = {
+  accountId: string
+  email: string | null
+  primaryUsed: number
+  secondaryUsed: number
+  samples: number
+}
+
+export type ForecastMethod = 'regression' | 'window_average'
+
+export type WindowForecast = {
+  usedPercent: number
+  resetAt: number | null
+  burnRatePerHour: number | null
+  exhaustsAt: number | null
+  willExhaustBeforeReset: boolean
+  projectedAtReset: number | null
+  method: ForecastMethod | null
+  samples: number
+}
+
+export type AccountForecast = {
+  accountId: string
+  email: string | null
+  primary: WindowForecast | null
+  secondary: WindowForecast | null
+  generatedAt: number
+}
+
+export type QuotaRefreshSettings = {
+  enabled: boolean
+  intervalMinutes: number
+  jitterSeconds: number
+}
+
+export type AccountBackoff = {
+  accountId: string
+  failures: number
+  retryAt: number
+}
+
+export type SchedulerStatus = {
+  enabled: boolean
+  intervalMinutes: number
+  lastRunAt: number | null
+  nextRunAt: number | null
+  backoff: AccountBackoff[]
+}
+
+export type RelaySettings = {
+  enabled: boolean
+  port: number
+  upstreamUrl: string
+}
+
+export type OAuthCallbackSettings = {
+  host: string
+  port: number
+  fallbackPorts: number[]
+}
+
+export type RelayStatus = {
+  running: boolean
+  baseUrl: string | null
+  lastAccountId: string | null
+  lastError: string | null
+}
+
+export type RotationEvent = {
+  at: number
+  fromAccountId: string | null
+  toAccountId: string
+  policy: RotationPolicy
+  reason: string
+  ide: IdeTarget | null
+  reloaded: boolean
+}
 
 export type AppData = {
   accounts: Account[]
@@ -50,6 +188,18 @@ export type AppData = {
   activeProxyId: string | null
   limitsBaseUrl: string
   preferredIde: IdeTarget | null
+  rotation: RotationSettings
+  rotationLog: RotationEvent[]
+  vaultAutoLockMinutes: number
+  customIdes: IdeTargetInfo[]
+  relay: RelaySettings
+  quotaRefresh: QuotaRefreshSettings
+  oauthCallback: OAuthCallbackSettings
+}
+
+export type VaultStatus = {
+  enabled: boolean
+  locked: boolean
 }
 
 export type OAuthStartResponse = {
@@ -63,7 +213,7 @@ export type OAuthFlowResponse = {
   authorizationUrl: string
   callbackUrl: string | null
   createdAt: number
-  status: 'waiting_callback' | 'exchanging' | 'completed' | 'error'
+  status: 'waiting_callback' | 'exchanging' | 'completed' | 'error' | 'expired' | 'cancelled'
   error: string | null
   account: Account | null
 }
@@ -76,9 +226,61 @@ export type ProxyTestResult = {
   error: string | null
 }
 
+export type RefreshAllResponse = {
+  state: AppData
+  refreshedAccountIds: string[]
+  failedAccountIds: string[]
+  pendingAccountIds: string[]
+}
+
 export type SwitchAccountResponse = {
   state: AppData
   ide: IdeTarget | null
   reloaded: boolean
   warning: string | null
-}
\ No newline at end of file
+}
+
+export type StateBackup = {
+  name: string
+  createdAt: number
+  size: number
+  sealed: boolean
+  accounts: number | null
+}
+
+export type RecoveryNotice = {
+  at: number
+  error: string
+  quarantinedAs: string | null
+  restoredFrom: string | null
+}
+
+export type AppErrorCode =
+  | 'storage'
+  | 'parse'
+  | 'unsupported_schema'
+  | 'vault_locked'
+  | 'vault'
+  | 'network'
+  | 'proxy'
+  | 'invalid_input'
+  | 'oauth_flow_not_found'
+  | 'oauth_callback'
+  | 'oauth_callback_server'
+  | 'relay'
+  | 'oauth_state_mismatch'
+  | 'oauth_exchange'
+  | 'token_refresh'
+  | 'quota_http'
+  | 'account_not_found'
+  | 'proxy_not_found'
+  | 'ide_reload'
+  | 'internal'
+
+// Rejection value of every command. `details` holds variant fields such as
+// `status` for HTTP failures or `accountId` for a missing account.
+export type AppError = {
+  code: AppErrorCode
+  message: string
+  details: Record<string, unknown> | null
+}